@@ -25,6 +25,11 @@ impl Color {
         Self { r, g, b }
     }
 
+    /// Perceived brightness of the color, using the Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
     pub fn map(&self, f: fn(f64) -> f64) -> Self {
         Self {
             r: f(self.r),
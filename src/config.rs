@@ -40,9 +40,15 @@ pub struct Config {
     pub camera: CameraConfig,
     pub renderer: RendererConfig,
     pub image: ImageConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub textures: Vec<TextureConfig>,
     pub materials: Vec<MaterialConfig>,
     pub objects: Vec<ObjectConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightConfig>,
     pub sky: SkyConfig,
+    #[serde(default, skip_serializing_if = "BvhConfig::is_default")]
+    pub bvh: BvhConfig,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -69,6 +75,60 @@ pub struct CameraConfig {
 pub struct RendererConfig {
     pub samples_per_pixel: u32,
     pub max_bounces: u32,
+    /// Number of worker threads. Defaults to one per available core.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+}
+
+/// How the bounding volume hierarchies used for ray intersection are built
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BvhConfig {
+    #[serde(default)]
+    pub method: BvhMethodConfig,
+    /// Nodes with more primitives than this are always split
+    #[serde(default = "default_max_leaf_size")]
+    pub max_leaf_size: usize,
+    /// Cost of visiting a node relative to `intersection_cost`, used by the SAH builder
+    #[serde(default = "default_traversal_cost")]
+    pub traversal_cost: f64,
+    #[serde(default = "default_intersection_cost")]
+    pub intersection_cost: f64,
+    /// Log node count, depth, SAH cost and leaf sizes of every BVH that gets built
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub statistics: bool,
+    /// Directory to store the BVHs of meshes in, relative to the scene file. Meshes are parsed
+    /// and their BVHs built on every run if this is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_directory: Option<PathBuf>,
+}
+
+impl BvhConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for BvhConfig {
+    fn default() -> Self {
+        Self {
+            method: BvhMethodConfig::default(),
+            max_leaf_size: default_max_leaf_size(),
+            traversal_cost: default_traversal_cost(),
+            intersection_cost: default_intersection_cost(),
+            statistics: false,
+            cache_directory: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BvhMethodConfig {
+    /// Surface area heuristic
+    #[default]
+    Sah,
+    /// Split in the middle of the longest axis, the original builder
+    Median,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -102,6 +162,35 @@ pub enum SkyConfig {
         )]
         color: Vec3,
     },
+
+    /// An equirectangular image, e.g. a Radiance `.hdr` or `.exr` file. `rotation` turns the
+    /// map around the vertical axis and is given in degrees.
+    #[serde(rename = "environment-map")]
+    EnvironmentMap {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+
+    /// A procedural daylight sky. The sun's elevation is given in degrees above the horizon and
+    /// its azimuth in degrees clockwise from the -z axis.
+    #[serde(rename = "physical")]
+    Physical {
+        sun_elevation: f64,
+        sun_azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(
+            default = "default_ground_albedo",
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        ground_albedo: Vec3,
+        #[serde(default = "default_sun_disk")]
+        sun_disk: bool,
+    },
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
@@ -110,45 +199,376 @@ pub enum MaterialConfig {
     #[serde(rename = "lambertian")]
     Lambertian {
         name: String,
-        #[serde(
-            serialize_with = "serialize_vec3",
-            deserialize_with = "deserialize_vec3"
-        )]
-        albedo: Vec3,
+        albedo: ColorSource,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
     },
 
     #[serde(rename = "metal")]
     Metal {
         name: String,
-        #[serde(
-            serialize_with = "serialize_vec3",
-            deserialize_with = "deserialize_vec3"
-        )]
-        albedo: Vec3,
+        albedo: ColorSource,
         fuzz: f64,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
+    },
+    /// A physically based metallic-roughness material
+    #[serde(rename = "principled")]
+    Principled {
+        name: String,
+        base_color: ColorSource,
+        /// 0 for dielectrics such as plastic or wood, 1 for metals
+        #[serde(default)]
+        metallic: f64,
+        /// From 0 for a mirror finish to 1 for a matte one
+        #[serde(default = "default_roughness")]
+        roughness: f64,
+        /// Scales the reflectance of dielectrics, with 0.5 reflecting 4% of the light head-on
+        #[serde(default = "default_specular")]
+        specular: f64,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
+    },
+    /// A metal described by its complex index of refraction
+    #[serde(rename = "conductor")]
+    Conductor {
+        name: String,
+        metal: ConductorMetalConfig,
+        /// From 0 for a mirror finish to 1 for a matte one
+        #[serde(default)]
+        roughness: f64,
+        /// From 0 for round highlights to 1 for the long streaks of brushed metal
+        #[serde(default)]
+        anisotropy: f64,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
     },
     #[serde(rename = "normal_vis")]
-    NormalVisualization { name: String },
+    NormalVisualization {
+        name: String,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
+    },
 
     #[serde(rename = "dielectric")]
     Dielectric {
         name: String,
         #[serde(default = "default_ior")]
         ior: f64,
+        /// 0 for clear glass, higher for frosted glass
+        #[serde(default)]
+        roughness: f64,
+        /// The color of white light after travelling `absorption_distance` through the
+        /// material
+        #[serde(
+            default = "default_absorption_color",
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        absorption_color: Vec3,
+        #[serde(default = "default_absorption_distance")]
+        absorption_distance: f64,
+        /// A sheet such as a window pane, rather than the surface of a solid
+        #[serde(default)]
+        thin_walled: bool,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
     },
 
     #[serde(rename = "diffuse_light")]
     DiffuseLight {
         name: String,
+        albedo: ColorSource,
+        strength: f64,
+    },
+
+    /// A blend of two other materials, referred to by name
+    #[serde(rename = "mix")]
+    Mix {
+        name: String,
+        first: String,
+        second: String,
+        /// 0 for all of `first`, 1 for all of `second`
+        factor: FactorSource,
+    },
+
+    /// A clear coat over another material, referred to by name
+    #[serde(rename = "coated")]
+    Coated {
+        name: String,
+        base: String,
+        #[serde(default = "default_ior")]
+        ior: f64,
+        /// 0 for a glossy coat, higher for a satin one
+        #[serde(default)]
+        roughness: f64,
+        /// The color of white light that went through the coat to the base and back
         #[serde(
+            default = "default_coat_tint",
             serialize_with = "serialize_vec3",
             deserialize_with = "deserialize_vec3"
         )]
-        albedo: Vec3,
-        strength: f64,
+        tint: Vec3,
+        #[serde(flatten)]
+        maps: SurfaceMapsConfig,
     },
 }
 
+/// Normal and bump maps that add detail to a material's surface
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Default)]
+pub struct SurfaceMapsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal_map: Option<NormalMapConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bump_map: Option<BumpMapConfig>,
+}
+
+/// A tangent-space normal map, `{ texture = "name" }`. Its image should be loaded with
+/// `color_space = "linear"`.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct NormalMapConfig {
+    pub texture: String,
+    /// Scales the tilt of the normals
+    #[serde(default = "default_normal_strength")]
+    pub strength: f64,
+}
+
+/// A height map, `{ texture = "name", height = 0.01 }`, whose brightness lifts the surface by
+/// up to `height`
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct BumpMapConfig {
+    pub texture: String,
+    pub height: f64,
+}
+
+/// The metal of a conductor, either a preset name such as `"gold"` or the complex index of
+/// refraction per channel, `{ eta = "r, g, b", k = "r, g, b" }`
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum ConductorMetalConfig {
+    Preset(ConductorPresetConfig),
+    Custom {
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        eta: Vec3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        k: Vec3,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConductorPresetConfig {
+    Gold,
+    Silver,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+    Chromium,
+    Iron,
+}
+
+impl ConductorPresetConfig {
+    pub const ALL: [ConductorPresetConfig; 6] = [
+        ConductorPresetConfig::Gold,
+        ConductorPresetConfig::Silver,
+        ConductorPresetConfig::Copper,
+        ConductorPresetConfig::Aluminium,
+        ConductorPresetConfig::Chromium,
+        ConductorPresetConfig::Iron,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConductorPresetConfig::Gold => "Gold",
+            ConductorPresetConfig::Silver => "Silver",
+            ConductorPresetConfig::Copper => "Copper",
+            ConductorPresetConfig::Aluminium => "Aluminium",
+            ConductorPresetConfig::Chromium => "Chromium",
+            ConductorPresetConfig::Iron => "Iron",
+        }
+    }
+}
+
+/// A material color, given either as a constant `"r, g, b"` string or as a reference to one of
+/// the scene's textures, `{ texture = "name" }`.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum ColorSource {
+    Constant(
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        Vec3,
+    ),
+    Texture {
+        texture: String,
+    },
+}
+
+impl From<Vec3> for ColorSource {
+    fn from(color: Vec3) -> Self {
+        ColorSource::Constant(color)
+    }
+}
+
+/// A number between 0 and 1, given either as a constant or as a reference to one of the
+/// scene's textures, `{ texture = "name" }`, whose brightness is used.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum FactorSource {
+    Constant(f64),
+    Texture { texture: String },
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum TextureConfig {
+    /// A PNG, JPEG or other image file, mapped by the surface's texture coordinates
+    #[serde(rename = "image")]
+    Image {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapConfig,
+        /// `linear` for images holding data rather than colors, such as normal maps
+        #[serde(default)]
+        color_space: ColorSpaceConfig,
+    },
+
+    /// A 3D checkerboard of cubes with edge length `scale`
+    #[serde(rename = "checker")]
+    Checker {
+        name: String,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        even: Vec3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        odd: Vec3,
+        #[serde(default = "default_checker_scale")]
+        scale: f64,
+    },
+
+    /// Layered Perlin noise. Higher `scale` values give finer detail.
+    #[serde(rename = "noise")]
+    Noise {
+        name: String,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        color: Vec3,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_noise_octaves")]
+        octaves: u32,
+    },
+}
+
+impl TextureConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            TextureConfig::Image { name, .. } => name,
+            TextureConfig::Checker { name, .. } => name,
+            TextureConfig::Noise { name, .. } => name,
+        }
+    }
+}
+
+/// How an image texture is continued beyond its edges.
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapConfig {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+/// How the values of an image texture are encoded.
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpaceConfig {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+fn default_max_leaf_size() -> usize {
+    8
+}
+
+fn default_traversal_cost() -> f64 {
+    1.0
+}
+
+fn default_intersection_cost() -> f64 {
+    2.0
+}
+
+fn default_checker_scale() -> f64 {
+    1.0
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+fn default_noise_octaves() -> u32 {
+    7
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_ground_albedo() -> Vec3 {
+    Vec3::new(0.3, 0.3, 0.3)
+}
+
+fn default_sun_disk() -> bool {
+    true
+}
+
+fn default_roughness() -> f64 {
+    0.5
+}
+
+fn default_specular() -> f64 {
+    0.5
+}
+
+fn default_absorption_color() -> Vec3 {
+    Vec3::ONES
+}
+
+fn default_absorption_distance() -> f64 {
+    1.0
+}
+
+fn default_normal_strength() -> f64 {
+    1.0
+}
+
+fn default_coat_tint() -> Vec3 {
+    Vec3::ONES
+}
+
 fn default_ior() -> f64 {
     1.5 // Default index of refraction for glass
 }
@@ -165,6 +585,8 @@ pub enum ObjectConfig {
         position: Vec3,
         radius: f64,
         material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
     },
     #[serde(rename = "triangle")]
     Triangle {
@@ -184,9 +606,458 @@ pub enum ObjectConfig {
         )]
         p3: Point3,
         material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// An infinite plane. Its texture repeats every unit.
+    #[serde(rename = "plane")]
+    Plane {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        point: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        normal: Vec3,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// A parallelogram spanned by two edges from one of its corners
+    #[serde(rename = "quad")]
+    Quad {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        corner: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        edge1: Vec3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        edge2: Vec3,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    #[serde(rename = "disk")]
+    Disk {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        normal: Vec3,
+        radius: f64,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// An axis aligned box between two opposite corners. Rotating it gives an oriented box.
+    #[serde(rename = "box")]
+    Cuboid {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        min: Point3,
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        max: Point3,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// A cylinder closed at both ends
+    #[serde(rename = "cylinder")]
+    Cylinder {
+        /// Center of the bottom cap
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        base: Point3,
+        /// Center of the top cap
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        top: Point3,
+        radius: f64,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// A cone closed at its base
+    #[serde(rename = "cone")]
+    Cone {
+        /// Center of the base
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        base: Point3,
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        apex: Point3,
+        /// Radius of the base
+        radius: f64,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
     },
+    #[serde(rename = "torus")]
+    Torus {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        /// The axis the ring goes around
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        axis: Vec3,
+        /// Distance from the center to the middle of the tube
+        major_radius: f64,
+        /// Radius of the tube
+        minor_radius: f64,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// Combines two closed objects: spheres, boxes, cylinders, cones, tori, meshes, `sdf` or
+    /// other `csg` objects. The operands keep their own materials and transforms.
+    #[serde(rename = "csg")]
+    Csg {
+        operation: CsgOperationConfig,
+        left: Box<ObjectConfig>,
+        right: Box<ObjectConfig>,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// A signed distance function, described as a tree of shapes and the ways to combine them
+    #[serde(rename = "sdf")]
+    Sdf {
+        shape: SdfConfig,
+        material: String,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// An OBJ, PLY or STL file. Objects placing the same file with the same material share
+    /// the triangle data.
     #[serde(rename = "mesh")]
-    Mesh { path: PathBuf, material: String },
+    Mesh {
+        path: PathBuf,
+        material: String,
+        /// Replaces every material of the mesh, including those from the file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material_override: Option<String>,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+    /// The default scene of a `.gltf` or `.glb` file
+    #[serde(rename = "gltf")]
+    Gltf {
+        path: PathBuf,
+        /// Used for primitives without a material
+        material: String,
+        /// Replace the scene camera with the first camera in the file
+        #[serde(default)]
+        use_camera: bool,
+        /// Replaces every material of the scene, including those from the file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material_override: Option<String>,
+        #[serde(flatten)]
+        transform: TransformConfig,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsgOperationConfig {
+    Union,
+    Intersection,
+    /// The left operand with the right one cut out of it
+    Difference,
+}
+
+impl CsgOperationConfig {
+    pub fn name(self) -> &'static str {
+        match self {
+            CsgOperationConfig::Union => "Union",
+            CsgOperationConfig::Intersection => "Intersection",
+            CsgOperationConfig::Difference => "Difference",
+        }
+    }
+}
+
+/// A node of an `sdf` object. Shapes are centered on the origin unless given a center.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum SdfConfig {
+    #[serde(rename = "sphere")]
+    Sphere {
+        #[serde(
+            default,
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        radius: f64,
+    },
+    #[serde(rename = "box")]
+    Cuboid {
+        #[serde(
+            default,
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        size: Vec3,
+    },
+    /// A box whose edges are rounded off, within the same size as the sharp box
+    #[serde(rename = "rounded_box")]
+    RoundedBox {
+        #[serde(
+            default,
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        size: Vec3,
+        radius: f64,
+    },
+    /// A ring around the y axis
+    #[serde(rename = "torus")]
+    Torus {
+        #[serde(
+            default,
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        center: Point3,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// A cylinder with rounded ends, around the segment from `a` to `b`
+    #[serde(rename = "capsule")]
+    Capsule {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        a: Point3,
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        b: Point3,
+        radius: f64,
+    },
+    /// Joins the shapes, blending them together where they are closer than `smoothness`
+    #[serde(rename = "smooth_union")]
+    SmoothUnion {
+        #[serde(default)]
+        smoothness: f64,
+        left: Box<SdfConfig>,
+        right: Box<SdfConfig>,
+    },
+    /// Cuts `right` out of `left`
+    #[serde(rename = "smooth_subtract")]
+    SmoothSubtract {
+        #[serde(default)]
+        smoothness: f64,
+        left: Box<SdfConfig>,
+        right: Box<SdfConfig>,
+    },
+    #[serde(rename = "smooth_intersect")]
+    SmoothIntersect {
+        #[serde(default)]
+        smoothness: f64,
+        left: Box<SdfConfig>,
+        right: Box<SdfConfig>,
+    },
+    /// A grid of `count` copies along each axis, `period` apart and centered on the origin
+    #[serde(rename = "repeat")]
+    Repeat {
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        period: Vec3,
+        count: [u32; 3],
+        shape: Box<SdfConfig>,
+    },
+    /// Ripples the surface with a sine pattern
+    #[serde(rename = "displace")]
+    Displace {
+        amplitude: f64,
+        frequency: f64,
+        shape: Box<SdfConfig>,
+    },
+}
+
+impl SdfConfig {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SdfConfig::Sphere { .. } => "Sphere",
+            SdfConfig::Cuboid { .. } => "Box",
+            SdfConfig::RoundedBox { .. } => "Rounded Box",
+            SdfConfig::Torus { .. } => "Torus",
+            SdfConfig::Capsule { .. } => "Capsule",
+            SdfConfig::SmoothUnion { .. } => "Smooth Union",
+            SdfConfig::SmoothSubtract { .. } => "Smooth Subtract",
+            SdfConfig::SmoothIntersect { .. } => "Smooth Intersect",
+            SdfConfig::Repeat { .. } => "Repeat",
+            SdfConfig::Displace { .. } => "Displace",
+        }
+    }
+}
+
+/// Places an object in the scene. The object is scaled first, then rotated and finally
+/// translated.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Default)]
+pub struct TransformConfig {
+    #[serde(
+        default,
+        skip_serializing_if = "is_zero",
+        serialize_with = "serialize_vec3",
+        deserialize_with = "deserialize_vec3"
+    )]
+    pub translate: Vec3,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate: Option<RotationConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<ScaleConfig>,
+}
+
+/// A rotation, either as `"x, y, z"` angles or as `{ axis = "x, y, z", angle = a }`.
+/// Angles are given in degrees.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum RotationConfig {
+    /// Rotations around the x, y and z axes, applied in that order
+    Euler(
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        Vec3,
+    ),
+    /// A rotation counterclockwise around `axis`, looking against the axis
+    AxisAngle {
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        axis: Vec3,
+        angle: f64,
+    },
+}
+
+/// A scale factor, either the same for all axes or given per axis as `"x, y, z"`.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum ScaleConfig {
+    Uniform(f64),
+    PerAxis(
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        Vec3,
+    ),
+}
+
+fn is_zero(v: &Vec3) -> bool {
+    *v == Vec3::default()
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum LightConfig {
+    /// Shines equally in all directions from a single point
+    #[serde(rename = "point")]
+    Point {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        position: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        color: Vec3,
+        intensity: f64,
+    },
+
+    /// A point light restricted to a cone. The light falls off smoothly between the inner and
+    /// outer cone angles, which are given in degrees.
+    #[serde(rename = "spot")]
+    Spot {
+        #[serde(
+            serialize_with = "serialize_point3",
+            deserialize_with = "deserialize_point3"
+        )]
+        position: Point3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        direction: Vec3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        color: Vec3,
+        intensity: f64,
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+
+    /// Parallel light from infinitely far away, like the sun.
+    /// `direction` is the direction the light travels in.
+    #[serde(rename = "directional")]
+    Directional {
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        direction: Vec3,
+        #[serde(
+            serialize_with = "serialize_vec3",
+            deserialize_with = "deserialize_vec3"
+        )]
+        color: Vec3,
+        intensity: f64,
+    },
 }
 
 impl Config {
@@ -208,9 +1079,13 @@ impl MaterialConfig {
         match self {
             MaterialConfig::Lambertian { name, .. } => name,
             MaterialConfig::Metal { name, .. } => name,
-            MaterialConfig::NormalVisualization { name } => name,
+            MaterialConfig::Principled { name, .. } => name,
+            MaterialConfig::Conductor { name, .. } => name,
+            MaterialConfig::NormalVisualization { name, .. } => name,
             MaterialConfig::Dielectric { name, .. } => name,
             MaterialConfig::DiffuseLight { name, .. } => name,
+            MaterialConfig::Mix { name, .. } => name,
+            MaterialConfig::Coated { name, .. } => name,
         }
     }
 
@@ -218,19 +1093,102 @@ impl MaterialConfig {
         match self {
             MaterialConfig::Lambertian { .. } => "Lambertian",
             MaterialConfig::Metal { .. } => "Metal",
+            MaterialConfig::Principled { .. } => "Principled",
+            MaterialConfig::Conductor { .. } => "Conductor",
             MaterialConfig::NormalVisualization { .. } => "Normal Visualization",
             MaterialConfig::Dielectric { .. } => "Dielectric",
             MaterialConfig::DiffuseLight { .. } => "Diffuse Light",
+            MaterialConfig::Mix { .. } => "Mix",
+            MaterialConfig::Coated { .. } => "Coated",
+        }
+    }
+
+    /// The normal and bump maps of materials with a surface of their own
+    pub fn maps(&self) -> Option<&SurfaceMapsConfig> {
+        match self {
+            MaterialConfig::Lambertian { maps, .. }
+            | MaterialConfig::Metal { maps, .. }
+            | MaterialConfig::Principled { maps, .. }
+            | MaterialConfig::Conductor { maps, .. }
+            | MaterialConfig::NormalVisualization { maps, .. }
+            | MaterialConfig::Dielectric { maps, .. }
+            | MaterialConfig::Coated { maps, .. } => Some(maps),
+            MaterialConfig::DiffuseLight { .. } | MaterialConfig::Mix { .. } => None,
+        }
+    }
+
+    /// The names of the other materials this one is built from
+    pub fn referenced_materials(&self) -> Vec<&str> {
+        match self {
+            MaterialConfig::Mix { first, second, .. } => vec![first, second],
+            MaterialConfig::Coated { base, .. } => vec![base],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl LightConfig {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LightConfig::Point { .. } => "Point Light",
+            LightConfig::Spot { .. } => "Spot Light",
+            LightConfig::Directional { .. } => "Directional Light",
         }
     }
 }
 
 impl ObjectConfig {
+    pub fn transform(&self) -> &TransformConfig {
+        match self {
+            ObjectConfig::Sphere { transform, .. } => transform,
+            ObjectConfig::Triangle { transform, .. } => transform,
+            ObjectConfig::Plane { transform, .. } => transform,
+            ObjectConfig::Quad { transform, .. } => transform,
+            ObjectConfig::Disk { transform, .. } => transform,
+            ObjectConfig::Cuboid { transform, .. } => transform,
+            ObjectConfig::Cylinder { transform, .. } => transform,
+            ObjectConfig::Cone { transform, .. } => transform,
+            ObjectConfig::Torus { transform, .. } => transform,
+            ObjectConfig::Csg { transform, .. } => transform,
+            ObjectConfig::Sdf { transform, .. } => transform,
+            ObjectConfig::Mesh { transform, .. } => transform,
+            ObjectConfig::Gltf { transform, .. } => transform,
+        }
+    }
+
+    pub fn transform_mut(&mut self) -> &mut TransformConfig {
+        match self {
+            ObjectConfig::Sphere { transform, .. } => transform,
+            ObjectConfig::Triangle { transform, .. } => transform,
+            ObjectConfig::Plane { transform, .. } => transform,
+            ObjectConfig::Quad { transform, .. } => transform,
+            ObjectConfig::Disk { transform, .. } => transform,
+            ObjectConfig::Cuboid { transform, .. } => transform,
+            ObjectConfig::Cylinder { transform, .. } => transform,
+            ObjectConfig::Cone { transform, .. } => transform,
+            ObjectConfig::Torus { transform, .. } => transform,
+            ObjectConfig::Csg { transform, .. } => transform,
+            ObjectConfig::Sdf { transform, .. } => transform,
+            ObjectConfig::Mesh { transform, .. } => transform,
+            ObjectConfig::Gltf { transform, .. } => transform,
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match self {
             ObjectConfig::Sphere { .. } => "Sphere",
             ObjectConfig::Triangle { .. } => "Triangle",
+            ObjectConfig::Plane { .. } => "Plane",
+            ObjectConfig::Quad { .. } => "Quad",
+            ObjectConfig::Disk { .. } => "Disk",
+            ObjectConfig::Cuboid { .. } => "Box",
+            ObjectConfig::Cylinder { .. } => "Cylinder",
+            ObjectConfig::Cone { .. } => "Cone",
+            ObjectConfig::Torus { .. } => "Torus",
+            ObjectConfig::Csg { .. } => "CSG",
+            ObjectConfig::Sdf { .. } => "SDF",
             ObjectConfig::Mesh { .. } => "Mesh",
+            ObjectConfig::Gltf { .. } => "glTF",
         }
     }
 }
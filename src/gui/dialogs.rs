@@ -4,8 +4,11 @@ use std::path::PathBuf;
 use super::property_editors;
 use super::shortcuts::Shortcuts;
 use super::utils;
-use crate::config::{MaterialConfig, ObjectConfig};
-use crate::math::Vec3;
+use crate::config::{
+    ConductorMetalConfig, ConductorPresetConfig, CsgOperationConfig, FactorSource, LightConfig,
+    MaterialConfig, ObjectConfig, SdfConfig, SurfaceMapsConfig, TextureConfig, TransformConfig,
+};
+use crate::math::{Point3, Vec3};
 
 pub struct HelpDialog {
     open: bool,
@@ -91,6 +94,7 @@ impl AddObjectDialog {
             position: Vec3::new(0.0, 0.0, -1.0),
             radius: 1.0,
             material: default_material,
+            transform: TransformConfig::default(),
         });
         self.error = None;
         self.open = true;
@@ -165,13 +169,19 @@ impl AddMaterialDialog {
     pub fn open(&mut self, materials: &[MaterialConfig]) {
         self.pending = Some(MaterialConfig::Lambertian {
             name: utils::new_material_name("lambertian", materials),
-            albedo: Vec3::new(0.5, 0.5, 0.5),
+            albedo: Vec3::new(0.5, 0.5, 0.5).into(),
+            maps: SurfaceMapsConfig::default(),
         });
         self.error = None;
         self.open = true;
     }
 
-    pub fn show(&mut self, ctx: &Context, materials: &[MaterialConfig]) -> Option<MaterialConfig> {
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        materials: &[MaterialConfig],
+        textures: &[TextureConfig],
+    ) -> Option<MaterialConfig> {
         if !self.open {
             return None;
         }
@@ -187,7 +197,9 @@ impl AddMaterialDialog {
             .show(ctx, |ui| {
                 if let Some(ref mut mat) = self.pending {
                     material_type_selector(ui, mat, materials);
-                    property_editors::material(ui, mat);
+                    let material_names: Vec<String> =
+                        materials.iter().map(|m| m.name().to_string()).collect();
+                    property_editors::material(ui, mat, &material_names, textures);
                 }
 
                 ui.separator();
@@ -222,6 +234,107 @@ impl AddMaterialDialog {
     }
 }
 
+pub struct AddLightDialog {
+    open: bool,
+    pending: Option<LightConfig>,
+}
+
+impl AddLightDialog {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            pending: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.pending = Some(default_point_light());
+        self.open = true;
+    }
+
+    pub fn show(&mut self, ctx: &Context) -> Option<LightConfig> {
+        if !self.open {
+            return None;
+        }
+
+        let mut result: Option<LightConfig> = None;
+        let mut close_requested = false;
+        let mut egui_open = self.open;
+
+        egui::Window::new("Add Light")
+            .open(&mut egui_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if let Some(ref mut light) = self.pending {
+                    light_type_selector(ui, light);
+                    property_editors::light(ui, light);
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        close_requested = true;
+                    }
+                    if ui.button("Add").clicked()
+                        && let Some(ref light) = self.pending
+                    {
+                        result = Some(light.clone());
+                        close_requested = true;
+                    }
+                });
+            });
+
+        if close_requested {
+            self.open = false;
+        } else {
+            self.open = egui_open;
+        }
+
+        result
+    }
+}
+
+fn default_point_light() -> LightConfig {
+    LightConfig::Point {
+        position: Point3::new(0.0, 2.0, 0.0),
+        color: Vec3::new(1.0, 1.0, 1.0),
+        intensity: 10.0,
+    }
+}
+
+fn light_type_selector(ui: &mut egui::Ui, light: &mut LightConfig) {
+    ui.horizontal(|ui| {
+        ui.label("Type:");
+        egui::ComboBox::from_id_salt("light_type_dialog")
+            .selected_text(light.type_name())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(light, default_point_light(), "Point Light");
+                ui.selectable_value(
+                    light,
+                    LightConfig::Spot {
+                        position: Point3::new(0.0, 2.0, 0.0),
+                        direction: Vec3::new(0.0, -1.0, 0.0),
+                        color: Vec3::new(1.0, 1.0, 1.0),
+                        intensity: 10.0,
+                        inner_angle: 20.0,
+                        outer_angle: 30.0,
+                    },
+                    "Spot Light",
+                );
+                ui.selectable_value(
+                    light,
+                    LightConfig::Directional {
+                        direction: Vec3::new(-1.0, -1.0, -1.0),
+                        color: Vec3::new(1.0, 1.0, 1.0),
+                        intensity: 1.0,
+                    },
+                    "Directional Light",
+                );
+            });
+    });
+}
+
 fn object_type_selector(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[MaterialConfig]) {
     let default_material = materials
         .first()
@@ -239,17 +352,146 @@ fn object_type_selector(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[
                         position: Vec3::new(0.0, 0.0, -1.0),
                         radius: 1.0,
                         material: default_material.clone(),
+                        transform: TransformConfig::default(),
                     },
                     "Sphere",
                 );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Plane {
+                        point: Point3::new(0.0, -1.0, 0.0),
+                        normal: Vec3::new(0.0, 1.0, 0.0),
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Plane",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Quad {
+                        corner: Point3::new(-0.5, -0.5, -1.0),
+                        edge1: Vec3::new(1.0, 0.0, 0.0),
+                        edge2: Vec3::new(0.0, 1.0, 0.0),
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Quad",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Disk {
+                        center: Point3::new(0.0, 0.0, -1.0),
+                        normal: Vec3::new(0.0, 0.0, 1.0),
+                        radius: 0.5,
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Disk",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Cuboid {
+                        min: Point3::new(-0.5, -0.5, -1.5),
+                        max: Point3::new(0.5, 0.5, -0.5),
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Box",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Cylinder {
+                        base: Point3::new(0.0, -0.5, -1.0),
+                        top: Point3::new(0.0, 0.5, -1.0),
+                        radius: 0.5,
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Cylinder",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Cone {
+                        base: Point3::new(0.0, -0.5, -1.0),
+                        apex: Point3::new(0.0, 0.5, -1.0),
+                        radius: 0.5,
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Cone",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Torus {
+                        center: Point3::new(0.0, 0.0, -1.0),
+                        axis: Vec3::new(0.0, 1.0, 0.0),
+                        major_radius: 0.5,
+                        minor_radius: 0.2,
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "Torus",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Csg {
+                        operation: CsgOperationConfig::Difference,
+                        left: Box::new(ObjectConfig::Cuboid {
+                            min: Point3::new(-0.5, -0.5, -1.5),
+                            max: Point3::new(0.5, 0.5, -0.5),
+                            material: default_material.clone(),
+                            transform: TransformConfig::default(),
+                        }),
+                        right: Box::new(ObjectConfig::Sphere {
+                            position: Vec3::new(0.0, 0.0, -1.0),
+                            radius: 0.65,
+                            material: default_material.clone(),
+                            transform: TransformConfig::default(),
+                        }),
+                        transform: TransformConfig::default(),
+                    },
+                    "CSG",
+                );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Sdf {
+                        shape: SdfConfig::SmoothUnion {
+                            smoothness: 0.2,
+                            left: Box::new(SdfConfig::Sphere {
+                                center: Point3::new(-0.25, 0.0, -1.0),
+                                radius: 0.3,
+                            }),
+                            right: Box::new(SdfConfig::Sphere {
+                                center: Point3::new(0.25, 0.0, -1.0),
+                                radius: 0.3,
+                            }),
+                        },
+                        material: default_material.clone(),
+                        transform: TransformConfig::default(),
+                    },
+                    "SDF",
+                );
                 ui.selectable_value(
                     obj,
                     ObjectConfig::Mesh {
                         path: PathBuf::new(),
                         material: default_material.clone(),
+                        material_override: None,
+                        transform: TransformConfig::default(),
                     },
                     "Mesh",
                 );
+                ui.selectable_value(
+                    obj,
+                    ObjectConfig::Gltf {
+                        path: PathBuf::new(),
+                        material: default_material.clone(),
+                        use_camera: false,
+                        material_override: None,
+                        transform: TransformConfig::default(),
+                    },
+                    "glTF",
+                );
             });
     });
 }
@@ -269,7 +511,8 @@ fn material_type_selector(
                     mat,
                     MaterialConfig::Lambertian {
                         name: lambertian_name,
-                        albedo: Vec3::new(0.5, 0.5, 0.5),
+                        albedo: Vec3::new(0.5, 0.5, 0.5).into(),
+                        maps: SurfaceMapsConfig::default(),
                     },
                     "Lambertian",
                 );
@@ -279,17 +522,46 @@ fn material_type_selector(
                     mat,
                     MaterialConfig::Metal {
                         name: metal_name,
-                        albedo: Vec3::new(0.5, 0.5, 0.5),
+                        albedo: Vec3::new(0.5, 0.5, 0.5).into(),
                         fuzz: 0.3,
+                        maps: SurfaceMapsConfig::default(),
                     },
                     "Metal",
                 );
 
+                let principled_name = utils::new_material_name("principled", existing);
+                ui.selectable_value(
+                    mat,
+                    MaterialConfig::Principled {
+                        name: principled_name,
+                        base_color: Vec3::new(0.8, 0.8, 0.8).into(),
+                        metallic: 0.0,
+                        roughness: 0.5,
+                        specular: 0.5,
+                        maps: SurfaceMapsConfig::default(),
+                    },
+                    "Principled",
+                );
+
+                let conductor_name = utils::new_material_name("conductor", existing);
+                ui.selectable_value(
+                    mat,
+                    MaterialConfig::Conductor {
+                        name: conductor_name,
+                        metal: ConductorMetalConfig::Preset(ConductorPresetConfig::Gold),
+                        roughness: 0.2,
+                        anisotropy: 0.0,
+                        maps: SurfaceMapsConfig::default(),
+                    },
+                    "Conductor",
+                );
+
                 let normal_vis_name = utils::new_material_name("normal_vis", existing);
                 ui.selectable_value(
                     mat,
                     MaterialConfig::NormalVisualization {
                         name: normal_vis_name,
+                        maps: SurfaceMapsConfig::default(),
                     },
                     "Normal Visualization",
                 );
@@ -300,6 +572,11 @@ fn material_type_selector(
                     MaterialConfig::Dielectric {
                         name: default_dielectric_name,
                         ior: 1.5,
+                        roughness: 0.0,
+                        absorption_color: Vec3::ONES,
+                        absorption_distance: 1.0,
+                        thin_walled: false,
+                        maps: SurfaceMapsConfig::default(),
                     },
                     "Dielectric",
                 );
@@ -309,11 +586,43 @@ fn material_type_selector(
                     mat,
                     MaterialConfig::DiffuseLight {
                         name: diffuse_light_name,
-                        albedo: Vec3::new(1.0, 1.0, 1.0),
+                        albedo: Vec3::new(1.0, 1.0, 1.0).into(),
                         strength: 1.0,
                     },
                     "Diffuse Light",
                 );
+
+                // Layered materials start out made of the first existing material
+                let first_material = existing
+                    .first()
+                    .map(|m| m.name().to_string())
+                    .unwrap_or_default();
+
+                let mix_name = utils::new_material_name("mix", existing);
+                ui.selectable_value(
+                    mat,
+                    MaterialConfig::Mix {
+                        name: mix_name,
+                        first: first_material.clone(),
+                        second: first_material.clone(),
+                        factor: FactorSource::Constant(0.5),
+                    },
+                    "Mix",
+                );
+
+                let coated_name = utils::new_material_name("coated", existing);
+                ui.selectable_value(
+                    mat,
+                    MaterialConfig::Coated {
+                        name: coated_name,
+                        base: first_material,
+                        ior: 1.5,
+                        roughness: 0.0,
+                        tint: Vec3::ONES,
+                        maps: SurfaceMapsConfig::default(),
+                    },
+                    "Coated",
+                );
             });
     });
 }
@@ -1,14 +1,22 @@
+use std::ops::ControlFlow;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use eframe::egui;
 use log::error;
 
+use crate::GeometryCache;
 use crate::config::{Config, MaterialConfig, ObjectConfig};
-use crate::load_scene_from_config;
+use crate::image::Image;
+use crate::load_scene_from_config_with_cache;
+use crate::rendering::renderer::ProgressiveLimits;
 use crate::rendering::sampler::RandomSampler;
 
-use super::dialogs::{AddMaterialDialog, AddObjectDialog, HelpDialog};
+use super::dialogs::{AddLightDialog, AddMaterialDialog, AddObjectDialog, HelpDialog};
 use super::property_editors;
 use super::shortcuts::Shortcuts;
 use super::utils;
@@ -19,24 +27,44 @@ pub struct ViewportRendererConfig {
     pub max_bounces: u32,
 }
 
+/// A progressive viewport render running on a background thread.
+/// Dropping the job asks the thread to stop after its current pass.
+struct PreviewJob {
+    passes: Receiver<(Image, u32)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for PreviewJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
 pub struct Editor {
     config: Config,
     viewport_renderer: ViewportRendererConfig,
     preview_texture: Option<egui::TextureHandle>,
+    preview_job: Option<PreviewJob>,
+    preview_passes: u32,
     selected_object: Option<usize>,
     selected_material: Option<usize>,
+    selected_light: Option<usize>,
     pending_object_deletion: Option<usize>,
     pending_material_deletion: Option<usize>,
+    pending_light_deletion: Option<usize>,
 
     // Dialogs
     add_object_dialog: AddObjectDialog,
     add_material_dialog: AddMaterialDialog,
+    add_light_dialog: AddLightDialog,
     help_dialog: HelpDialog,
 
     // Shortcuts
     shortcuts: Shortcuts,
 
     asset_base_path: PathBuf,
+    /// Meshes loaded by earlier previews, so that moving objects does not reload them
+    geometry_cache: Arc<Mutex<GeometryCache>>,
 }
 
 impl Editor {
@@ -48,15 +76,21 @@ impl Editor {
                 max_bounces: 10,
             },
             preview_texture: None,
+            preview_job: None,
+            preview_passes: 0,
             selected_object: None,
             selected_material: None,
+            selected_light: None,
             pending_object_deletion: None,
             pending_material_deletion: None,
+            pending_light_deletion: None,
             add_object_dialog: AddObjectDialog::new(),
             add_material_dialog: AddMaterialDialog::new(),
+            add_light_dialog: AddLightDialog::new(),
             help_dialog: HelpDialog::new(),
             shortcuts: Shortcuts::new(),
             asset_base_path: PathBuf::new(),
+            geometry_cache: Arc::new(Mutex::new(GeometryCache::new())),
         }
     }
 
@@ -71,14 +105,15 @@ impl Editor {
     pub fn load_config(&mut self, config: Config) {
         self.config = config;
         self.preview_texture = None;
+        self.preview_job = None;
         self.selected_object = None;
         self.selected_material = None;
+        self.selected_light = None;
     }
 
-    fn render_preview(&self, available_size: egui::Rect) -> Option<egui::ColorImage> {
-        let mut rng = rand::rng();
-        let mut sampler = RandomSampler::new(&mut rng);
-
+    /// Starts rendering the scene progressively in the background, replacing any preview render
+    /// that is still running. Finished passes are picked up by [`Editor::poll_preview`].
+    fn start_preview_render(&mut self, ctx: &egui::Context, available_size: egui::Rect) {
         let preview_width = available_size.max.x;
         let preview_height = available_size.max.y;
         let preview_aspect_ratio = (preview_width / preview_height) as f64;
@@ -91,35 +126,97 @@ impl Editor {
             renderer: crate::RendererConfig {
                 max_bounces: self.viewport_renderer.max_bounces,
                 samples_per_pixel: self.viewport_renderer.samples_per_pixel,
+                ..self.config.renderer.clone()
             },
             ..self.config.clone()
         };
+        let asset_base_path = self.asset_base_path.clone();
+        let geometry_cache = self.geometry_cache.clone();
+        let limits = ProgressiveLimits {
+            max_passes: Some(self.viewport_renderer.samples_per_pixel),
+            ..Default::default()
+        };
 
-        let (camera, world, renderer) =
-            load_scene_from_config(&preview_config, &self.asset_base_path);
-
-        let image = renderer.render(&world, &camera, &mut sampler, true);
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let job_cancelled = cancelled.clone();
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let mut rng = rand::rng();
+            let mut sampler = RandomSampler::new(&mut rng);
+
+            let (camera, world, renderer) = {
+                let mut cache = geometry_cache
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                load_scene_from_config_with_cache(&preview_config, &asset_base_path, &mut cache)
+            };
+
+            renderer.render_progressive(
+                &world,
+                &camera,
+                &mut sampler,
+                &limits,
+                false,
+                |image, passes| {
+                    if job_cancelled.load(Ordering::Relaxed)
+                        || sender.send((image.clone(), passes)).is_err()
+                    {
+                        return ControlFlow::Break(());
+                    }
+                    ctx.request_repaint();
+                    ControlFlow::Continue(())
+                },
+            );
+        });
 
-        let width = image.width as usize;
-        let height = image.height as usize;
+        self.preview_job = Some(PreviewJob {
+            passes: receiver,
+            cancelled,
+        });
+    }
 
-        let mut rgba_data = Vec::with_capacity(width * height * 4);
+    /// Shows the most recent pass of the running preview render, if there is a new one.
+    fn poll_preview(&mut self, ctx: &egui::Context) {
+        let Some(job) = &self.preview_job else {
+            return;
+        };
 
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.pixels[y * width + x];
-                rgba_data.push((pixel.r * 255.0) as u8);
-                rgba_data.push((pixel.g * 255.0) as u8);
-                rgba_data.push((pixel.b * 255.0) as u8);
-                rgba_data.push(255);
+        if let Some((image, passes)) = job.passes.try_iter().last() {
+            let color_image = to_color_image(&image);
+            match &mut self.preview_texture {
+                Some(texture) => texture.set(color_image, egui::TextureOptions::NEAREST),
+                None => {
+                    self.preview_texture = Some(ctx.load_texture(
+                        "preview",
+                        color_image,
+                        egui::TextureOptions::NEAREST,
+                    ))
+                }
             }
+            self.preview_passes = passes;
         }
+    }
+}
+
+fn to_color_image(image: &Image) -> egui::ColorImage {
+    let width = image.width as usize;
+    let height = image.height as usize;
 
-        Some(egui::ColorImage::from_rgba_unmultiplied(
-            [width, height],
-            &rgba_data,
-        ))
+    let mut rgba_data = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.pixels[y * width + x];
+            rgba_data.push((pixel.r * 255.0) as u8);
+            rgba_data.push((pixel.g * 255.0) as u8);
+            rgba_data.push((pixel.b * 255.0) as u8);
+            rgba_data.push(255);
+        }
     }
+
+    egui::ColorImage::from_rgba_unmultiplied([width, height], &rgba_data)
 }
 
 impl Default for Editor {
@@ -130,6 +227,7 @@ impl Default for Editor {
 
 impl eframe::App for Editor {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_preview(ctx);
         self.ui_top_menu(ctx);
         self.ui_left_panel(ctx);
         self.ui_right_panel(ctx);
@@ -192,6 +290,10 @@ impl Editor {
                 if ui.button("+ Add material").clicked() {
                     self.add_material_dialog.open(&self.config.materials);
                 }
+
+                if ui.button("+ Add light").clicked() {
+                    self.add_light_dialog.open();
+                }
             });
 
             egui::CollapsingHeader::new("Objects")
@@ -207,10 +309,26 @@ impl Editor {
                                     position.x, position.y, position.z, radius
                                 )
                             }
-                            ObjectConfig::Triangle { .. } => "Triangle".to_string(),
+                            ObjectConfig::Triangle { .. }
+                            | ObjectConfig::Plane { .. }
+                            | ObjectConfig::Quad { .. }
+                            | ObjectConfig::Disk { .. }
+                            | ObjectConfig::Cuboid { .. }
+                            | ObjectConfig::Cylinder { .. }
+                            | ObjectConfig::Cone { .. }
+                            | ObjectConfig::Torus { .. } => obj.type_name().to_string(),
+                            ObjectConfig::Csg { operation, .. } => {
+                                format!("CSG ({})", operation.name())
+                            }
+                            ObjectConfig::Sdf { shape, .. } => {
+                                format!("SDF ({})", shape.type_name())
+                            }
                             ObjectConfig::Mesh { path, .. } => {
                                 format!("Mesh ({})", path.display())
                             }
+                            ObjectConfig::Gltf { path, .. } => {
+                                format!("glTF ({})", path.display())
+                            }
                         };
 
                         let is_selected = self.selected_object == Some(i);
@@ -219,6 +337,7 @@ impl Editor {
                         if response.clicked() {
                             self.selected_object = Some(i);
                             self.selected_material = None;
+                            self.selected_light = None;
                         }
                         response.context_menu(|ui| {
                             if ui.button("Delete").clicked() {
@@ -239,7 +358,13 @@ impl Editor {
                             MaterialConfig::Metal { name, .. } => {
                                 format!("{} (Metal)", name)
                             }
-                            MaterialConfig::NormalVisualization { name } => {
+                            MaterialConfig::Principled { name, .. } => {
+                                format!("{} (Principled)", name)
+                            }
+                            MaterialConfig::Conductor { name, .. } => {
+                                format!("{} (Conductor)", name)
+                            }
+                            MaterialConfig::NormalVisualization { name, .. } => {
                                 format!("{} (Normal)", name)
                             }
                             MaterialConfig::Dielectric { name, .. } => {
@@ -248,6 +373,12 @@ impl Editor {
                             MaterialConfig::DiffuseLight { name, .. } => {
                                 format!("{} (Diffuse Light)", name)
                             }
+                            MaterialConfig::Mix { name, .. } => {
+                                format!("{} (Mix)", name)
+                            }
+                            MaterialConfig::Coated { name, .. } => {
+                                format!("{} (Coated)", name)
+                            }
                         };
 
                         let is_selected = self.selected_material == Some(i);
@@ -256,6 +387,7 @@ impl Editor {
                         if response.clicked() {
                             self.selected_material = Some(i);
                             self.selected_object = None;
+                            self.selected_light = None;
                         }
                         response.context_menu(|ui| {
                             if ui.button("Delete").clicked() {
@@ -265,6 +397,26 @@ impl Editor {
                     }
                 });
 
+            egui::CollapsingHeader::new("Lights")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for (i, light) in self.config.lights.iter().enumerate() {
+                        let is_selected = self.selected_light == Some(i);
+
+                        let response = ui.selectable_label(is_selected, light.type_name());
+                        if response.clicked() {
+                            self.selected_light = Some(i);
+                            self.selected_object = None;
+                            self.selected_material = None;
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Delete").clicked() {
+                                self.pending_light_deletion = Some(i);
+                            }
+                        });
+                    }
+                });
+
             ui.separator();
         });
 
@@ -277,6 +429,11 @@ impl Editor {
             self.pending_material_deletion = None;
             self.config.materials.remove(material_index_to_delete);
         }
+        if let Some(light_index_to_delete) = self.pending_light_deletion {
+            self.pending_light_deletion = None;
+            self.selected_light = None;
+            self.config.lights.remove(light_index_to_delete);
+        }
     }
 
     fn ui_right_panel(&mut self, ctx: &egui::Context) {
@@ -287,6 +444,8 @@ impl Editor {
                     "{} Properties",
                     if self.selected_material.is_some() {
                         "Material"
+                    } else if self.selected_light.is_some() {
+                        "Light"
                     } else {
                         "Object"
                     }
@@ -300,11 +459,24 @@ impl Editor {
                         property_editors::object(ui, obj, &self.config.materials);
                     }
                 } else if let Some(mat_idx) = self.selected_material {
+                    // A material can't be made of itself
+                    let material_names: Vec<String> = self
+                        .config
+                        .materials
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| *i != mat_idx)
+                        .map(|(_, mat)| mat.name().to_string())
+                        .collect();
                     if let Some(mat) = self.config.materials.get_mut(mat_idx) {
-                        property_editors::material(ui, mat);
+                        property_editors::material(ui, mat, &material_names, &self.config.textures);
+                    }
+                } else if let Some(light_idx) = self.selected_light {
+                    if let Some(light) = self.config.lights.get_mut(light_idx) {
+                        property_editors::light(ui, light);
                     }
                 } else {
-                    ui.label("Select an object or material to edit");
+                    ui.label("Select an object, material or light to edit");
                 }
             });
 
@@ -355,13 +527,6 @@ impl Editor {
         )
     }
 
-    fn render_preview_to_texture(&mut self, ctx: &egui::Context, available_size: egui::Rect) {
-        if let Some(color_image) = self.render_preview(available_size) {
-            self.preview_texture =
-                Some(ctx.load_texture("preview", color_image, egui::TextureOptions::NEAREST));
-        }
-    }
-
     fn ui_central_panel(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             // Check for keyboard shortcuts
@@ -370,7 +535,7 @@ impl Editor {
                 .is_pressed(ctx, &self.shortcuts.render_preview)
             {
                 let preview_size = self.preview_size(ui);
-                self.render_preview_to_texture(ctx, preview_size);
+                self.start_preview_render(ctx, preview_size);
             }
 
             // Preview viewport
@@ -382,6 +547,16 @@ impl Editor {
                 if let Some(ref texture) = self.preview_texture {
                     let image = egui::Image::new(texture);
                     image.paint_at(ui, rect);
+                    ui.painter().text(
+                        rect.left_top() + egui::vec2(8.0, 8.0),
+                        egui::Align2::LEFT_TOP,
+                        format!(
+                            "Pass {}/{}",
+                            self.preview_passes, self.viewport_renderer.samples_per_pixel
+                        ),
+                        egui::FontId::default(),
+                        egui::Color32::WHITE,
+                    );
                 } else {
                     ui.painter()
                         .rect_filled(rect, 0.0, egui::Color32::DARK_GRAY);
@@ -406,10 +581,18 @@ impl Editor {
         }
 
         // Material Dialog
-        if let Some(mat) = self.add_material_dialog.show(ctx, &self.config.materials) {
+        if let Some(mat) =
+            self.add_material_dialog
+                .show(ctx, &self.config.materials, &self.config.textures)
+        {
             self.config.materials.push(mat);
         }
 
+        // Light Dialog
+        if let Some(light) = self.add_light_dialog.show(ctx) {
+            self.config.lights.push(light);
+        }
+
         // Help Dialog
         self.help_dialog.show(ctx, &self.shortcuts);
     }
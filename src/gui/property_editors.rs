@@ -2,7 +2,16 @@ use std::path::PathBuf;
 
 use eframe::egui::{self};
 
-use crate::{MaterialConfig, ObjectConfig, gui::editor::ViewportRendererConfig};
+use crate::{
+    MaterialConfig, ObjectConfig,
+    config::{
+        BumpMapConfig, ConductorMetalConfig, ConductorPresetConfig, CsgOperationConfig,
+        LightConfig, NormalMapConfig, RotationConfig, ScaleConfig, SurfaceMapsConfig,
+        TextureConfig, TransformConfig,
+    },
+    gui::editor::ViewportRendererConfig,
+    math::Vec3,
+};
 
 use super::widgets;
 
@@ -15,6 +24,7 @@ pub fn object(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[MaterialCo
                 position,
                 radius,
                 material,
+                ..
             } => {
                 ui.label("Type:");
                 ui.label("Sphere");
@@ -30,17 +40,222 @@ pub fn object(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[MaterialCo
                 ui.add(egui::DragValue::new(radius).range(0.0..=1e9).speed(0.1));
                 ui.end_row();
 
-                ui.label("Material:");
-                egui::ComboBox::from_id_salt("material_select")
-                    .selected_text(material.clone())
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Plane {
+                point,
+                normal,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Plane");
+                ui.end_row();
+
+                ui.label("Point:");
+                widgets::vector_input(ui, &mut point.0);
+                ui.end_row();
+
+                ui.label("Normal:");
+                widgets::vector_input(ui, normal);
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Quad {
+                corner,
+                edge1,
+                edge2,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Quad");
+                ui.end_row();
+
+                ui.label("Corner:");
+                widgets::vector_input(ui, &mut corner.0);
+                ui.end_row();
+
+                ui.label("Edge 1:");
+                widgets::vector_input(ui, edge1);
+                ui.end_row();
+
+                ui.label("Edge 2:");
+                widgets::vector_input(ui, edge2);
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Disk {
+                center,
+                normal,
+                radius,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Disk");
+                ui.end_row();
+
+                ui.label("Center:");
+                widgets::vector_input(ui, &mut center.0);
+                ui.end_row();
+
+                ui.label("Normal:");
+                widgets::vector_input(ui, normal);
+                ui.end_row();
+
+                ui.label("Radius:");
+                ui.add(egui::DragValue::new(radius).range(0.0..=1e9).speed(0.1));
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Cuboid {
+                min, max, material, ..
+            } => {
+                ui.label("Type:");
+                ui.label("Box");
+                ui.end_row();
+
+                ui.label("Min:");
+                widgets::vector_input(ui, &mut min.0);
+                ui.end_row();
+
+                ui.label("Max:");
+                widgets::vector_input(ui, &mut max.0);
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Cylinder {
+                base,
+                top,
+                radius,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Cylinder");
+                ui.end_row();
+
+                ui.label("Base:");
+                widgets::vector_input(ui, &mut base.0);
+                ui.end_row();
+
+                ui.label("Top:");
+                widgets::vector_input(ui, &mut top.0);
+                ui.end_row();
+
+                ui.label("Radius:");
+                ui.add(egui::DragValue::new(radius).range(0.0..=1e9).speed(0.1));
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Cone {
+                base,
+                apex,
+                radius,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Cone");
+                ui.end_row();
+
+                ui.label("Base:");
+                widgets::vector_input(ui, &mut base.0);
+                ui.end_row();
+
+                ui.label("Apex:");
+                widgets::vector_input(ui, &mut apex.0);
+                ui.end_row();
+
+                ui.label("Radius:");
+                ui.add(egui::DragValue::new(radius).range(0.0..=1e9).speed(0.1));
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                material,
+                ..
+            } => {
+                ui.label("Type:");
+                ui.label("Torus");
+                ui.end_row();
+
+                ui.label("Center:");
+                widgets::vector_input(ui, &mut center.0);
+                ui.end_row();
+
+                ui.label("Axis:");
+                widgets::vector_input(ui, axis);
+                ui.end_row();
+
+                ui.label("Major radius:");
+                ui.add(
+                    egui::DragValue::new(major_radius)
+                        .range(0.0..=1e9)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("Minor radius:");
+                ui.add(
+                    egui::DragValue::new(minor_radius)
+                        .range(0.0..=1e9)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Csg { operation, .. } => {
+                ui.label("Type:");
+                ui.label("CSG");
+                ui.end_row();
+
+                ui.label("Operation:");
+                egui::ComboBox::from_id_salt("csg_operation_select")
+                    .selected_text(operation.name())
                     .show_ui(ui, |ui| {
-                        for mat in materials {
-                            ui.selectable_value(material, mat.name().to_string(), mat.name());
+                        for option in [
+                            CsgOperationConfig::Union,
+                            CsgOperationConfig::Intersection,
+                            CsgOperationConfig::Difference,
+                        ] {
+                            ui.selectable_value(operation, option, option.name());
                         }
                     });
                 ui.end_row();
             }
-            ObjectConfig::Mesh { path, material } => {
+            ObjectConfig::Sdf {
+                shape, material, ..
+            } => {
+                // The expression tree is only editable in the scene file
+                ui.label("Type:");
+                ui.label("SDF");
+                ui.end_row();
+
+                ui.label("Shape:");
+                ui.label(shape.type_name());
+                ui.end_row();
+
+                material_input(ui, "Material:", material, materials);
+            }
+            ObjectConfig::Mesh {
+                path,
+                material,
+                material_override,
+                ..
+            } => {
                 let mut display_path = path.to_string_lossy().to_string();
                 ui.label("Type:");
                 ui.label("Mesh");
@@ -50,7 +265,7 @@ pub fn object(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[MaterialCo
                 ui.text_edit_singleline(&mut display_path);
                 if ui.button("Browse").clicked()
                     && let Some(selected_path) = rfd::FileDialog::new()
-                        .add_filter("OBJ files", &["obj"])
+                        .add_filter("Mesh files", &["obj", "ply", "stl"])
                         .pick_file()
                 {
                     display_path = selected_path.display().to_string();
@@ -58,26 +273,239 @@ pub fn object(ui: &mut egui::Ui, obj: &mut ObjectConfig, materials: &[MaterialCo
                 *path = PathBuf::from(display_path);
                 ui.end_row();
 
-                ui.label("Material:");
-                egui::ComboBox::from_id_salt("material_select")
-                    .selected_text(material.clone())
-                    .show_ui(ui, |ui| {
-                        for mat in materials {
-                            ui.selectable_value(material, mat.name().to_string(), mat.name());
-                        }
-                    });
+                material_input(ui, "Material:", material, materials);
+
+                material_override_input(ui, material_override, materials);
+            }
+            ObjectConfig::Gltf {
+                path,
+                material,
+                use_camera,
+                material_override,
+                ..
+            } => {
+                let mut display_path = path.to_string_lossy().to_string();
+                ui.label("Type:");
+                ui.label("glTF");
+                ui.end_row();
+
+                ui.label("Path:");
+                ui.text_edit_singleline(&mut display_path);
+                if ui.button("Browse").clicked()
+                    && let Some(selected_path) = rfd::FileDialog::new()
+                        .add_filter("glTF files", &["gltf", "glb"])
+                        .pick_file()
+                {
+                    display_path = selected_path.display().to_string();
+                }
+                *path = PathBuf::from(display_path);
+                ui.end_row();
+
+                material_input(ui, "Default material:", material, materials);
+
+                ui.label("Use camera:");
+                ui.checkbox(use_camera, "");
                 ui.end_row();
+
+                material_override_input(ui, material_override, materials);
             }
             _ => {}
         });
+
+    egui::Grid::new("object_transform_grid")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| transform(ui, obj.transform_mut()));
+
+    if let ObjectConfig::Csg { left, right, .. } = obj {
+        for (label, operand) in [("Left operand", left), ("Right operand", right)] {
+            egui::CollapsingHeader::new(label)
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.push_id(label, |ui| object(ui, operand, materials))
+                });
+        }
+    }
+}
+
+fn material_input(
+    ui: &mut egui::Ui,
+    label: &str,
+    material: &mut String,
+    materials: &[MaterialConfig],
+) {
+    ui.label(label);
+    egui::ComboBox::from_id_salt("material_select")
+        .selected_text(material.clone())
+        .show_ui(ui, |ui| {
+            for mat in materials {
+                ui.selectable_value(material, mat.name().to_string(), mat.name());
+            }
+        });
+    ui.end_row();
+}
+
+fn material_override_input(
+    ui: &mut egui::Ui,
+    material_override: &mut Option<String>,
+    materials: &[MaterialConfig],
+) {
+    ui.label("Material override:");
+    egui::ComboBox::from_id_salt("material_override_select")
+        .selected_text(material_override.clone().unwrap_or("None".to_string()))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(material_override, None, "None");
+            for mat in materials {
+                ui.selectable_value(material_override, Some(mat.name().to_string()), mat.name());
+            }
+        });
+    ui.end_row();
+}
+
+fn transform(ui: &mut egui::Ui, transform: &mut TransformConfig) {
+    ui.label("Translate:");
+    widgets::vector_input(ui, &mut transform.translate);
+    ui.end_row();
+
+    ui.label("Rotate:");
+    ui.horizontal(|ui| match &mut transform.rotate {
+        None => {
+            if ui.button("Add").clicked() {
+                transform.rotate = Some(RotationConfig::Euler(crate::math::Vec3::default()));
+            }
+        }
+        Some(RotationConfig::Euler(angles)) => {
+            widgets::vector_input(ui, angles);
+        }
+        Some(RotationConfig::AxisAngle { axis, angle }) => {
+            widgets::vector_input(ui, axis);
+            ui.add(egui::DragValue::new(angle).speed(1.0).suffix("°"));
+        }
+    });
+    ui.end_row();
+
+    ui.label("Scale:");
+    ui.horizontal(|ui| match &mut transform.scale {
+        None => {
+            if ui.button("Add").clicked() {
+                transform.scale = Some(ScaleConfig::Uniform(1.0));
+            }
+        }
+        Some(ScaleConfig::Uniform(factor)) => {
+            ui.add(egui::DragValue::new(factor).speed(0.1));
+        }
+        Some(ScaleConfig::PerAxis(factors)) => {
+            widgets::vector_input(ui, factors);
+        }
+    });
+    ui.end_row();
+}
+
+/// Edits the normal and bump maps of a material, each either off or one of the scene's
+/// textures
+fn surface_maps(ui: &mut egui::Ui, maps: &mut SurfaceMapsConfig, textures: &[TextureConfig]) {
+    ui.label("Normal map:");
+    ui.horizontal(|ui| {
+        let selected = maps
+            .normal_map
+            .as_ref()
+            .map_or("None".to_string(), |map| map.texture.clone());
+        egui::ComboBox::from_id_salt("normal_map_select")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(maps.normal_map.is_none(), "None")
+                    .clicked()
+                {
+                    maps.normal_map = None;
+                }
+                for texture in textures {
+                    let name = texture.name();
+                    let is_selected = maps
+                        .normal_map
+                        .as_ref()
+                        .is_some_and(|map| map.texture == name);
+                    if ui.selectable_label(is_selected, name).clicked() {
+                        let strength = maps.normal_map.as_ref().map_or(1.0, |map| map.strength);
+                        maps.normal_map = Some(NormalMapConfig {
+                            texture: name.to_string(),
+                            strength,
+                        });
+                    }
+                }
+            });
+        if let Some(map) = &mut maps.normal_map {
+            ui.add(egui::Slider::new(&mut map.strength, 0.0..=2.0))
+                .on_hover_text("strength");
+        }
+    });
+    ui.end_row();
+
+    ui.label("Bump map:");
+    ui.horizontal(|ui| {
+        let selected = maps
+            .bump_map
+            .as_ref()
+            .map_or("None".to_string(), |map| map.texture.clone());
+        egui::ComboBox::from_id_salt("bump_map_select")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(maps.bump_map.is_none(), "None")
+                    .clicked()
+                {
+                    maps.bump_map = None;
+                }
+                for texture in textures {
+                    let name = texture.name();
+                    let is_selected = maps
+                        .bump_map
+                        .as_ref()
+                        .is_some_and(|map| map.texture == name);
+                    if ui.selectable_label(is_selected, name).clicked() {
+                        let height = maps.bump_map.as_ref().map_or(0.01, |map| map.height);
+                        maps.bump_map = Some(BumpMapConfig {
+                            texture: name.to_string(),
+                            height,
+                        });
+                    }
+                }
+            });
+        if let Some(map) = &mut maps.bump_map {
+            ui.add(egui::DragValue::new(&mut map.height).speed(0.001))
+                .on_hover_text("height");
+        }
+    });
+}
+
+/// Picks one of the other materials a mix or coated material is made of
+fn material_reference_input(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    material: &mut String,
+    material_names: &[String],
+) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(material.clone())
+        .show_ui(ui, |ui| {
+            for name in material_names {
+                ui.selectable_value(material, name.clone(), name);
+            }
+        });
 }
 
-pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
+/// Edits a material. `material_names` are the other materials it can be made of.
+pub fn material(
+    ui: &mut egui::Ui,
+    mat: &mut MaterialConfig,
+    material_names: &[String],
+    textures: &[TextureConfig],
+) {
     egui::Grid::new("object_properties_grid")
         .num_columns(2)
         .striped(true)
         .show(ui, |ui| match mat {
-            MaterialConfig::Lambertian { name, albedo } => {
+            MaterialConfig::Lambertian { name, albedo, maps } => {
                 ui.label("Type:");
                 ui.label("Lambertian");
                 ui.end_row();
@@ -87,9 +515,17 @@ pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
                 ui.end_row();
 
                 ui.label("Albedo:");
-                widgets::color_input(ui, albedo);
+                widgets::color_source_input(ui, "albedo_source", albedo, textures);
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
             }
-            MaterialConfig::Metal { name, albedo, fuzz } => {
+            MaterialConfig::Metal {
+                name,
+                albedo,
+                fuzz,
+                maps,
+            } => {
                 ui.label("Type:");
                 ui.label("Metal");
                 ui.end_row();
@@ -99,13 +535,116 @@ pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
                 ui.end_row();
 
                 ui.label("Albedo:");
-                widgets::color_input(ui, albedo);
+                widgets::color_source_input(ui, "albedo_source", albedo, textures);
                 ui.end_row();
 
                 ui.label("Fuzz:");
                 ui.add(egui::Slider::new(fuzz, 0.0..=1.0));
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
             }
-            MaterialConfig::NormalVisualization { name } => {
+            MaterialConfig::Principled {
+                name,
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                maps,
+            } => {
+                ui.label("Type:");
+                ui.label("Principled");
+                ui.end_row();
+
+                ui.label("Name:");
+                ui.text_edit_singleline(name);
+                ui.end_row();
+
+                ui.label("Base color:");
+                widgets::color_source_input(ui, "base_color_source", base_color, textures);
+                ui.end_row();
+
+                ui.label("Metallic:");
+                ui.add(egui::Slider::new(metallic, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Roughness:");
+                ui.add(egui::Slider::new(roughness, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Specular:");
+                ui.add(egui::Slider::new(specular, 0.0..=1.0));
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
+            }
+            MaterialConfig::Conductor {
+                name,
+                metal,
+                roughness,
+                anisotropy,
+                maps,
+            } => {
+                ui.label("Type:");
+                ui.label("Conductor");
+                ui.end_row();
+
+                ui.label("Name:");
+                ui.text_edit_singleline(name);
+                ui.end_row();
+
+                ui.label("Metal:");
+                let selected = match metal {
+                    ConductorMetalConfig::Preset(preset) => preset.name(),
+                    ConductorMetalConfig::Custom { .. } => "Custom",
+                };
+                egui::ComboBox::from_id_salt("conductor_metal_select")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for preset in ConductorPresetConfig::ALL {
+                            ui.selectable_value(
+                                metal,
+                                ConductorMetalConfig::Preset(preset),
+                                preset.name(),
+                            );
+                        }
+                        if ui
+                            .selectable_label(
+                                matches!(metal, ConductorMetalConfig::Custom { .. }),
+                                "Custom",
+                            )
+                            .clicked()
+                            && !matches!(metal, ConductorMetalConfig::Custom { .. })
+                        {
+                            *metal = ConductorMetalConfig::Custom {
+                                eta: Vec3::new(0.2, 0.2, 0.2),
+                                k: Vec3::new(3.0, 3.0, 3.0),
+                            };
+                        }
+                    });
+                ui.end_row();
+
+                if let ConductorMetalConfig::Custom { eta, k } = metal {
+                    ui.label("Eta:");
+                    widgets::vector_input(ui, eta);
+                    ui.end_row();
+
+                    ui.label("K:");
+                    widgets::vector_input(ui, k);
+                    ui.end_row();
+                }
+
+                ui.label("Roughness:");
+                ui.add(egui::Slider::new(roughness, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Anisotropy:");
+                ui.add(egui::Slider::new(anisotropy, 0.0..=1.0));
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
+            }
+            MaterialConfig::NormalVisualization { name, maps } => {
                 ui.label("Type:");
                 ui.label("Normal visualizer");
                 ui.end_row();
@@ -113,8 +652,18 @@ pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
                 ui.label("Name:");
                 ui.text_edit_singleline(name);
                 ui.end_row();
+
+                surface_maps(ui, maps, textures);
             }
-            MaterialConfig::Dielectric { name, ior } => {
+            MaterialConfig::Dielectric {
+                name,
+                ior,
+                roughness,
+                absorption_color,
+                absorption_distance,
+                thin_walled,
+                maps,
+            } => {
                 ui.label("Type:");
                 ui.label("Dielectric");
                 ui.end_row();
@@ -125,6 +674,29 @@ pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
 
                 ui.label("Index of Refraction:");
                 ui.add(egui::Slider::new(ior, 1.0..=2.5));
+                ui.end_row();
+
+                ui.label("Roughness:");
+                ui.add(egui::Slider::new(roughness, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Absorption color:");
+                widgets::color_input(ui, absorption_color);
+                ui.end_row();
+
+                ui.label("Absorption distance:");
+                ui.add(
+                    egui::DragValue::new(absorption_distance)
+                        .range(0.001..=1e9)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("Thin walled:");
+                ui.checkbox(thin_walled, "");
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
             }
             MaterialConfig::DiffuseLight {
                 name,
@@ -140,12 +712,151 @@ pub fn material(ui: &mut egui::Ui, mat: &mut MaterialConfig) {
                 ui.end_row();
 
                 ui.label("Albedo:");
-                widgets::color_input(ui, albedo);
+                widgets::color_source_input(ui, "albedo_source", albedo, textures);
                 ui.end_row();
 
                 ui.label("Strength:");
                 ui.add(egui::DragValue::new(strength).range(0.0..=100.0).speed(0.1));
             }
+            MaterialConfig::Mix {
+                name,
+                first,
+                second,
+                factor,
+            } => {
+                ui.label("Type:");
+                ui.label("Mix");
+                ui.end_row();
+
+                ui.label("Name:");
+                ui.text_edit_singleline(name);
+                ui.end_row();
+
+                ui.label("First:");
+                material_reference_input(ui, "mix_first_select", first, material_names);
+                ui.end_row();
+
+                ui.label("Second:");
+                material_reference_input(ui, "mix_second_select", second, material_names);
+                ui.end_row();
+
+                ui.label("Factor:");
+                widgets::factor_source_input(ui, "mix_factor_source", factor, textures);
+            }
+            MaterialConfig::Coated {
+                name,
+                base,
+                ior,
+                roughness,
+                tint,
+                maps,
+            } => {
+                ui.label("Type:");
+                ui.label("Coated");
+                ui.end_row();
+
+                ui.label("Name:");
+                ui.text_edit_singleline(name);
+                ui.end_row();
+
+                ui.label("Base:");
+                material_reference_input(ui, "coated_base_select", base, material_names);
+                ui.end_row();
+
+                ui.label("Index of Refraction:");
+                ui.add(egui::Slider::new(ior, 1.0..=2.5));
+                ui.end_row();
+
+                ui.label("Roughness:");
+                ui.add(egui::Slider::new(roughness, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Tint:");
+                widgets::color_input(ui, tint);
+                ui.end_row();
+
+                surface_maps(ui, maps, textures);
+            }
+        });
+}
+
+pub fn light(ui: &mut egui::Ui, light: &mut LightConfig) {
+    egui::Grid::new("light_properties_grid")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Type:");
+            ui.label(light.type_name());
+            ui.end_row();
+
+            match light {
+                LightConfig::Point {
+                    position,
+                    color,
+                    intensity,
+                } => {
+                    ui.label("Position:");
+                    widgets::vector_input(ui, &mut position.0);
+                    ui.end_row();
+
+                    ui.label("Color:");
+                    widgets::color_input(ui, color);
+                    ui.end_row();
+
+                    ui.label("Intensity:");
+                    ui.add(egui::DragValue::new(intensity).range(0.0..=1e6).speed(0.1));
+                    ui.end_row();
+                }
+                LightConfig::Spot {
+                    position,
+                    direction,
+                    color,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    ui.label("Position:");
+                    widgets::vector_input(ui, &mut position.0);
+                    ui.end_row();
+
+                    ui.label("Direction:");
+                    widgets::vector_input(ui, direction);
+                    ui.end_row();
+
+                    ui.label("Color:");
+                    widgets::color_input(ui, color);
+                    ui.end_row();
+
+                    ui.label("Intensity:");
+                    ui.add(egui::DragValue::new(intensity).range(0.0..=1e6).speed(0.1));
+                    ui.end_row();
+
+                    ui.label("Inner Angle:");
+                    ui.add(egui::Slider::new(inner_angle, 0.0..=90.0));
+                    ui.end_row();
+
+                    ui.label("Outer Angle:");
+                    ui.add(egui::Slider::new(outer_angle, 0.0..=90.0));
+                    ui.end_row();
+                }
+                LightConfig::Directional {
+                    direction,
+                    color,
+                    intensity,
+                } => {
+                    ui.label("Direction:");
+                    widgets::vector_input(ui, direction);
+                    ui.end_row();
+
+                    ui.label("Color:");
+                    widgets::color_input(ui, color);
+                    ui.end_row();
+
+                    ui.label("Intensity:");
+                    ui.add(egui::DragValue::new(intensity).range(0.0..=1e6).speed(0.1));
+                    ui.end_row();
+                }
+            }
         });
 }
 
@@ -222,6 +933,8 @@ pub fn sky(ui: &mut egui::Ui, sky: &mut crate::config::SkyConfig) {
                 .selected_text(match sky {
                     crate::config::SkyConfig::LinearGradient { .. } => "Linear Gradient",
                     crate::config::SkyConfig::Solid { .. } => "Solid Color",
+                    crate::config::SkyConfig::EnvironmentMap { .. } => "Environment Map",
+                    crate::config::SkyConfig::Physical { .. } => "Physical",
                 })
                 .show_ui(ui, |ui| {
                     ui.selectable_value(
@@ -239,6 +952,26 @@ pub fn sky(ui: &mut egui::Ui, sky: &mut crate::config::SkyConfig) {
                         },
                         "Solid Color",
                     );
+                    ui.selectable_value(
+                        sky,
+                        crate::config::SkyConfig::EnvironmentMap {
+                            path: PathBuf::new(),
+                            rotation: 0.0,
+                            intensity: 1.0,
+                        },
+                        "Environment Map",
+                    );
+                    ui.selectable_value(
+                        sky,
+                        crate::config::SkyConfig::Physical {
+                            sun_elevation: 45.0,
+                            sun_azimuth: 0.0,
+                            turbidity: 3.0,
+                            ground_albedo: crate::math::Vec3::new(0.3, 0.3, 0.3),
+                            sun_disk: true,
+                        },
+                        "Physical",
+                    );
                 });
             ui.end_row();
 
@@ -257,6 +990,59 @@ pub fn sky(ui: &mut egui::Ui, sky: &mut crate::config::SkyConfig) {
                     widgets::color_input(ui, color);
                     ui.end_row();
                 }
+                crate::config::SkyConfig::EnvironmentMap {
+                    path,
+                    rotation,
+                    intensity,
+                } => {
+                    let mut display_path = path.to_string_lossy().to_string();
+                    ui.label("Path:");
+                    ui.text_edit_singleline(&mut display_path);
+                    if ui.button("Browse").clicked()
+                        && let Some(selected_path) = rfd::FileDialog::new()
+                            .add_filter("HDR images", &["hdr", "exr"])
+                            .pick_file()
+                    {
+                        display_path = selected_path.display().to_string();
+                    }
+                    *path = PathBuf::from(display_path);
+                    ui.end_row();
+
+                    ui.label("Rotation:");
+                    ui.add(egui::Slider::new(rotation, -180.0..=180.0));
+                    ui.end_row();
+
+                    ui.label("Intensity:");
+                    ui.add(egui::DragValue::new(intensity).range(0.0..=1e6).speed(0.1));
+                    ui.end_row();
+                }
+                crate::config::SkyConfig::Physical {
+                    sun_elevation,
+                    sun_azimuth,
+                    turbidity,
+                    ground_albedo,
+                    sun_disk,
+                } => {
+                    ui.label("Sun Elevation:");
+                    ui.add(egui::Slider::new(sun_elevation, 0.0..=90.0));
+                    ui.end_row();
+
+                    ui.label("Sun Azimuth:");
+                    ui.add(egui::Slider::new(sun_azimuth, -180.0..=180.0));
+                    ui.end_row();
+
+                    ui.label("Turbidity:");
+                    ui.add(egui::Slider::new(turbidity, 1.7..=10.0));
+                    ui.end_row();
+
+                    ui.label("Ground Albedo:");
+                    widgets::color_input(ui, ground_albedo);
+                    ui.end_row();
+
+                    ui.label("Sun Disk:");
+                    ui.checkbox(sun_disk, "");
+                    ui.end_row();
+                }
             }
         });
 }
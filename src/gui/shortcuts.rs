@@ -60,3 +60,9 @@ impl Shortcuts {
         ctx.input_mut(|i| i.consume_shortcut(&shortcut.shortcut))
     }
 }
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
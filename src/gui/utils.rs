@@ -9,9 +9,13 @@ fn material_label(mat: &MaterialConfig) -> String {
     match mat {
         MaterialConfig::Lambertian { .. } => "lambertian".into(),
         MaterialConfig::Metal { .. } => "metal".into(),
+        MaterialConfig::Principled { .. } => "principled".into(),
+        MaterialConfig::Conductor { .. } => "conductor".into(),
         MaterialConfig::NormalVisualization { .. } => "normal_vis".into(),
         MaterialConfig::Dielectric { .. } => "dielectric".into(),
         MaterialConfig::DiffuseLight { .. } => "diffuse_light".into(),
+        MaterialConfig::Mix { .. } => "mix".into(),
+        MaterialConfig::Coated { .. } => "coated".into(),
     }
 }
 
@@ -30,7 +34,20 @@ pub fn validate_object(obj: &ObjectConfig, materials: &[MaterialConfig]) -> Resu
     let material_name = match obj {
         ObjectConfig::Sphere { material, .. } => material,
         ObjectConfig::Triangle { material, .. } => material,
+        ObjectConfig::Plane { material, .. }
+        | ObjectConfig::Quad { material, .. }
+        | ObjectConfig::Disk { material, .. }
+        | ObjectConfig::Cuboid { material, .. }
+        | ObjectConfig::Cylinder { material, .. }
+        | ObjectConfig::Cone { material, .. }
+        | ObjectConfig::Torus { material, .. }
+        | ObjectConfig::Sdf { material, .. } => material,
+        ObjectConfig::Csg { left, right, .. } => {
+            validate_object(left, materials)?;
+            return validate_object(right, materials);
+        }
         ObjectConfig::Mesh { material, .. } => material,
+        ObjectConfig::Gltf { material, .. } => material,
     };
 
     let material_exists = materials.iter().any(|m| m.name() == *material_name);
@@ -38,12 +55,31 @@ pub fn validate_object(obj: &ObjectConfig, materials: &[MaterialConfig]) -> Resu
         return Err("Material not found".to_string());
     }
 
+    if let ObjectConfig::Mesh {
+        material_override: Some(name),
+        ..
+    }
+    | ObjectConfig::Gltf {
+        material_override: Some(name),
+        ..
+    } = obj
+        && !materials.iter().any(|m| m.name() == *name)
+    {
+        return Err("Override material not found".to_string());
+    }
+
     if let ObjectConfig::Mesh { path, .. } = obj
         && path.as_os_str().is_empty()
     {
         return Err("Mesh path is required".to_string());
     }
 
+    if let ObjectConfig::Gltf { path, .. } = obj
+        && path.as_os_str().is_empty()
+    {
+        return Err("glTF path is required".to_string());
+    }
+
     Ok(())
 }
 
@@ -59,6 +95,12 @@ pub fn validate_material(mat: &MaterialConfig, existing: &[MaterialConfig]) -> R
         return Err("Name must be unique".to_string());
     }
 
+    for referenced in mat.referenced_materials() {
+        if !existing.iter().any(|m| m.name() == referenced) {
+            return Err("Material not found".to_string());
+        }
+    }
+
     Ok(())
 }
 
@@ -73,23 +115,29 @@ pub fn default_config() -> Config {
         renderer: crate::config::RendererConfig {
             samples_per_pixel: 20,
             max_bounces: 10,
+            threads: None,
         },
         image: crate::config::ImageConfig {
             width: 400,
             output: PathBuf::from("output.ppm"),
         },
+        textures: Vec::new(),
         materials: vec![MaterialConfig::Lambertian {
             name: "matte".to_string(),
-            albedo: Vec3::new(0.5, 0.5, 0.5),
+            albedo: Vec3::new(0.5, 0.5, 0.5).into(),
+            maps: SurfaceMapsConfig::default(),
         }],
         objects: vec![ObjectConfig::Sphere {
             position: Vec3::new(0.0, 0.0, -1.0),
             radius: 1.0,
             material: "matte".to_string(),
+            transform: crate::config::TransformConfig::default(),
         }],
+        lights: Vec::new(),
         sky: SkyConfig::LinearGradient {
             from: Vec3::new(1.0, 1.0, 1.0),
             to: Vec3::new(0.5, 0.7, 1.0),
         },
+        bvh: crate::config::BvhConfig::default(),
     }
 }
@@ -1,5 +1,6 @@
 use eframe::egui::{self};
 
+use crate::config::{ColorSource, FactorSource, TextureConfig};
 use crate::math::Vec3;
 
 pub fn panel_heading(ui: &mut egui::Ui, text: &str) {
@@ -31,3 +32,80 @@ pub fn color_input(ui: &mut egui::Ui, color: &mut Vec3) {
         as_f32_array[2] as f64,
     );
 }
+
+/// Edits a color that is either constant or taken from one of the scene's textures.
+pub fn color_source_input(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    source: &mut ColorSource,
+    textures: &[TextureConfig],
+) {
+    ui.horizontal(|ui| {
+        let selected_text = match source {
+            ColorSource::Constant(_) => "Constant".to_string(),
+            ColorSource::Texture { texture } => texture.clone(),
+        };
+        egui::ComboBox::from_id_salt(id_salt)
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(matches!(source, ColorSource::Constant(_)), "Constant")
+                    .clicked()
+                    && !matches!(source, ColorSource::Constant(_))
+                {
+                    *source = ColorSource::Constant(Vec3::new(0.5, 0.5, 0.5));
+                }
+                for texture in textures {
+                    let name = texture.name().to_string();
+                    let is_selected =
+                        matches!(source, ColorSource::Texture { texture } if *texture == name);
+                    if ui.selectable_label(is_selected, &name).clicked() {
+                        *source = ColorSource::Texture { texture: name };
+                    }
+                }
+            });
+
+        if let ColorSource::Constant(color) = source {
+            color_input(ui, color);
+        }
+    });
+}
+
+/// Edits a factor between 0 and 1 that is either constant or taken from the brightness of one
+/// of the scene's textures.
+pub fn factor_source_input(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    source: &mut FactorSource,
+    textures: &[TextureConfig],
+) {
+    ui.horizontal(|ui| {
+        let selected_text = match source {
+            FactorSource::Constant(_) => "Constant".to_string(),
+            FactorSource::Texture { texture } => texture.clone(),
+        };
+        egui::ComboBox::from_id_salt(id_salt)
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(matches!(source, FactorSource::Constant(_)), "Constant")
+                    .clicked()
+                    && !matches!(source, FactorSource::Constant(_))
+                {
+                    *source = FactorSource::Constant(0.5);
+                }
+                for texture in textures {
+                    let name = texture.name().to_string();
+                    let is_selected =
+                        matches!(source, FactorSource::Texture { texture } if *texture == name);
+                    if ui.selectable_label(is_selected, &name).clicked() {
+                        *source = FactorSource::Texture { texture: name };
+                    }
+                }
+            });
+
+        if let FactorSource::Constant(factor) = source {
+            ui.add(egui::Slider::new(factor, 0.0..=1.0));
+        }
+    });
+}
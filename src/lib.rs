@@ -11,24 +11,38 @@ use std::path::Path;
 
 pub use crate::config::*;
 pub use crate::rendering::*;
-pub use crate::world::World;
+pub use crate::world::{GeometryCache, World};
 
 pub fn load_scene_from_config(
     config: &Config,
     asset_base_path: &Path,
 ) -> (Camera, World, Renderer) {
+    load_scene_from_config_with_cache(config, asset_base_path, &mut GeometryCache::new())
+}
+
+/// Like [`load_scene_from_config`], but reuses the meshes that earlier loads left in `cache`.
+pub fn load_scene_from_config_with_cache(
+    config: &Config,
+    asset_base_path: &Path,
+    cache: &mut GeometryCache,
+) -> (Camera, World, Renderer) {
+    let world = World::from_config_with_cache(config, asset_base_path, cache);
+
+    let camera_config = world.imported_camera().unwrap_or(&config.camera);
     let camera = Camera::new(
-        config.camera.aspect_ratio,
+        camera_config.aspect_ratio,
         config.image.width,
-        config.camera.field_of_view,
-        config.camera.position,
-        config.camera.look_at,
+        camera_config.field_of_view,
+        camera_config.position,
+        camera_config.look_at,
     );
-
-    let world = World::from_config(config, asset_base_path);
     let renderer = Renderer::new(
         config.renderer.samples_per_pixel,
         config.renderer.max_bounces,
+        config
+            .renderer
+            .threads
+            .unwrap_or_else(renderer::default_thread_count),
     );
 
     (camera, world, renderer)
@@ -1,16 +1,22 @@
 use std::fs::File;
+use std::ops::ControlFlow;
 use std::path::Path;
+use std::time::Duration;
 use std::{env, process};
 
 use eframe::egui;
 
 use log::error;
 use log::info;
+use yart::image::Image;
+use yart::renderer::ProgressiveLimits;
 use yart::{Config, load_scene_from_config};
 
 fn print_usage() {
     println!("Usage: ");
-    println!("yart <config.toml>");
+    println!(
+        "yart [--threads N] [--progressive] [--time-budget SECONDS] [--bvh-stats] [--bvh-cache DIR] <config.toml>"
+    );
     println!("yart --editor [config.toml]");
 }
 
@@ -38,16 +44,81 @@ fn run_gui(args: &[String]) {
 }
 
 fn run_cli(args: &[String]) {
-    let config_path = args.get(1).unwrap_or_else(|| {
+    let mut config_path = None;
+    let mut threads = None;
+    let mut progressive = false;
+    let mut time_budget = None;
+    let mut bvh_statistics = false;
+    let mut bvh_cache = None;
+
+    let mut remaining = args.iter().skip(1);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--threads" => {
+                let value = remaining
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok());
+                if value.is_none() {
+                    error!("--threads expects a number");
+                    print_usage();
+                    process::exit(1);
+                }
+                threads = value;
+            }
+            "--progressive" => progressive = true,
+            "--bvh-stats" => bvh_statistics = true,
+            "--bvh-cache" => {
+                let Some(directory) = remaining.next() else {
+                    error!("--bvh-cache expects a directory");
+                    print_usage();
+                    process::exit(1);
+                };
+                // Relative to where yart runs rather than to the scene file
+                bvh_cache = std::env::current_dir()
+                    .map(|current| current.join(directory))
+                    .ok();
+            }
+            "--time-budget" => {
+                let value = remaining
+                    .next()
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .filter(|seconds| *seconds >= 0.0);
+                if value.is_none() {
+                    error!("--time-budget expects a number of seconds");
+                    print_usage();
+                    process::exit(1);
+                }
+                time_budget = value.map(Duration::from_secs_f64);
+                progressive = true;
+            }
+            unknown if unknown.starts_with("--") => {
+                error!("unknown option {unknown}");
+                print_usage();
+                process::exit(1);
+            }
+            _ => config_path = Some(arg),
+        }
+    }
+
+    let config_path = config_path.unwrap_or_else(|| {
         error!("no config file supplied");
         print_usage();
         process::exit(1);
     });
 
-    let config = Config::from_path(Path::new(config_path)).unwrap_or_else(|err| {
+    let mut config = Config::from_path(Path::new(config_path)).unwrap_or_else(|err| {
         error!("could not read config: {err}");
         process::exit(1);
     });
+    if threads.is_some() {
+        config.renderer.threads = threads;
+    }
+    if bvh_statistics {
+        config.bvh.statistics = true;
+    }
+    if bvh_cache.is_some() {
+        config.bvh.cache_directory = bvh_cache;
+    }
 
     let mut rng = rand::rng();
     let mut sampler = yart::rendering::sampler::RandomSampler::new(&mut rng);
@@ -55,12 +126,28 @@ fn run_cli(args: &[String]) {
     let asset_base_path = Path::new(&config_path).parent().unwrap();
     let (camera, world, renderer) = load_scene_from_config(&config, asset_base_path);
 
-    let image = renderer.render(&world, &camera, &mut sampler, true);
-    let mut output_file = File::create(&config.image.output).expect("Unable to open output file");
-    image.write_ppm(&mut output_file);
+    if progressive {
+        // Overwrite the output after every pass so a preview is available right away.
+        let limits = ProgressiveLimits {
+            max_passes: Some(config.renderer.samples_per_pixel),
+            time_budget,
+        };
+        renderer.render_progressive(&world, &camera, &mut sampler, &limits, true, |image, _| {
+            write_image(image, &config.image.output);
+            ControlFlow::Continue(())
+        });
+    } else {
+        let image = renderer.render(&world, &camera, &mut sampler, true);
+        write_image(&image, &config.image.output);
+    }
     info!("image written to {:?}", config.image.output);
 }
 
+fn write_image(image: &Image, path: &Path) {
+    let mut output_file = File::create(path).expect("Unable to open output file");
+    image.write_ppm(&mut output_file);
+}
+
 fn main() {
     colog::init();
 
@@ -0,0 +1,207 @@
+use super::geometry::{AABB, Hit, Intersect, Primitive};
+use super::interval::Interval;
+use super::ray::Ray;
+
+/// How far past a surface the search for the next surface of the same operand starts
+const CROSSING_EPSILON: f64 = 1e-7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+    /// Inside either operand
+    Union,
+    /// Inside both operands
+    Intersection,
+    /// Inside the left operand but not the right one
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// The union, intersection or difference of two closed objects. Every surface keeps the
+/// material of the operand it belongs to, so the walls carved out by a difference have the
+/// right operand's material.
+#[derive(Clone)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Primitive,
+    pub right: Primitive,
+    bounding_box: AABB,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Primitive, right: Primitive) -> Self {
+        let (a, b) = (left.bounding_box(), right.bounding_box());
+        let bounding_box = match operation {
+            CsgOperation::Union => AABB::from_boxes(a, b),
+            CsgOperation::Intersection => {
+                let [a, b] = [a.intervals(), b.intervals()];
+                AABB::from_intervals(std::array::from_fn(|axis| {
+                    Interval::new(a[axis].min.max(b[axis].min), a[axis].max.min(b[axis].max))
+                }))
+            }
+            CsgOperation::Difference => a,
+        };
+        Self {
+            operation,
+            left,
+            right,
+            bounding_box,
+        }
+    }
+}
+
+/// The next surface of an operand along the ray, and whether the ray was inside the operand
+/// before reaching it. Without a next surface, the ray is outside from here on.
+fn next_crossing(operand: &Primitive, ray: &Ray, interval: Interval) -> (Option<Hit>, bool) {
+    let hit = operand.intersect(ray, interval);
+    let inside = hit.as_ref().is_some_and(|hit| !hit.front_face);
+    (hit, inside)
+}
+
+impl Intersect for Csg {
+    /// Walks the surfaces of both operands along the ray in order, keeping track of whether
+    /// the ray is inside each of them. The first surface where the ray enters or leaves the
+    /// combined solid is the hit.
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        if !self.bounding_box.hit(ray, interval) {
+            return None;
+        }
+
+        let (mut left_hit, mut in_left) = next_crossing(&self.left, ray, interval);
+        let (mut right_hit, mut in_right) = next_crossing(&self.right, ray, interval);
+        let mut inside = self.operation.contains(in_left, in_right);
+
+        loop {
+            let left_is_next = match (&left_hit, &right_hit) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(left), Some(right)) => left.t <= right.t,
+            };
+
+            let (hit, operand, in_operand) = if left_is_next {
+                (&mut left_hit, &self.left, &mut in_left)
+            } else {
+                (&mut right_hit, &self.right, &mut in_right)
+            };
+            let t = hit.as_ref().map_or(interval.max, |hit| hit.t);
+            *in_operand = !*in_operand;
+
+            let now_inside = self.operation.contains(in_left, in_right);
+            if now_inside != inside {
+                let mut hit = hit.take()?;
+                // The normal already faces the ray, but entering a carved out operand means
+                // leaving the solid
+                hit.front_face = now_inside;
+                return Some(hit);
+            }
+            inside = now_inside;
+
+            let rest = Interval::new(t + CROSSING_EPSILON * t.abs().max(1.0), interval.max);
+            *hit = operand.intersect(ray, rest);
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Cuboid, Point3, Shape, Sphere, Vec3};
+
+    fn sphere(x: f64, radius: f64, material_id: usize) -> Primitive {
+        Primitive::Sphere(Sphere {
+            center: Point3::new(x, 0.0, 0.0),
+            radius,
+            material_id,
+        })
+    }
+
+    /// Where a ray along the x axis from far away enters and leaves `csg`
+    fn crossings(csg: &Csg) -> Vec<(f64, bool, usize)> {
+        let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let mut crossings = Vec::new();
+        let mut min = 0.0;
+        while let Some(hit) = csg.intersect(&ray, Interval::new(min, f64::INFINITY)) {
+            crossings.push((hit.point.0.x, hit.front_face, hit.material_id));
+            min = hit.t + 1e-6;
+        }
+        crossings
+    }
+
+    fn assert_crossings(actual: Vec<(f64, bool, usize)>, expected: &[(f64, bool, usize)]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual.0 - expected.0).abs() < 1e-6, "{actual:?}");
+            assert_eq!((actual.1, actual.2), (expected.1, expected.2));
+        }
+    }
+
+    #[test]
+    fn overlapping_spheres() {
+        // Spheres over [-2, 0] and [-1, 3]
+        let left = sphere(-1.0, 1.0, 1);
+        let right = sphere(1.0, 2.0, 2);
+
+        let union = Csg::new(CsgOperation::Union, left.clone(), right.clone());
+        assert_crossings(crossings(&union), &[(-2.0, true, 1), (3.0, false, 2)]);
+
+        let intersection = Csg::new(CsgOperation::Intersection, left.clone(), right.clone());
+        assert_crossings(
+            crossings(&intersection),
+            &[(-1.0, true, 2), (0.0, false, 1)],
+        );
+
+        let difference = Csg::new(CsgOperation::Difference, left, right);
+        assert_crossings(crossings(&difference), &[(-2.0, true, 1), (-1.0, false, 2)]);
+    }
+
+    #[test]
+    fn hollowed_out_block() {
+        let block = Primitive::Shape(Box::new(Shape::Cuboid(Cuboid::new(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            1,
+        ))));
+        let hollow = Csg::new(CsgOperation::Difference, block, sphere(0.0, 0.5, 2));
+        assert_crossings(
+            crossings(&hollow),
+            &[
+                (-1.0, true, 1),
+                (-0.5, false, 2),
+                (0.5, true, 2),
+                (1.0, false, 1),
+            ],
+        );
+
+        // Starting inside the cavity, the ray first enters the solid at the cavity's wall
+        let ray = Ray::new(Point3::ORIGIN, Vec3::new(0.0, 1.0, 0.0));
+        let hit = hollow
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-6);
+        assert!(hit.front_face);
+        assert!(hit.normal.y < 0.0);
+    }
+
+    #[test]
+    fn disjoint_intersection_is_empty() {
+        let csg = Csg::new(
+            CsgOperation::Intersection,
+            sphere(-3.0, 1.0, 1),
+            sphere(3.0, 1.0, 2),
+        );
+        assert!(crossings(&csg).is_empty());
+    }
+}
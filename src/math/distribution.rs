@@ -0,0 +1,180 @@
+/// A piecewise-constant probability distribution over [0, 1), used to importance sample
+/// tabulated functions such as the brightness of an image.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Builds the distribution from non-negative function values, one per equally sized segment.
+    /// A function that is zero everywhere yields a uniform distribution.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one value");
+
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, value) in func.iter().enumerate() {
+            cdf.push(cdf[i] + value / n as f64);
+        }
+
+        let integral = cdf[n];
+        if integral > 0.0 {
+            for value in cdf.iter_mut() {
+                *value /= integral;
+            }
+        } else {
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / n as f64;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Number of segments
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    /// The integral of the function over [0, 1)
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps the uniform sample `u` in [0, 1) to a sample of the distribution.
+    /// Returns the sample, its density and the index of the segment it lies in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = (offset as f64 + du) / self.len() as f64;
+        (x, self.segment_pdf(offset), offset)
+    }
+
+    /// The density with which `sample` returns `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let offset = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.segment_pdf(offset)
+    }
+
+    fn segment_pdf(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise-constant probability distribution over [0, 1)², sampled by first picking a row
+/// and then a column within it.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Builds the distribution from `width * height` non-negative values in row-major order.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height);
+
+        let rows: Vec<Distribution1D> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        Self { rows, marginal }
+    }
+
+    /// Maps two uniform samples to a point `(u, v)`, where `v` selects the row.
+    /// Returns the point and its density.
+    pub fn sample(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.rows[row].sample(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    /// The density with which `sample` returns the point `(u, v)`.
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.rows[row].pdf(u) * self.marginal.pdf(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn distribution_1d_follows_function() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+
+        assert_eq!(distribution.integral(), 2.0);
+        assert_eq!(distribution.pdf(0.1), 0.5);
+        assert_eq!(distribution.pdf(0.3), 1.5);
+        assert_eq!(distribution.pdf(0.6), 0.0);
+        assert_eq!(distribution.pdf(0.9), 2.0);
+
+        let mut rng = SmallRng::seed_from_u64(1337);
+        let mut counts = [0; 4];
+        for _ in 0..80_000 {
+            let (x, pdf, offset) = distribution.sample(rng.random());
+            assert_eq!(offset, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[offset] += 1;
+        }
+
+        assert_eq!(counts[2], 0);
+        assert!((counts[0] as f64 / 10_000.0 - 1.0).abs() < 0.05);
+        assert!((counts[1] as f64 / 10_000.0 - 3.0).abs() < 0.05);
+        assert!((counts[3] as f64 / 10_000.0 - 4.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn zero_function_samples_uniformly() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+
+        assert_eq!(distribution.pdf(0.7), 1.0);
+        assert!((distribution.sample(0.3).0 - 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distribution_2d_pdf_matches_samples() {
+        let func = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let distribution = Distribution2D::new(&func, 3, 2);
+
+        let mut rng = SmallRng::seed_from_u64(1337);
+        for _ in 0..1000 {
+            let ((u, v), pdf) = distribution.sample(rng.random(), rng.random());
+            assert!(pdf > 0.0);
+            assert!((pdf - distribution.pdf(u, v)).abs() < 1e-12);
+        }
+
+        // The density of each cell is proportional to its value
+        let total: f64 = func.iter().sum();
+        assert!((distribution.pdf(0.9, 0.9) - 5.0 * 6.0 / total).abs() < 1e-12);
+    }
+}
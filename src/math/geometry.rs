@@ -1,22 +1,56 @@
-use std::ops::Range;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::color::Color;
+
+use super::csg::Csg;
 use super::interval::Interval;
 use super::ray::Ray;
+use super::sdf::Sdf;
+use super::shapes::Shape;
+use super::transform::Transform;
 use super::vector::{Normal3, Point3, Vec3};
 
+#[derive(Clone)]
 pub struct Hit {
     pub point: Point3,
+    /// Geometric normal, facing against the ray
     pub normal: Normal3,
+    /// Normal used for shading, e.g. interpolated from vertex normals. Lies on the same side
+    /// of the surface as `normal`.
+    pub shading_normal: Normal3,
     pub t: f64,
     pub front_face: bool,
     pub material_id: usize,
+    /// Index of the hit primitive in the BVH that found the hit, or in the instance's BVH if
+    /// the primitive was reached through an instance
+    pub primitive_id: usize,
+    /// Index of the instance through which the primitive was hit, if any
+    pub instance_id: Option<usize>,
+    /// Texture coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
+    /// How the hit point moves along the surface as `u` and `v` grow, which orients normal
+    /// and bump maps. `None` for surfaces without a texture parameterization to follow.
+    pub tangents: Option<[Vec3; 2]>,
+    /// Vertex color at the hit point, white for primitives without vertex colors
+    pub color: Color,
 }
 
 pub trait Intersect {
     fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit>;
     fn bounding_box(&self) -> AABB;
+
+    /// Returns whether the ray hits anything within the interval.
+    /// Used for shadow rays, where the closest hit is not needed.
+    fn intersects(&self, ray: &Ray, interval: Interval) -> bool {
+        self.intersect(ray, interval).is_some()
+    }
 }
 
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
@@ -57,12 +91,23 @@ impl Intersect for Sphere {
             }
         };
 
+        let (u, v) = sphere_uv(outward_normal.0);
+        let tangents = sphere_tangents(outward_normal.0).map(|t| t.map(|t| t * self.radius));
+        let color = Color::WHITE;
+
         Some(Hit {
             point,
             normal,
+            shading_normal: normal,
             t: root,
             front_face,
             material_id: self.material_id,
+            primitive_id: 0,
+            instance_id: None,
+            u,
+            v,
+            tangents,
+            color,
         })
     }
 
@@ -72,91 +117,402 @@ impl Intersect for Sphere {
     }
 }
 
-//#[derive(Clone)]
+/// Maps a point on the unit sphere to texture coordinates, with `u` going around the y axis
+/// starting at -x, and `v` from the bottom to the top.
+pub(crate) fn sphere_uv(point: Vec3) -> (f64, f64) {
+    let theta = (-point.y).clamp(-1.0, 1.0).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// How a point on the unit sphere moves as the texture coordinates of [`sphere_uv`] grow,
+/// `None` at the poles
+fn sphere_tangents(point: Vec3) -> Option<[Vec3; 2]> {
+    let Vec3 { x, y, z } = point;
+    let ring_radius = (x * x + z * z).sqrt();
+    if ring_radius < 1e-9 {
+        return None;
+    }
+    Some([
+        Vec3::new(z, 0.0, -x) * (2.0 * PI),
+        Vec3::new(-x * y / ring_radius, ring_radius, -y * z / ring_radius) * PI,
+    ])
+}
+
+/// A triangle as loaded from a file, with optional vertex attributes. BVHs store triangles as
+/// [`PackedTriangle`]s instead, see [`BVH::build_triangles`].
+#[derive(Clone)]
 pub struct Triangle {
     pub p1: Point3,
     pub p2: Point3,
     pub p3: Point3,
     pub material_id: usize,
+    /// Vertex normals, interpolated across the triangle for smooth shading
+    pub normals: Option<[Normal3; 3]>,
+    /// Vertex texture coordinates
+    pub uvs: Option<[(f64, f64); 3]>,
+    /// Vertex colors, multiplied with the color of the material
+    pub colors: Option<[Color; 3]>,
+    /// How a point moves as `u` and `v` grow, see [`Triangle::uv_tangents`]
+    pub tangents: Option<[Vec3; 2]>,
+}
+
+impl Triangle {
+    /// Creates a flat shaded triangle without texture coordinates.
+    pub fn new(p1: Point3, p2: Point3, p3: Point3, material_id: usize) -> Self {
+        Self {
+            p1,
+            p2,
+            p3,
+            material_id,
+            normals: None,
+            uvs: None,
+            colors: None,
+            tangents: None,
+        }
+    }
+
+    /// How a point moves across the triangle as its texture coordinates grow. The same for the
+    /// whole triangle, and `None` without texture coordinates or if they don't span an area.
+    pub fn uv_tangents(&self) -> Option<[Vec3; 2]> {
+        let [uv1, uv2, uv3] = self.uvs?;
+        let (edge1, edge2) = (self.p2 - self.p1, self.p3 - self.p1);
+        let (du1, dv1) = (uv2.0 - uv1.0, uv2.1 - uv1.1);
+        let (du2, dv2) = (uv3.0 - uv1.0, uv3.1 - uv1.1);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant == 0.0 {
+            return None;
+        }
+        let tangents = [
+            (edge1 * dv2 - edge2 * dv1) / determinant,
+            (edge2 * du1 - edge1 * du2) / determinant,
+        ];
+        tangents
+            .iter()
+            .all(|t| t.x.is_finite() && t.y.is_finite() && t.z.is_finite())
+            .then_some(tangents)
+    }
 }
 
 impl Intersect for Triangle {
     fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
-        let edge1 = self.p2 - self.p1;
-        let edge2 = self.p3 - self.p1;
+        let vertices = [self.p1, self.p2, self.p3];
+        let (t, weights) = intersect_triangle(vertices, ray, interval)?;
+        let mut hit = triangle_hit(vertices, ray, t, weights, self.material_id);
+        apply_vertex_attributes(&mut hit, self.normals, self.uvs, self.tangents, self.colors);
+        Some(hit)
+    }
 
-        let h = ray.direction.cross(edge2);
-        let a = edge1.dot(h);
+    fn bounding_box(&self) -> AABB {
+        triangle_bounding_box([self.p1, self.p2, self.p3])
+    }
+}
 
-        let eps = 1e-8;
-        // Ray parallel to the triangle
-        if a.abs() < eps {
-            return None;
+/// Watertight ray/triangle intersection after Woop, Benthin and Wald, "Watertight Ray/Triangle
+/// Intersection" (2013). A ray through an edge or vertex shared by several triangles hits at
+/// least one of them. Returns the distance along the ray and the barycentric weights of the
+/// three vertices.
+fn intersect_triangle(
+    vertices: [Point3; 3],
+    ray: &Ray,
+    interval: Interval,
+) -> Option<(f64, [f64; 3])> {
+    // Shear and scale the vertices so that the ray points along +z from the origin
+    let direction = ray.direction;
+    let kz = if direction.x.abs() > direction.y.abs() {
+        if direction.x.abs() > direction.z.abs() {
+            0
+        } else {
+            2
         }
+    } else if direction.y.abs() > direction.z.abs() {
+        1
+    } else {
+        2
+    };
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    // Keep the winding, and with it the sign of the edge functions
+    if direction.axis(kz) < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let sz = 1.0 / direction.axis(kz);
+    let sx = direction.axis(kx) * sz;
+    let sy = direction.axis(ky) * sz;
+
+    let [a, b, c] = vertices.map(|vertex| vertex - ray.origin);
+    let (ax, ay) = (a.axis(kx) - sx * a.axis(kz), a.axis(ky) - sy * a.axis(kz));
+    let (bx, by) = (b.axis(kx) - sx * b.axis(kz), b.axis(ky) - sy * b.axis(kz));
+    let (cx, cy) = (c.axis(kx) - sx * c.axis(kz), c.axis(ky) - sy * c.axis(kz));
+
+    // Edge functions, each the weight of the vertex opposite its edge
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
 
-        let f = 1.0 / a;
-        let s = ray.origin - self.p1;
+    // Zero for rays parallel to the triangle
+    let determinant = u + v + w;
+    if determinant == 0.0 {
+        return None;
+    }
 
-        let u = f * s.dot(h);
-        if u < -eps || u > 1.0 + eps {
-            return None;
-        }
+    let t = (u * a.axis(kz) + v * b.axis(kz) + w * c.axis(kz)) * sz / determinant;
+    if !interval.surrounds(t) {
+        return None;
+    }
 
-        let q = s.cross(edge1);
+    Some((t, [u / determinant, v / determinant, w / determinant]))
+}
 
-        let v = f * ray.direction.dot(q);
-        if v < -eps || u + v > 1.0 + eps {
-            return None;
+/// A flat shaded hit whose `u` and `v` are the barycentric weights of the second and third
+/// vertex, the way [`apply_vertex_attributes`] expects them.
+fn triangle_hit(
+    [p1, p2, p3]: [Point3; 3],
+    ray: &Ray,
+    t: f64,
+    weights: [f64; 3],
+    material_id: usize,
+) -> Hit {
+    let outward_normal = Normal3((p2 - p1).cross(p3 - p1).normalized());
+    let front_face = outward_normal.dot(ray.direction) < 0.0;
+    let normal = if front_face {
+        outward_normal
+    } else {
+        -outward_normal
+    };
+
+    Hit {
+        point: ray.at(t),
+        normal,
+        shading_normal: normal,
+        t,
+        material_id,
+        front_face,
+        primitive_id: 0,
+        instance_id: None,
+        u: weights[1],
+        v: weights[2],
+        tangents: None,
+        color: Color::WHITE,
+    }
+}
+
+/// Interpolates the vertex attributes of a hit made by [`triangle_hit`].
+fn apply_vertex_attributes(
+    hit: &mut Hit,
+    normals: Option<[Normal3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    tangents: Option<[Vec3; 2]>,
+    colors: Option<[Color; 3]>,
+) {
+    let weights = [1.0 - hit.u - hit.v, hit.u, hit.v];
+
+    if let Some(normals) = normals {
+        let interpolated =
+            (weights[0] * normals[0].0 + weights[1] * normals[1].0 + weights[2] * normals[2].0)
+                .normalized();
+        hit.shading_normal = if interpolated.dot(hit.normal.0) < 0.0 {
+            Normal3(-interpolated)
+        } else {
+            Normal3(interpolated)
+        };
+    }
+
+    if let Some(uvs) = uvs {
+        hit.u = weights[0] * uvs[0].0 + weights[1] * uvs[1].0 + weights[2] * uvs[2].0;
+        hit.v = weights[0] * uvs[0].1 + weights[1] * uvs[1].1 + weights[2] * uvs[2].1;
+    }
+    hit.tangents = tangents;
+
+    if let Some(colors) = colors {
+        hit.color = colors[0] * weights[0] + colors[1] * weights[1] + colors[2] * weights[2];
+    }
+}
+
+fn triangle_bounding_box([p1, p2, p3]: [Point3; 3]) -> AABB {
+    let padding = 1e-4;
+    let x = Interval::new(
+        p1.0.x.min(p2.0.x).min(p3.0.x),
+        p1.0.x.max(p2.0.x).max(p3.0.x),
+    )
+    .expand(padding);
+
+    let y = Interval::new(
+        p1.0.y.min(p2.0.y).min(p3.0.y),
+        p1.0.y.max(p2.0.y).max(p3.0.y),
+    )
+    .expand(padding);
+
+    let z = Interval::new(
+        p1.0.z.min(p2.0.z).min(p3.0.z),
+        p1.0.z.max(p2.0.z).max(p3.0.z),
+    )
+    .expand(padding);
+
+    AABB { x, y, z }
+}
+
+/// Marks a packed triangle without vertex attributes
+const NO_ATTRIBUTES: u32 = u32::MAX;
+
+/// A triangle as stored in a BVH: single precision vertices and a material, 44 bytes instead
+/// of the hundreds a [`Triangle`] takes. Its vertex attributes, if any, are kept by the BVH
+/// and only interpolated for the closest hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackedTriangle {
+    vertices: [[f32; 3]; 3],
+    material_id: u32,
+    /// Index into the [`VertexAttributes`] of the BVH holding the triangle
+    attributes: u32,
+}
+
+impl PackedTriangle {
+    /// Creates a flat shaded triangle without texture coordinates.
+    pub fn new(p1: Point3, p2: Point3, p3: Point3, material_id: usize) -> Self {
+        let pack = |p: Point3| [p.0.x as f32, p.0.y as f32, p.0.z as f32];
+        Self {
+            vertices: [pack(p1), pack(p2), pack(p3)],
+            material_id: material_id as u32,
+            attributes: NO_ATTRIBUTES,
         }
+    }
 
-        let t = f * edge2.dot(q);
+    pub fn vertices(&self) -> [Point3; 3] {
+        self.vertices
+            .map(|[x, y, z]| Point3::new(x as f64, y as f64, z as f64))
+    }
 
-        if !interval.surrounds(t) {
+    pub fn material_id(&self) -> usize {
+        self.material_id as usize
+    }
+}
+
+impl Intersect for PackedTriangle {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let vertices = self.vertices();
+        let (t, weights) = intersect_triangle(vertices, ray, interval)?;
+        Some(triangle_hit(vertices, ray, t, weights, self.material_id()))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        triangle_bounding_box(self.vertices())
+    }
+}
+
+/// The vertex attributes of a [`PackedTriangle`], in single precision
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttributes {
+    normals: Option<[[f32; 3]; 3]>,
+    uvs: Option<[[f32; 2]; 3]>,
+    colors: Option<[[f32; 3]; 3]>,
+    tangents: Option<[[f32; 3]; 2]>,
+}
+
+impl VertexAttributes {
+    /// `None` if the triangle has no attributes worth storing
+    fn pack(triangle: &Triangle) -> Option<Self> {
+        if triangle.normals.is_none() && triangle.uvs.is_none() && triangle.colors.is_none() {
             return None;
         }
+        Some(Self {
+            normals: triangle
+                .normals
+                .map(|normals| normals.map(|n| [n.0.x as f32, n.0.y as f32, n.0.z as f32])),
+            uvs: triangle
+                .uvs
+                .map(|uvs| uvs.map(|(u, v)| [u as f32, v as f32])),
+            colors: triangle
+                .colors
+                .map(|colors| colors.map(|c| [c.r as f32, c.g as f32, c.b as f32])),
+            tangents: triangle
+                .tangents
+                .map(|tangents| tangents.map(|t| [t.x as f32, t.y as f32, t.z as f32])),
+        })
+    }
 
-        let point = ray.at(t);
-        let outward_normal = Normal3(edge1.cross(edge2).normalized());
+    fn normals(&self) -> Option<[Normal3; 3]> {
+        self.normals
+            .map(|normals| normals.map(|[x, y, z]| Normal3::new(x as f64, y as f64, z as f64)))
+    }
 
-        let mut normal = outward_normal;
-        let front_face = {
-            if outward_normal.dot(ray.direction) < 0.0 {
-                true
-            } else {
-                normal = -outward_normal;
-                false
-            }
-        };
-        Some(Hit {
-            point,
-            normal,
-            t,
-            material_id: self.material_id,
-            front_face,
-        })
+    fn uvs(&self) -> Option<[(f64, f64); 3]> {
+        self.uvs.map(|uvs| uvs.map(|[u, v]| (u as f64, v as f64)))
     }
 
-    fn bounding_box(&self) -> AABB {
-        let padding = 1e-4;
-        let x = Interval::new(
-            self.p1.0.x.min(self.p2.0.x).min(self.p3.0.x),
-            self.p1.0.x.max(self.p2.0.x).max(self.p3.0.x),
-        )
-        .expand(padding);
+    fn colors(&self) -> Option<[Color; 3]> {
+        self.colors
+            .map(|colors| colors.map(|[r, g, b]| Color::new(r as f64, g as f64, b as f64)))
+    }
 
-        let y = Interval::new(
-            self.p1.0.y.min(self.p2.0.y).min(self.p3.0.y),
-            self.p1.0.y.max(self.p2.0.y).max(self.p3.0.y),
-        )
-        .expand(padding);
+    fn tangents(&self) -> Option<[Vec3; 2]> {
+        self.tangents
+            .map(|tangents| tangents.map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64)))
+    }
+}
 
-        let z = Interval::new(
-            self.p1.0.z.min(self.p2.0.z).min(self.p3.0.z),
-            self.p1.0.z.max(self.p2.0.z).max(self.p3.0.z),
-        )
-        .expand(padding);
+/// A placed copy of a shared BVH. Many instances can share the same primitives, each with its
+/// own transformation and optionally its own material.
+#[derive(Clone)]
+pub struct Instance {
+    pub object: Arc<BVH>,
+    pub transform: Transform,
+    /// Replaces the material of every primitive in `object`
+    pub material_override: Option<usize>,
+}
 
-        AABB { x, y, z }
+impl Instance {
+    pub fn new(object: Arc<BVH>, transform: Transform, material_override: Option<usize>) -> Self {
+        Self {
+            object,
+            transform,
+            material_override,
+        }
+    }
+
+    /// Transforms the ray into object space. Returns the ray along with the interval in object
+    /// space and the factor by which distances grow from world to object space.
+    fn object_ray(&self, ray: &Ray, interval: Interval) -> (Ray, Interval, f64) {
+        let inverse = self.transform.inverse();
+        let direction = inverse.vector(ray.direction);
+        let scale = direction.length();
+        let object_ray = Ray::new(inverse.point(ray.origin), direction);
+        let object_interval = Interval::new(interval.min * scale, interval.max * scale);
+        (object_ray, object_interval, scale)
+    }
+}
+
+impl Intersect for Instance {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let (object_ray, object_interval, scale) = self.object_ray(ray, interval);
+        let mut hit = self.object.intersect(&object_ray, object_interval)?;
+
+        hit.t /= scale;
+        hit.point = self.transform.point(hit.point);
+        // The inverse transpose keeps the normals on the side facing against the ray
+        hit.normal = self.transform.normal(hit.normal);
+        hit.shading_normal = self.transform.normal(hit.shading_normal);
+        hit.tangents = hit.tangents.map(|t| t.map(|t| self.transform.vector(t)));
+        if let Some(material_id) = self.material_override {
+            hit.material_id = material_id;
+        }
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let object_box = self.object.bounding_box();
+        let mut bounding_box = AABB::new();
+        for corner in object_box.corners() {
+            let corner = self.transform.point(corner);
+            bounding_box = AABB::from_boxes(bounding_box, AABB::from_extrema(corner, corner));
+        }
+        bounding_box
+    }
+
+    fn intersects(&self, ray: &Ray, interval: Interval) -> bool {
+        let (object_ray, object_interval, _) = self.object_ray(ray, interval);
+        self.object.intersects(&object_ray, object_interval)
     }
 }
 
@@ -179,7 +535,7 @@ impl AABB {
 
     /// Creates an AABB from two opposite corner points.
     /// The points need not be in any particular order (min/max are computed automatically).
-    fn from_extrema(a: Point3, b: Point3) -> Self {
+    pub(crate) fn from_extrema(a: Point3, b: Point3) -> Self {
         let x = {
             if a.0.x <= b.0.x {
                 Interval::new(a.0.x, b.0.x)
@@ -227,6 +583,40 @@ impl AABB {
         Self { x, y, z }
     }
 
+    fn corners(&self) -> [Point3; 8] {
+        std::array::from_fn(|i| {
+            Point3::new(
+                if i & 1 == 0 { self.x.min } else { self.x.max },
+                if i & 2 == 0 { self.y.min } else { self.y.max },
+                if i & 4 == 0 { self.z.min } else { self.z.max },
+            )
+        })
+    }
+
+    pub(crate) fn from_intervals([x, y, z]: [Interval; 3]) -> Self {
+        Self { x, y, z }
+    }
+
+    pub(crate) fn intervals(&self) -> [Interval; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    fn centroid(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    /// Surface area of the box, zero for an empty box.
+    pub fn surface_area(&self) -> f64 {
+        let x = self.x.size().max(0.0);
+        let y = self.y.size().max(0.0);
+        let z = self.z.size().max(0.0);
+        2.0 * (x * y + y * z + z * x)
+    }
+
     fn axis_interval(&self, n: u32) -> Interval {
         match n {
             1 => self.y,
@@ -277,9 +667,22 @@ impl AABB {
     }
 }
 
+impl Default for AABB {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The contents of a BVH. Kept small since large meshes hold millions of them: triangles are
+/// packed and the rarer shapes and instances are boxed.
+#[derive(Clone)]
 pub enum Primitive {
     Sphere(Sphere),
-    Triangle(Triangle),
+    Triangle(PackedTriangle),
+    Shape(Box<Shape>),
+    Csg(Box<Csg>),
+    Sdf(Box<Sdf>),
+    Instance(Box<Instance>),
 }
 
 impl Intersect for Primitive {
@@ -287,6 +690,10 @@ impl Intersect for Primitive {
         match &self {
             Primitive::Sphere(sphere) => sphere.intersect(ray, interval),
             Primitive::Triangle(triangle) => triangle.intersect(ray, interval),
+            Primitive::Shape(shape) => shape.intersect(ray, interval),
+            Primitive::Csg(csg) => csg.intersect(ray, interval),
+            Primitive::Sdf(sdf) => sdf.intersect(ray, interval),
+            Primitive::Instance(instance) => instance.intersect(ray, interval),
         }
     }
 
@@ -294,12 +701,23 @@ impl Intersect for Primitive {
         match &self {
             Primitive::Sphere(sphere) => sphere.bounding_box(),
             Primitive::Triangle(triangle) => triangle.bounding_box(),
+            Primitive::Shape(shape) => shape.bounding_box(),
+            Primitive::Csg(csg) => csg.bounding_box(),
+            Primitive::Sdf(sdf) => sdf.bounding_box(),
+            Primitive::Instance(instance) => instance.bounding_box(),
+        }
+    }
+
+    fn intersects(&self, ray: &Ray, interval: Interval) -> bool {
+        match &self {
+            Primitive::Instance(instance) => instance.intersects(ray, interval),
+            _ => self.intersect(ray, interval).is_some(),
         }
     }
 }
 
 #[derive(Clone, Copy)]
-enum BVHNodeKind {
+pub(crate) enum BVHNodeKind {
     Inner { left: u32, right: u32 },
     Leaf { start: u32, count: u32 },
 }
@@ -307,57 +725,211 @@ enum BVHNodeKind {
 #[derive(Clone, Copy)]
 pub struct BVHNode {
     pub bounding_box: AABB,
-    kind: BVHNodeKind,
+    pub(crate) kind: BVHNodeKind,
+}
+
+/// How a BVH node decides where to split its primitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitMethod {
+    /// Sort by bounding box minimum along the longest axis and split in the middle
+    Median,
+    /// Pick the cheapest split according to the surface area heuristic
+    #[default]
+    Sah,
+}
+
+/// Settings for building a BVH
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BVHOptions {
+    pub split_method: SplitMethod,
+    /// Nodes with more primitives than this are always split
+    pub max_leaf_size: usize,
+    /// Cost of testing a ray against a node's bounding box
+    pub traversal_cost: f64,
+    /// Cost of intersecting a ray with a primitive
+    pub intersection_cost: f64,
+    /// Threads to build independent subtrees on. The tree is the same for any thread count.
+    pub threads: usize,
 }
 
+impl BVHOptions {
+    pub fn new() -> Self {
+        Self {
+            split_method: SplitMethod::default(),
+            max_leaf_size: 8,
+            traversal_cost: 1.0,
+            intersection_cost: 2.0,
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+impl Default for BVHOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Summary of a built BVH, useful for tuning [`BVHOptions`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BVHStatistics {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    /// Expected cost of tracing a ray that hits the root, per the surface area heuristic
+    pub sah_cost: f64,
+    /// Number of leaves per size bucket: 0, 1, 2-3, 4-7, ...
+    pub leaf_size_histogram: Vec<usize>,
+    pub build_time: Duration,
+    /// Bytes used by the nodes and primitives
+    pub memory: usize,
+}
+
+impl std::fmt::Display for BVHStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves, depth {}, SAH cost {:.2}, leaf sizes [",
+            self.node_count, self.leaf_count, self.max_depth, self.sah_cost
+        )?;
+        let mut first = true;
+        for (bucket, &leaves) in self.leaf_size_histogram.iter().enumerate() {
+            if leaves == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            match bucket {
+                0 => write!(f, "0: {leaves}")?,
+                1 => write!(f, "1: {leaves}")?,
+                _ => write!(f, "{}-{}: {leaves}", 1 << (bucket - 1), (1 << bucket) - 1)?,
+            }
+        }
+        write!(
+            f,
+            "], built in {:.1?}, {:.1} MiB",
+            self.build_time,
+            self.memory as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// A primitive's bounds while the BVH is being built
+struct BuildItem {
+    index: usize,
+    bounding_box: AABB,
+    centroid: Point3,
+}
+
+/// Number of buckets centroids are sorted into when evaluating SAH splits
+const SAH_BINS: usize = 16;
+/// Deeper trees would overflow the traversal stack
+const MAX_BUILD_DEPTH: u32 = 36;
+/// Smaller subtrees are built on the current thread, spawning would cost more than it saves
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
 pub struct BVH {
     pub nodes: Vec<BVHNode>,
     pub primitives: Vec<Primitive>,
+    /// Vertex attributes of the packed triangles that have any
+    attributes: Vec<VertexAttributes>,
     root: usize,
+    options: BVHOptions,
+    build_time: Duration,
 }
 
 impl BVH {
+    /// Builds the subtree over `items`, which start at index `start` of the final primitive
+    /// order, and returns the index of its root in `nodes`.
     fn build_helper(
-        primitives: &mut Vec<Primitive>,
+        items: &mut [BuildItem],
         nodes: &mut Vec<BVHNode>,
-        range: Range<usize>,
+        start: usize,
         depth: u32,
+        options: &BVHOptions,
+        threads: usize,
     ) -> usize {
-        let count = (range.end - range.start) as u32;
+        let count = items.len();
         let mut bounding_box = AABB::new();
-        for primitive in &primitives[range.clone()] {
-            bounding_box = AABB::from_boxes(bounding_box, primitive.bounding_box());
+        for item in items.iter() {
+            bounding_box = AABB::from_boxes(bounding_box, item.bounding_box);
         }
+
+        let split = if count <= 1 || depth > MAX_BUILD_DEPTH {
+            None
+        } else {
+            match options.split_method {
+                SplitMethod::Median => {
+                    (count > options.max_leaf_size).then(|| median_split(items, &bounding_box))
+                }
+                SplitMethod::Sah => sah_split(items, &bounding_box, options),
+            }
+        };
+
         // Base case
-        if count <= 64 || depth > 36 {
+        let Some(mid) = split else {
             let node_index = nodes.len();
 
             let node = BVHNode {
                 bounding_box,
                 kind: BVHNodeKind::Leaf {
-                    start: range.start as u32,
-                    count,
+                    start: start as u32,
+                    count: count as u32,
                 },
             };
             nodes.push(node);
             return node_index;
-        }
+        };
 
-        let axis = bounding_box.longest_axis();
-        let slice = &mut primitives[range.clone()];
-        slice.sort_by(|a, b| {
-            let a_axis_interval = a.bounding_box().axis_interval(axis);
-            let b_axis_interval = b.bounding_box().axis_interval(axis);
-            a_axis_interval.min.total_cmp(&b_axis_interval.min)
-        });
-        let mid = range.start + (count as usize / 2);
-        let left_node = BVH::build_helper(primitives, nodes, range.start..mid, depth + 1);
-        let right_node = BVH::build_helper(primitives, nodes, mid..range.end, depth + 1);
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let (left_node, right_node) = if threads > 1 && count >= PARALLEL_BUILD_THRESHOLD {
+            // Build the right half on a new thread into its own nodes and append them afterwards
+            let right_threads = threads / 2;
+            let (left_node, (right_nodes, right_root)) = thread::scope(|scope| {
+                let right = scope.spawn(|| {
+                    let mut right_nodes = Vec::new();
+                    let root = BVH::build_helper(
+                        right_items,
+                        &mut right_nodes,
+                        start + mid,
+                        depth + 1,
+                        options,
+                        right_threads,
+                    );
+                    (right_nodes, root)
+                });
+                let left_node = BVH::build_helper(
+                    left_items,
+                    nodes,
+                    start,
+                    depth + 1,
+                    options,
+                    threads - right_threads,
+                );
+                (left_node, right.join().unwrap())
+            });
+
+            let offset = nodes.len();
+            nodes.extend(right_nodes.into_iter().map(|mut node| {
+                if let BVHNodeKind::Inner { left, right } = &mut node.kind {
+                    *left += offset as u32;
+                    *right += offset as u32;
+                }
+                node
+            }));
+            (left_node, offset + right_root)
+        } else {
+            let left_node =
+                BVH::build_helper(left_items, nodes, start, depth + 1, options, threads);
+            let right_node =
+                BVH::build_helper(right_items, nodes, start + mid, depth + 1, options, threads);
+            (left_node, right_node)
+        };
         let node_index = nodes.len();
-        let bounding_box = AABB::from_boxes(
-            nodes[left_node].bounding_box,
-            nodes[right_node].bounding_box,
-        );
 
         let node = BVHNode {
             bounding_box,
@@ -371,16 +943,223 @@ impl BVH {
         node_index
     }
 
-    pub fn build(mut primitives: Vec<Primitive>) -> Self {
+    /// Builds the nodes over primitives with the given bounds and returns them with the root
+    /// index and the order the primitives must be stored in.
+    fn build_nodes(
+        bounding_boxes: impl Iterator<Item = AABB>,
+        options: &BVHOptions,
+    ) -> (Vec<BVHNode>, usize, Vec<usize>) {
+        let mut items: Vec<BuildItem> = bounding_boxes
+            .enumerate()
+            .map(|(index, bounding_box)| BuildItem {
+                index,
+                bounding_box,
+                centroid: bounding_box.centroid(),
+            })
+            .collect();
+
         let mut nodes: Vec<BVHNode> = Vec::new();
-        let count = primitives.len();
-        let index = BVH::build_helper(&mut primitives, &mut nodes, 0..count, 0);
+        let threads = options.threads.max(1);
+        let root = BVH::build_helper(&mut items, &mut nodes, 0, 0, options, threads);
+        let order = items.iter().map(|item| item.index).collect();
+
+        (nodes, root, order)
+    }
+
+    pub fn build(primitives: Vec<Primitive>) -> Self {
+        Self::build_with(primitives, &BVHOptions::default())
+    }
+
+    pub fn build_with(mut primitives: Vec<Primitive>, options: &BVHOptions) -> Self {
+        let start_time = Instant::now();
+        let (mut nodes, root, mut order) =
+            BVH::build_nodes(primitives.iter().map(Primitive::bounding_box), options);
+        // Store the primitives in leaf order
+        permute(&mut primitives, &mut order);
+        nodes.shrink_to_fit();
+        primitives.shrink_to_fit();
 
         BVH {
             primitives,
+            attributes: Vec::new(),
             nodes,
-            root: index,
+            root,
+            options: *options,
+            build_time: start_time.elapsed(),
+        }
+    }
+
+    /// Builds a BVH over a mesh, packing its triangles along with their vertex attributes.
+    pub fn build_triangles(triangles: Vec<Triangle>, options: &BVHOptions) -> Self {
+        let start_time = Instant::now();
+        let (primitives, attributes) = pack_triangles(triangles);
+        let mut bvh = BVH::build_with(primitives, options);
+        bvh.attributes = attributes;
+        bvh.build_time = start_time.elapsed();
+        bvh
+    }
+
+    /// Builds a BVH over a mesh without taking ownership of its triangles. Only the packed
+    /// triangles and their vertex attributes are copied, in leaf order.
+    pub fn build_from_slice(triangles: &[Triangle], options: &BVHOptions) -> Self {
+        let start_time = Instant::now();
+        // The bounds of the packed triangles, whose vertices are rounded to single precision
+        let bounding_boxes = triangles.iter().map(|triangle| {
+            PackedTriangle::new(triangle.p1, triangle.p2, triangle.p3, triangle.material_id)
+                .bounding_box()
+        });
+        let (mut nodes, root, order) = BVH::build_nodes(bounding_boxes, options);
+        nodes.shrink_to_fit();
+
+        let mut attributes = Vec::new();
+        let primitives = order
+            .iter()
+            .map(|&index| pack_triangle(&triangles[index], &mut attributes))
+            .collect();
+        BVH {
+            primitives,
+            attributes,
+            nodes,
+            root,
+            options: *options,
+            build_time: start_time.elapsed(),
+        }
+    }
+
+    /// The triangle at `index` with its vertex attributes, `None` for other primitives.
+    pub fn triangle(&self, index: usize) -> Option<Triangle> {
+        let Primitive::Triangle(packed) = self.primitives.get(index)? else {
+            return None;
+        };
+        let [p1, p2, p3] = packed.vertices();
+        let mut triangle = Triangle::new(p1, p2, p3, packed.material_id());
+        if let Some(attributes) = self.attributes.get(packed.attributes as usize) {
+            triangle.normals = attributes.normals();
+            triangle.uvs = attributes.uvs();
+            triangle.colors = attributes.colors();
+            triangle.tangents = attributes.tangents();
+        }
+        Some(triangle)
+    }
+
+    /// Reassembles the BVH of a mesh from its nodes and its triangles in leaf order, e.g. as
+    /// read back from a file. Returns `None` if the nodes do not form a tree that
+    /// [`BVH::build_triangles`] could have built.
+    pub(crate) fn from_parts(
+        nodes: Vec<BVHNode>,
+        triangles: Vec<Triangle>,
+        root: usize,
+        options: BVHOptions,
+        build_time: Duration,
+    ) -> Option<Self> {
+        let (primitives, attributes) = pack_triangles(triangles);
+
+        // Children are built before their parents, which keeps the tree free of cycles, and
+        // the height must stay within what the traversal stack can hold
+        let mut heights = vec![0; nodes.len()];
+        for (index, node) in nodes.iter().enumerate() {
+            heights[index] = match node.kind {
+                BVHNodeKind::Leaf { start, count } => {
+                    if start as usize + count as usize > primitives.len() {
+                        return None;
+                    }
+                    0
+                }
+                BVHNodeKind::Inner { left, right } => {
+                    let (left, right) = (left as usize, right as usize);
+                    if left >= index || right >= index {
+                        return None;
+                    }
+                    heights[left].max(heights[right]) + 1
+                }
+            };
+        }
+        if heights
+            .get(root)
+            .is_none_or(|&height| height > MAX_BUILD_DEPTH + 1)
+        {
+            return None;
+        }
+
+        Some(BVH {
+            nodes,
+            primitives,
+            attributes,
+            root,
+            options,
+            build_time,
+        })
+    }
+
+    pub(crate) fn root_index(&self) -> usize {
+        self.root
+    }
+
+    pub fn options(&self) -> &BVHOptions {
+        &self.options
+    }
+
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    /// Bytes used by the nodes, primitives and vertex attributes, not counting the BVHs of
+    /// instances
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<BVHNode>()
+            + self.primitives.capacity() * std::mem::size_of::<Primitive>()
+            + self.attributes.capacity() * std::mem::size_of::<VertexAttributes>()
+    }
+
+    pub fn statistics(&self) -> BVHStatistics {
+        let mut statistics = BVHStatistics {
+            node_count: 0,
+            leaf_count: 0,
+            max_depth: 0,
+            sah_cost: 0.0,
+            leaf_size_histogram: Vec::new(),
+            build_time: self.build_time,
+            memory: self.memory_usage(),
+        };
+        let root_area = self.root_node().bounding_box.surface_area();
+
+        let mut stack = vec![(self.root, 0)];
+        while let Some((node_index, depth)) = stack.pop() {
+            let node = &self.nodes[node_index];
+            statistics.node_count += 1;
+            statistics.max_depth = statistics.max_depth.max(depth);
+            // Probability of a ray through the root also hitting this node
+            let probability = if root_area > 0.0 {
+                node.bounding_box.surface_area() / root_area
+            } else {
+                0.0
+            };
+
+            match node.kind {
+                BVHNodeKind::Leaf { count, .. } => {
+                    statistics.leaf_count += 1;
+                    statistics.sah_cost +=
+                        probability * count as f64 * self.options.intersection_cost;
+
+                    let bucket = if count == 0 {
+                        0
+                    } else {
+                        count.ilog2() as usize + 1
+                    };
+                    if statistics.leaf_size_histogram.len() <= bucket {
+                        statistics.leaf_size_histogram.resize(bucket + 1, 0);
+                    }
+                    statistics.leaf_size_histogram[bucket] += 1;
+                }
+                BVHNodeKind::Inner { left, right } => {
+                    statistics.sah_cost += probability * self.options.traversal_cost;
+                    stack.push((left as usize, depth + 1));
+                    stack.push((right as usize, depth + 1));
+                }
+            }
         }
+
+        statistics
     }
 
     fn root_node(&self) -> &BVHNode {
@@ -394,8 +1173,12 @@ impl BVH {
         for i in start..start + count {
             let primitive: &Primitive = &self.primitives[i as usize];
 
-            if let Some(hit) = primitive.intersect(ray, Interval::new(interval.min, closest)) {
+            if let Some(mut hit) = primitive.intersect(ray, Interval::new(interval.min, closest)) {
                 closest = hit.t;
+                match primitive {
+                    Primitive::Instance(_) => hit.instance_id = Some(i as usize),
+                    _ => hit.primitive_id = i as usize,
+                }
                 hit_anything = Some(hit)
             }
         }
@@ -403,6 +1186,137 @@ impl BVH {
     }
 }
 
+/// Packs triangles for a BVH, collecting the vertex attributes of those that have any.
+fn pack_triangles(triangles: Vec<Triangle>) -> (Vec<Primitive>, Vec<VertexAttributes>) {
+    let mut attributes = Vec::new();
+    let primitives = triangles
+        .into_iter()
+        .map(|triangle| pack_triangle(&triangle, &mut attributes))
+        .collect();
+    // Collecting may have reused the much larger allocation of `triangles`
+    (primitives, attributes)
+}
+
+/// Packs a triangle, adding its vertex attributes to `attributes` if it has any.
+fn pack_triangle(triangle: &Triangle, attributes: &mut Vec<VertexAttributes>) -> Primitive {
+    let mut packed =
+        PackedTriangle::new(triangle.p1, triangle.p2, triangle.p3, triangle.material_id);
+    if let Some(vertex_attributes) = VertexAttributes::pack(triangle) {
+        packed.attributes = attributes.len() as u32;
+        attributes.push(vertex_attributes);
+    }
+    Primitive::Triangle(packed)
+}
+
+/// Reorders `values` so that `values[i]` becomes the old `values[order[i]]`, following the
+/// permutation's cycles instead of allocating a second buffer. `order` is used up.
+fn permute<T>(values: &mut [T], order: &mut [usize]) {
+    for i in 0..values.len() {
+        let mut current = i;
+        while order[current] != usize::MAX {
+            let next = order[current];
+            order[current] = usize::MAX;
+            if next == i {
+                break;
+            }
+            values.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+/// Sorts the items along the longest axis and returns the index of the middle.
+fn median_split(items: &mut [BuildItem], bounding_box: &AABB) -> usize {
+    let axis = bounding_box.longest_axis();
+    items.sort_by(|a, b| {
+        let a_axis_interval = a.bounding_box.axis_interval(axis);
+        let b_axis_interval = b.bounding_box.axis_interval(axis);
+        a_axis_interval.min.total_cmp(&b_axis_interval.min)
+    });
+    items.len() / 2
+}
+
+/// The SAH bin a centroid falls into along `axis`.
+fn sah_bin(centroid: Point3, extent: Interval, axis: u32) -> usize {
+    let offset = (centroid.0.axis(axis) - extent.min) / extent.size();
+    ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
+
+/// Partitions the items at the cheapest binned SAH split and returns the index of the first
+/// item on the right, or `None` if the items are cheaper to keep in a single leaf.
+fn sah_split(items: &mut [BuildItem], bounding_box: &AABB, options: &BVHOptions) -> Option<usize> {
+    let count = items.len();
+    let mut centroid_bounds = AABB::new();
+    for item in items.iter() {
+        centroid_bounds = AABB::from_boxes(
+            centroid_bounds,
+            AABB::from_extrema(item.centroid, item.centroid),
+        );
+    }
+
+    let area = bounding_box.surface_area();
+    let mut best: Option<(f64, u32, usize)> = None;
+    for axis in 0..=2 {
+        let extent = centroid_bounds.axis_interval(axis);
+        if extent.size() <= 0.0 {
+            continue;
+        }
+
+        let mut bins = [(0, AABB::new()); SAH_BINS];
+        for item in items.iter() {
+            let bin = &mut bins[sah_bin(item.centroid, extent, axis)];
+            bin.0 += 1;
+            bin.1 = AABB::from_boxes(bin.1, item.bounding_box);
+        }
+
+        // Sweep from the right first so the split costs can be found in a single pass
+        let mut right = [(0, 0.0); SAH_BINS];
+        let (mut right_count, mut right_box) = (0, AABB::new());
+        for boundary in (1..SAH_BINS).rev() {
+            right_count += bins[boundary].0;
+            right_box = AABB::from_boxes(right_box, bins[boundary].1);
+            right[boundary] = (right_count, right_box.surface_area());
+        }
+
+        let (mut left_count, mut left_box) = (0, AABB::new());
+        for boundary in 1..SAH_BINS {
+            left_count += bins[boundary - 1].0;
+            left_box = AABB::from_boxes(left_box, bins[boundary - 1].1);
+            let (right_count, right_area) = right[boundary];
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let cost = options.traversal_cost
+                + options.intersection_cost
+                    * (left_count as f64 * left_box.surface_area()
+                        + right_count as f64 * right_area)
+                    / area;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, boundary));
+            }
+        }
+    }
+
+    let leaf_cost = count as f64 * options.intersection_cost;
+    match best {
+        Some((cost, axis, boundary)) if cost < leaf_cost || count > options.max_leaf_size => {
+            let extent = centroid_bounds.axis_interval(axis);
+            let mut mid = 0;
+            for i in 0..count {
+                if sah_bin(items[i].centroid, extent, axis) < boundary {
+                    items.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            Some(mid)
+        }
+        // All centroids coincide, so every split is as good as any other
+        None if count > options.max_leaf_size => Some(count / 2),
+        _ => None,
+    }
+}
+
 impl Intersect for BVH {
     fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
         if !self.bounding_box().hit(ray, interval) {
@@ -444,12 +1358,61 @@ impl Intersect for BVH {
                 }
             }
         }
+
+        // Only the closest hit is worth interpolating vertex attributes for. Hits through
+        // instances were already shaded by the instance's BVH.
+        if let Some(hit) = &mut hit_anything
+            && hit.instance_id.is_none()
+            && let Primitive::Triangle(triangle) = &self.primitives[hit.primitive_id]
+            && let Some(attributes) = self.attributes.get(triangle.attributes as usize)
+        {
+            apply_vertex_attributes(
+                hit,
+                attributes.normals(),
+                attributes.uvs(),
+                attributes.tangents(),
+                attributes.colors(),
+            );
+        }
         hit_anything
     }
 
     fn bounding_box(&self) -> AABB {
         self.root_node().bounding_box
     }
+
+    fn intersects(&self, ray: &Ray, interval: Interval) -> bool {
+        if !self.bounding_box().hit(ray, interval) {
+            return false;
+        }
+
+        let mut stack = [0; 64];
+        let mut stack_top = 1;
+        stack[0] = self.root;
+        while stack_top > 0 {
+            stack_top -= 1;
+            let node = self.nodes[stack[stack_top]];
+            if !node.bounding_box.hit(ray, interval) {
+                continue;
+            }
+
+            match node.kind {
+                BVHNodeKind::Leaf { start, count } => {
+                    let primitives = &self.primitives[start as usize..(start + count) as usize];
+                    if primitives.iter().any(|p| p.intersects(ray, interval)) {
+                        return true;
+                    }
+                }
+                BVHNodeKind::Inner { left, right } => {
+                    stack[stack_top] = right as usize;
+                    stack_top += 1;
+                    stack[stack_top] = left as usize;
+                    stack_top += 1;
+                }
+            }
+        }
+        false
+    }
 }
 
 #[cfg(test)]
@@ -477,12 +1440,12 @@ mod tests {
     }
 
     fn tri() -> Triangle {
-        Triangle {
-            p1: Point3::new(0.0, 0.0, 0.0),
-            p2: Point3::new(1.0, 0.0, 0.0),
-            p3: Point3::new(0.0, 1.0, 0.0),
-            material_id: 0,
-        }
+        Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            0,
+        )
     }
 
     #[test]
@@ -496,6 +1459,39 @@ mod tests {
         assert!((hit.t - 4.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn sphere_texture_coordinates() {
+        let sphere = unit_sphere(Point3::new(0.0, 0.0, -5.0));
+
+        let ray = Ray::new(Point3::ORIGIN, Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.intersect(&ray, Interval::new(0.0, 1000.0)).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-9);
+        assert!((hit.v - 0.5).abs() < 1e-9);
+
+        let ray = Ray::new(Point3::new(0.0, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = sphere.intersect(&ray, Interval::new(0.0, 1000.0)).unwrap();
+        assert!((hit.v - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sphere_tangents_follow_texture_coordinates() {
+        let epsilon = 1e-6;
+        for point in [Vec3::new(0.6, 0.0, 0.8), Vec3::new(-0.3, 0.5, -0.4)] {
+            let point = point.normalized();
+            let (u, v) = sphere_uv(point);
+            let [dpdu, dpdv] = sphere_tangents(point).unwrap();
+
+            let (moved_u, moved_v) = sphere_uv((point + dpdu * epsilon).normalized());
+            assert!(((moved_u - u) / epsilon - 1.0).abs() < 1e-4);
+            assert!(((moved_v - v) / epsilon).abs() < 1e-4);
+
+            let (moved_u, moved_v) = sphere_uv((point + dpdv * epsilon).normalized());
+            assert!(((moved_u - u) / epsilon).abs() < 1e-4);
+            assert!(((moved_v - v) / epsilon - 1.0).abs() < 1e-4);
+        }
+        assert!(sphere_tangents(Vec3::new(0.0, 1.0, 0.0)).is_none());
+    }
+
     #[test]
     fn sphere_miss() {
         let sphere = unit_sphere(Point3::new(0.0, 0.0, -5.0));
@@ -621,7 +1617,7 @@ mod tests {
 
         let triangle = tri();
 
-        let hit = triangle.intersect(&ray, Interval::new(0.001, std::f64::INFINITY));
+        let hit = triangle.intersect(&ray, Interval::new(0.001, f64::INFINITY));
 
         assert!(hit.is_some());
 
@@ -637,7 +1633,7 @@ mod tests {
 
         let triangle = tri();
 
-        let hit = triangle.intersect(&ray, Interval::new(0.001, std::f64::INFINITY));
+        let hit = triangle.intersect(&ray, Interval::new(0.001, f64::INFINITY));
 
         assert!(hit.is_none())
     }
@@ -648,7 +1644,7 @@ mod tests {
 
         let triangle = tri();
 
-        let hit = triangle.intersect(&ray, Interval::new(0.001, std::f64::INFINITY));
+        let hit = triangle.intersect(&ray, Interval::new(0.001, f64::INFINITY));
 
         assert!(hit.is_none())
     }
@@ -659,7 +1655,7 @@ mod tests {
 
         let triangle = tri();
 
-        let hit = triangle.intersect(&ray, Interval::new(0.001, std::f64::INFINITY));
+        let hit = triangle.intersect(&ray, Interval::new(0.001, f64::INFINITY));
 
         assert!(hit.is_some())
     }
@@ -670,7 +1666,7 @@ mod tests {
 
         let triangle = tri();
 
-        let hit = triangle.intersect(&ray, Interval::new(0.001, std::f64::INFINITY));
+        let hit = triangle.intersect(&ray, Interval::new(0.001, f64::INFINITY));
 
         assert!(hit.is_some())
     }
@@ -682,7 +1678,7 @@ mod tests {
         let triangle = tri();
 
         let rec = triangle
-            .intersect(&ray, Interval::new(0.001, std::f64::INFINITY))
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
             .unwrap();
 
         assert!(rec.front_face);
@@ -695,12 +1691,434 @@ mod tests {
         let triangle = tri();
 
         let rec = triangle
-            .intersect(&ray, Interval::new(0.001, std::f64::INFINITY))
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
             .unwrap();
 
         assert!(!rec.front_face);
     }
 
+    #[test]
+    fn triangle_interpolates_vertex_attributes() {
+        let mut triangle = tri();
+        triangle.normals = Some([
+            Normal3::new(0.0, 0.0, 1.0),
+            Normal3::new(1.0, 0.0, 0.0),
+            Normal3::new(0.0, 1.0, 0.0),
+        ]);
+        triangle.uvs = Some([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+
+        let ray = Ray::new(Point3::new(0.5, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+
+        // Halfway between the first and second vertex
+        let expected = Vec3::new(1.0, 0.0, 1.0).normalized();
+        assert!((rec.shading_normal.0 - expected).length() < 1e-9);
+        assert_eq!(rec.normal, Normal3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.5).abs() < 1e-9);
+        assert!(rec.v.abs() < 1e-9);
+
+        // Seen from behind, the shading normal flips along with the geometric normal
+        let ray = Ray::new(Point3::new(0.5, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.shading_normal.0 + expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn triangle_tangents_follow_texture_coordinates() {
+        // Texture coordinates rotated a quarter turn against the triangle
+        let mut triangle = tri();
+        triangle.uvs = Some([(0.0, 0.0), (0.0, 2.0), (-1.0, 0.0)]);
+        triangle.tangents = triangle.uv_tangents();
+
+        let ray = Ray::new(Point3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let [dpdu, dpdv] = hit.tangents.unwrap();
+        assert!((dpdu - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-9);
+        assert!((dpdv - Vec3::new(0.5, 0.0, 0.0)).length() < 1e-9);
+
+        // All corners at the same texture coordinates
+        triangle.uvs = Some([(0.5, 0.5); 3]);
+        triangle.tangents = triangle.uv_tangents();
+        let hit = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(hit.tangents.is_none());
+    }
+
+    #[test]
+    fn triangle_interpolates_vertex_colors() {
+        let mut triangle = tri();
+        let ray = Ray::new(Point3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let rec = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert_eq!(rec.color, Color::WHITE);
+
+        triangle.colors = Some([
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+        ]);
+        let rec = triangle
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let expected = Color::new(0.5, 0.25, 0.25);
+        assert!((rec.color.r - expected.r).abs() < 1e-9);
+        assert!((rec.color.g - expected.g).abs() < 1e-9);
+        assert!((rec.color.b - expected.b).abs() < 1e-9);
+    }
+
+    #[test]
+    fn instance_transforms_ray_and_hit() {
+        let object = Arc::new(BVH::build(vec![Primitive::Sphere(unit_sphere(
+            Point3::ORIGIN,
+        ))]));
+        // An ellipsoid stretched along z, centered at z = -5
+        let transform = Transform::translation(Vec3::new(0.0, 0.0, -5.0))
+            * Transform::scale(Vec3::new(1.0, 1.0, 2.0));
+        let instance = Instance::new(object, transform, Some(7));
+
+        let ray = Ray::new(Point3::ORIGIN, Vec3::new(0.0, 0.0, -1.0));
+        let rec = instance
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert!((rec.point.0 - Vec3::new(0.0, 0.0, -3.0)).length() < 1e-9);
+        assert!((rec.normal.0 - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(rec.front_face);
+        assert_eq!(rec.material_id, 7);
+
+        // The interval is measured in world space
+        assert!(
+            instance
+                .intersect(&ray, Interval::new(0.001, 2.9))
+                .is_none()
+        );
+        assert!(instance.intersects(&ray, Interval::new(0.001, 3.1)));
+        assert!(!instance.intersects(&ray, Interval::new(0.001, 2.9)));
+    }
+
+    #[test]
+    fn instances_share_primitives() {
+        let object = Arc::new(BVH::build_triangles(vec![tri()], &BVHOptions::new()));
+        let left = Instance::new(
+            object.clone(),
+            Transform::translation(Vec3::new(-2.0, 0.0, 0.0)),
+            None,
+        );
+        let right = Instance::new(
+            object.clone(),
+            Transform::translation(Vec3::new(2.0, 0.0, 0.0))
+                * Transform::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0),
+            None,
+        );
+        let scene = BVH::build(vec![
+            Primitive::Instance(Box::new(left)),
+            Primitive::Instance(Box::new(right)),
+        ]);
+        assert_eq!(Arc::strong_count(&object), 3);
+
+        // The rotated copy covers x in [1, 2]
+        let ray = Ray::new(Point3::new(1.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert_eq!(rec.instance_id, Some(1));
+        assert_eq!(rec.primitive_id, 0);
+        assert_eq!(rec.material_id, 0);
+
+        let ray = Ray::new(Point3::new(-1.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene
+            .intersect(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert_eq!(rec.instance_id, Some(0));
+    }
+
+    /// Small spheres in an uneven layout: a dense cluster next to a sparse row
+    fn scattered_spheres() -> Vec<Primitive> {
+        let mut primitives = Vec::new();
+        for i in 0..200 {
+            let f = i as f64;
+            let center = Point3::new((f * 0.37).sin(), (f * 0.73).cos(), (f * 1.31).sin());
+            primitives.push(Primitive::Sphere(Sphere {
+                center,
+                radius: 0.05,
+                material_id: i,
+            }));
+        }
+        for i in 0..20 {
+            primitives.push(Primitive::Sphere(Sphere {
+                center: Point3::new(10.0 + 5.0 * i as f64, 0.0, 0.0),
+                radius: 0.5,
+                material_id: 200 + i,
+            }));
+        }
+        primitives
+    }
+
+    #[test]
+    fn sah_and_median_find_the_same_hits() {
+        let median = BVH::build_with(
+            scattered_spheres(),
+            &BVHOptions {
+                split_method: SplitMethod::Median,
+                ..BVHOptions::new()
+            },
+        );
+        let sah = BVH::build(scattered_spheres());
+
+        for i in 0..400 {
+            let f = i as f64;
+            let origin = Point3::new(-3.0, (f * 0.11).sin() * 1.2, (f * 0.17).cos() * 1.2);
+            let direction = Vec3::new(1.0, (f * 0.05).sin() * 0.1, (f * 0.07).cos() * 0.1);
+            let ray = Ray::new(origin, direction);
+            let interval = Interval::new(0.001, f64::INFINITY);
+
+            let a = median.intersect(&ray, interval);
+            let b = sah.intersect(&ray, interval);
+            assert_eq!(
+                a.map(|hit| (hit.material_id, hit.t)),
+                b.map(|hit| (hit.material_id, hit.t))
+            );
+        }
+    }
+
+    #[test]
+    fn sah_lowers_the_expected_cost() {
+        let options = BVHOptions::new();
+        let median = BVH::build_with(
+            scattered_spheres(),
+            &BVHOptions {
+                split_method: SplitMethod::Median,
+                ..options
+            },
+        )
+        .statistics();
+        let sah = BVH::build_with(scattered_spheres(), &options).statistics();
+
+        assert!(sah.sah_cost < median.sah_cost, "{sah} vs {median}");
+        // No bucket beyond 8-15 primitives
+        assert!(sah.leaf_size_histogram.len() <= 5, "{sah}");
+        let leaves: usize = sah.leaf_size_histogram.iter().sum();
+        assert_eq!(leaves, sah.leaf_count);
+        assert_eq!(sah.node_count, 2 * sah.leaf_count - 1);
+    }
+
+    #[test]
+    fn bvh_statistics() {
+        let single = BVH::build(vec![Primitive::Sphere(unit_sphere(Point3::new(
+            0.0, 0.0, 0.0,
+        )))]);
+        let statistics = single.statistics();
+        assert_eq!(statistics.node_count, 1);
+        assert_eq!(statistics.leaf_count, 1);
+        assert_eq!(statistics.max_depth, 0);
+        assert_eq!(statistics.leaf_size_histogram, vec![0, 1]);
+        assert_eq!(statistics.sah_cost, BVHOptions::new().intersection_cost);
+
+        // Two far apart spheres are worth splitting even though they would fit a leaf
+        let pair = BVH::build(vec![
+            Primitive::Sphere(unit_sphere(Point3::new(-10.0, 0.0, 0.0))),
+            Primitive::Sphere(unit_sphere(Point3::new(10.0, 0.0, 0.0))),
+        ]);
+        let statistics = pair.statistics();
+        assert_eq!(statistics.node_count, 3);
+        assert_eq!(statistics.max_depth, 1);
+        assert_eq!(statistics.leaf_size_histogram, vec![0, 2]);
+        assert!(
+            statistics.to_string().starts_with(&format!(
+                "3 nodes, 2 leaves, depth 1, SAH cost {:.2}, leaf sizes [1: 2], built in",
+                statistics.sah_cost
+            )),
+            "{statistics}"
+        );
+        assert_eq!(statistics.memory, pair.memory_usage());
+    }
+
+    #[test]
+    fn parallel_build_matches_serial_build() {
+        let triangles: Vec<Triangle> = (0..20_000)
+            .map(|i| {
+                let f = i as f64;
+                let p = Point3::new((f * 0.37).sin() * 50.0, (f * 0.73).cos() * 50.0, f * 0.01);
+                Triangle::new(
+                    p,
+                    p + Vec3::new(0.3, 0.0, 0.0),
+                    p + Vec3::new(0.0, 0.3, 0.1),
+                    i,
+                )
+            })
+            .collect();
+        let options = |threads| BVHOptions {
+            threads,
+            ..BVHOptions::new()
+        };
+
+        let serial = BVH::build_from_slice(&triangles, &options(1));
+        let parallel = BVH::build_triangles(triangles, &options(8));
+
+        assert_eq!(serial.nodes.len(), parallel.nodes.len());
+        assert_eq!(serial.statistics().sah_cost, parallel.statistics().sah_cost);
+        let material_ids = |bvh: &BVH| -> Vec<usize> {
+            bvh.primitives
+                .iter()
+                .map(|primitive| match primitive {
+                    Primitive::Triangle(triangle) => triangle.material_id(),
+                    _ => unreachable!(),
+                })
+                .collect()
+        };
+        assert_eq!(material_ids(&serial), material_ids(&parallel));
+
+        for i in 0..100 {
+            let f = i as f64;
+            let ray = Ray::new(
+                Point3::new((f * 0.5).sin() * 40.0, (f * 0.9).cos() * 40.0, -10.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            );
+            let interval = Interval::new(0.001, f64::INFINITY);
+            assert_eq!(
+                serial.intersect(&ray, interval).map(|hit| hit.material_id),
+                parallel
+                    .intersect(&ray, interval)
+                    .map(|hit| hit.material_id)
+            );
+        }
+    }
+
+    #[test]
+    fn packed_primitives_are_small() {
+        assert_eq!(std::mem::size_of::<PackedTriangle>(), 44);
+        assert!(std::mem::size_of::<Primitive>() <= 48);
+    }
+
+    #[test]
+    fn rays_do_not_slip_through_shared_edges() {
+        // A fan of triangles around the origin, whose edges all meet at its center
+        let n = 7;
+        let corner = |i: usize| {
+            let angle = 2.0 * PI * (i % n) as f64 / n as f64;
+            Point3::new(angle.cos(), angle.sin(), 0.0)
+        };
+        let fan: Vec<Triangle> = (0..n)
+            .map(|i| Triangle::new(Point3::ORIGIN, corner(i), corner(i + 1), 0))
+            .collect();
+        let bvh = BVH::build_triangles(fan, &BVHOptions::new());
+
+        let mut targets = vec![Point3::ORIGIN];
+        for i in 0..n {
+            // Along the shared edges, the outer rim being outside the fan
+            for f in [0.1, 0.37, 0.5, 0.99] {
+                targets.push(Point3(corner(i).0 * f));
+            }
+        }
+        for target in targets {
+            for origin in [
+                Point3::new(0.3, -0.7, 2.0),
+                Point3::new(-1.3, 0.1, -0.9),
+                Point3::new(0.0, 0.0, 5.0),
+            ] {
+                let ray = Ray::new(origin, target - origin);
+                assert!(
+                    bvh.intersect(&ray, Interval::new(0.001, f64::INFINITY))
+                        .is_some(),
+                    "ray from {origin:?} slipped through {target:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn packed_triangles_keep_vertex_attributes() {
+        let mut smooth = tri();
+        smooth.normals = Some([Normal3::new(0.0, 0.0, 1.0); 3]);
+        smooth.uvs = Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        smooth.colors = Some([Color::new(1.0, 0.0, 0.0); 3]);
+        smooth.tangents = smooth.uv_tangents();
+        let mut flat = tri();
+        flat.p1 = Point3::new(5.0, 0.0, 0.0);
+        flat.material_id = 3;
+
+        let bvh = BVH::build_triangles(vec![smooth.clone(), flat], &BVHOptions::new());
+        let ray = Ray::new(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let expected = smooth.intersect(&ray, Interval::new(0.001, f64::INFINITY));
+        let hit = bvh.intersect(&ray, Interval::new(0.001, f64::INFINITY));
+        assert_eq!(
+            hit.map(|hit| (
+                hit.t,
+                hit.u,
+                hit.v,
+                hit.color,
+                hit.shading_normal,
+                hit.tangents
+            )),
+            expected.map(|hit| (
+                hit.t,
+                hit.u,
+                hit.v,
+                hit.color,
+                hit.shading_normal,
+                hit.tangents
+            ))
+        );
+
+        let unpacked: Vec<Triangle> = (0..2).filter_map(|i| bvh.triangle(i)).collect();
+        let smooth = unpacked.iter().find(|t| t.material_id == 0).unwrap();
+        assert_eq!(smooth.uvs, Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]));
+        assert_eq!(
+            smooth.tangents,
+            Some([Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)])
+        );
+        let flat = unpacked.iter().find(|t| t.material_id == 3).unwrap();
+        assert_eq!(flat.p1, Point3::new(5.0, 0.0, 0.0));
+        assert_eq!(flat.normals, None);
+    }
+
+    #[test]
+    fn slice_build_keeps_vertex_attributes() {
+        let mut smooth = tri();
+        smooth.normals = Some([
+            Normal3::new(0.0, 0.0, 1.0),
+            Normal3::new(1.0, 0.0, 0.0),
+            Normal3::new(0.0, 1.0, 0.0),
+        ]);
+        smooth.uvs = Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        let mut flat = tri();
+        flat.p1 = Point3::new(5.0, 0.0, 0.0);
+        flat.material_id = 3;
+        let triangles = vec![flat, smooth.clone()];
+
+        let bvh = BVH::build_from_slice(&triangles, &BVHOptions::new());
+        let unpacked: Vec<Triangle> = (0..2).filter_map(|i| bvh.triangle(i)).collect();
+        let smooth_copy = unpacked.iter().find(|t| t.material_id == 0).unwrap();
+        assert_eq!(smooth_copy.normals, smooth.normals);
+        assert_eq!(smooth_copy.uvs, smooth.uvs);
+        let flat_copy = unpacked.iter().find(|t| t.material_id == 3).unwrap();
+        assert_eq!(flat_copy.normals, None);
+
+        let ray = Ray::new(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let expected = smooth.intersect(&ray, Interval::new(0.001, f64::INFINITY));
+        let hit = bvh.intersect(&ray, Interval::new(0.001, f64::INFINITY));
+        assert_eq!(
+            hit.map(|hit| (hit.u, hit.v, hit.shading_normal)),
+            expected.map(|hit| (hit.u, hit.v, hit.shading_normal))
+        );
+    }
+
+    #[test]
+    fn permute_follows_cycles() {
+        let mut values = vec!['a', 'b', 'c', 'd', 'e'];
+        permute(&mut values, &mut [3, 0, 4, 1, 2]);
+        assert_eq!(values, vec!['d', 'a', 'e', 'b', 'c']);
+    }
+
     #[test]
     fn aabb_default_constructor() {
         let r#box = AABB::new();
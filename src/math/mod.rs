@@ -2,11 +2,21 @@ pub trait Lerp<T> {
     fn lerp(start: T, end: T, t: f64) -> T;
 }
 
+pub mod csg;
+pub mod distribution;
 pub mod geometry;
 pub mod interval;
 pub mod ray;
+pub mod sdf;
+pub mod shapes;
+pub mod transform;
 pub mod vector;
 
+pub use csg::*;
+pub use distribution::*;
 pub use geometry::*;
 pub use ray::*;
+pub use sdf::*;
+pub use shapes::*;
+pub use transform::*;
 pub use vector::*;
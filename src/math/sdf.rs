@@ -0,0 +1,529 @@
+use super::geometry::{AABB, Hit, Intersect, sphere_uv};
+use super::interval::Interval;
+use super::ray::Ray;
+use super::shapes::surface_hit;
+use super::vector::{Normal3, Point3, Vec3};
+
+/// Sphere tracing stops this close to the surface
+const SURFACE_EPSILON: f64 = 1e-5;
+
+/// Sphere tracing gives up after this many steps, which only rays grazing a surface take
+const MAX_STEPS: usize = 512;
+
+/// Steps are shortened a little, since smooth combinations only roughly bound the distance
+const STEP_SCALE: f64 = 0.9;
+
+/// Offset of the samples that estimate the gradient
+const GRADIENT_EPSILON: f64 = 1e-6;
+
+/// A signed distance function as an expression tree, negative inside the shape. Shapes are
+/// exact distances, combinations bound the distance from below.
+#[derive(Clone, Debug)]
+pub enum SdfNode {
+    Sphere {
+        center: Point3,
+        radius: f64,
+    },
+    Cuboid {
+        center: Point3,
+        half_size: Vec3,
+    },
+    /// A box of the given size whose edges are rounded off with `radius`
+    RoundedBox {
+        center: Point3,
+        half_size: Vec3,
+        radius: f64,
+    },
+    /// A ring around the y axis
+    Torus {
+        center: Point3,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// The points within `radius` of the segment from `a` to `b`
+    Capsule {
+        a: Point3,
+        b: Point3,
+        radius: f64,
+    },
+    /// Blends the shapes where they are closer than `smoothness`, or joins them with a crease
+    /// if it is zero
+    SmoothUnion {
+        smoothness: f64,
+        left: Box<SdfNode>,
+        right: Box<SdfNode>,
+    },
+    /// Cuts `right` out of `left`
+    SmoothSubtraction {
+        smoothness: f64,
+        left: Box<SdfNode>,
+        right: Box<SdfNode>,
+    },
+    SmoothIntersection {
+        smoothness: f64,
+        left: Box<SdfNode>,
+        right: Box<SdfNode>,
+    },
+    /// `count` copies of `shape` along each axis, `period` apart and centered on the origin.
+    /// The shape should fit in one period, otherwise neighboring copies are cut off.
+    Repeat {
+        period: Vec3,
+        count: [u32; 3],
+        shape: Box<SdfNode>,
+    },
+    /// Moves the surface outwards by a sine pattern of the given amplitude and frequency
+    Displace {
+        amplitude: f64,
+        frequency: f64,
+        shape: Box<SdfNode>,
+    },
+}
+
+/// Smooth minimum with a polynomial blend, Quilez, "Smooth Minimum"
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    -smooth_min(-a, -b, k)
+}
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.abs(), v.y.abs(), v.z.abs())
+}
+
+/// Distance from the box with corners at `-half_size` and `half_size`
+fn box_distance(p: Vec3, half_size: Vec3) -> f64 {
+    let q = abs(p) - half_size;
+    let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+    let inside = q.x.max(q.y).max(q.z).min(0.0);
+    outside + inside
+}
+
+fn box_around(center: Point3, extent: Vec3) -> AABB {
+    AABB::from_extrema(center - extent, center + extent)
+}
+
+fn map_box(bounding_box: AABB, f: impl Fn(usize, Interval) -> Interval) -> AABB {
+    let intervals = bounding_box.intervals();
+    AABB::from_intervals(std::array::from_fn(|axis| f(axis, intervals[axis])))
+}
+
+impl SdfNode {
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            SdfNode::Sphere { center, radius } => (p - *center).length() - radius,
+            SdfNode::Cuboid { center, half_size } => box_distance(p - *center, *half_size),
+            SdfNode::RoundedBox {
+                center,
+                half_size,
+                radius,
+            } => {
+                let radius = radius.min(half_size.x).min(half_size.y).min(half_size.z);
+                let inner = *half_size - Vec3::new(radius, radius, radius);
+                box_distance(p - *center, inner) - radius
+            }
+            SdfNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let q = p - *center;
+                let ring = (q.x * q.x + q.z * q.z).sqrt() - major_radius;
+                (ring * ring + q.y * q.y).sqrt() - minor_radius
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let length_squared = ba.length_squared();
+                let h = if length_squared > 0.0 {
+                    (pa.dot(ba) / length_squared).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (pa - ba * h).length() - radius
+            }
+            SdfNode::SmoothUnion {
+                smoothness,
+                left,
+                right,
+            } => smooth_min(left.distance(p), right.distance(p), *smoothness),
+            SdfNode::SmoothSubtraction {
+                smoothness,
+                left,
+                right,
+            } => smooth_max(left.distance(p), -right.distance(p), *smoothness),
+            SdfNode::SmoothIntersection {
+                smoothness,
+                left,
+                right,
+            } => smooth_max(left.distance(p), right.distance(p), *smoothness),
+            SdfNode::Repeat {
+                period,
+                count,
+                shape,
+            } => {
+                // Moves `p` into the cell of the nearest copy
+                let local = |x: f64, period: f64, count: u32| {
+                    if period <= 0.0 || count <= 1 {
+                        return x;
+                    }
+                    let half_span = (count - 1) as f64 / 2.0;
+                    let cell = (x / period + half_span)
+                        .round()
+                        .clamp(0.0, (count - 1) as f64);
+                    x - (cell - half_span) * period
+                };
+                shape.distance(Point3::new(
+                    local(p.0.x, period.x, count[0]),
+                    local(p.0.y, period.y, count[1]),
+                    local(p.0.z, period.z, count[2]),
+                ))
+            }
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                shape,
+            } => {
+                let q = p.0 * *frequency;
+                shape.distance(p) - amplitude * q.x.sin() * q.y.sin() * q.z.sin()
+            }
+        }
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        match self {
+            SdfNode::Sphere { center, radius } => {
+                box_around(*center, Vec3::new(*radius, *radius, *radius))
+            }
+            SdfNode::Cuboid { center, half_size }
+            | SdfNode::RoundedBox {
+                center, half_size, ..
+            } => box_around(*center, abs(*half_size)),
+            SdfNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let extent = major_radius + minor_radius;
+                box_around(*center, Vec3::new(extent, *minor_radius, extent))
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                AABB::from_boxes(box_around(*a, r), box_around(*b, r))
+            }
+            SdfNode::SmoothUnion {
+                smoothness,
+                left,
+                right,
+            } => {
+                // The blend adds at most a quarter of the smoothness
+                let union = AABB::from_boxes(left.bounding_box(), right.bounding_box());
+                map_box(union, |_, interval| {
+                    interval.expand(smoothness.max(0.0) / 2.0)
+                })
+            }
+            SdfNode::SmoothSubtraction { left, .. } => left.bounding_box(),
+            SdfNode::SmoothIntersection { left, right, .. } => {
+                let right = right.bounding_box().intervals();
+                map_box(left.bounding_box(), |axis, interval| {
+                    Interval::new(
+                        interval.min.max(right[axis].min),
+                        interval.max.min(right[axis].max),
+                    )
+                })
+            }
+            SdfNode::Repeat {
+                period,
+                count,
+                shape,
+            } => map_box(shape.bounding_box(), |axis, interval| {
+                let period = period.axis(axis as u32);
+                let count = count[axis];
+                if period <= 0.0 || count <= 1 {
+                    return interval;
+                }
+                interval.expand((count - 1) as f64 * period)
+            }),
+            SdfNode::Displace {
+                amplitude, shape, ..
+            } => map_box(shape.bounding_box(), |_, interval| {
+                interval.expand(2.0 * amplitude.abs())
+            }),
+        }
+    }
+
+    /// How much faster than the distance to the surface the function can change. Sphere
+    /// tracing divides its steps by this.
+    pub fn lipschitz_bound(&self) -> f64 {
+        match self {
+            SdfNode::Sphere { .. }
+            | SdfNode::Cuboid { .. }
+            | SdfNode::RoundedBox { .. }
+            | SdfNode::Torus { .. }
+            | SdfNode::Capsule { .. } => 1.0,
+            SdfNode::SmoothUnion { left, right, .. }
+            | SdfNode::SmoothSubtraction { left, right, .. }
+            | SdfNode::SmoothIntersection { left, right, .. } => {
+                left.lipschitz_bound().max(right.lipschitz_bound())
+            }
+            SdfNode::Repeat { shape, .. } => shape.lipschitz_bound(),
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                shape,
+            } => shape.lipschitz_bound() + (amplitude * frequency).abs() * 3.0_f64.sqrt(),
+        }
+    }
+}
+
+/// An object whose surface is the zero set of a signed distance function, found by sphere
+/// tracing. The whole object has one material.
+#[derive(Clone)]
+pub struct Sdf {
+    pub root: SdfNode,
+    pub material_id: usize,
+    bounding_box: AABB,
+    lipschitz_bound: f64,
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode, material_id: usize) -> Self {
+        Self {
+            bounding_box: root.bounding_box(),
+            lipschitz_bound: root.lipschitz_bound().max(1.0),
+            root,
+            material_id,
+        }
+    }
+
+    /// The normalized gradient of the distance function, estimated from four samples at the
+    /// corners of a tetrahedron
+    fn gradient(&self, p: Point3) -> Vec3 {
+        let gradient = [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ]
+        .into_iter()
+        .fold(Vec3::ZERO, |sum, k| {
+            sum + k * self.root.distance(p + k * GRADIENT_EPSILON)
+        });
+        if gradient.length_squared() > 0.0 {
+            gradient.normalized()
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        }
+    }
+}
+
+/// The part of `interval` in which the ray is inside the box
+fn clip(bounding_box: &AABB, ray: &Ray, interval: Interval) -> Option<Interval> {
+    let mut clipped = interval;
+    for (axis, slab) in bounding_box.intervals().into_iter().enumerate() {
+        let origin = ray.origin.0.axis(axis as u32);
+        let direction = ray.direction.axis(axis as u32);
+        if direction == 0.0 {
+            if !slab.contains(origin) {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (slab.min - origin) / direction;
+        let t1 = (slab.max - origin) / direction;
+        clipped = Interval::new(clipped.min.max(t0.min(t1)), clipped.max.min(t0.max(t1)));
+        if clipped.min > clipped.max {
+            return None;
+        }
+    }
+    Some(clipped)
+}
+
+impl Intersect for Sdf {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let clipped = clip(&self.bounding_box, ray, interval)?;
+
+        // Steps by the distance to the surface, which is safe on either side of it
+        let mut t = clipped.min;
+        for _ in 0..MAX_STEPS {
+            if t > clipped.max {
+                return None;
+            }
+            let distance = self.root.distance(ray.at(t));
+            if distance.abs() < SURFACE_EPSILON {
+                if !interval.surrounds(t) {
+                    return None;
+                }
+                let normal = self.gradient(ray.at(t));
+                let uv = sphere_uv(normal);
+                return Some(surface_hit(ray, t, Normal3(normal), self.material_id, uv));
+            }
+            t += STEP_SCALE * distance.abs() / self.lipschitz_bound;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(x: f64, radius: f64) -> Box<SdfNode> {
+        Box::new(SdfNode::Sphere {
+            center: Point3::new(x, 0.0, 0.0),
+            radius,
+        })
+    }
+
+    fn trace(node: SdfNode, origin: Point3, direction: Vec3) -> Option<Hit> {
+        Sdf::new(node, 0).intersect(
+            &Ray::new(origin, direction),
+            Interval::new(0.001, f64::INFINITY),
+        )
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn traced_sphere_matches_the_analytic_one() {
+        let hit = trace(
+            *sphere(0.0, 1.0),
+            Point3::new(0.3, 0.2, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        let expected_z = (1.0_f64 - 0.3 * 0.3 - 0.2 * 0.2).sqrt();
+        assert_near(hit.t, 5.0 - expected_z);
+        assert_near(hit.normal.x, 0.3);
+        assert_near(hit.normal.y, 0.2);
+        assert!(hit.front_face);
+
+        // From the inside, the surface faces away
+        let hit = trace(*sphere(0.0, 1.0), Point3::ORIGIN, Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert_near(hit.t, 1.0);
+        assert!(!hit.front_face);
+        assert_near(hit.normal.x, -1.0);
+    }
+
+    #[test]
+    fn shapes_are_exact_distances() {
+        let p = Point3::new(3.0, 0.0, 0.0);
+        let shapes = [
+            SdfNode::Cuboid {
+                center: Point3::ORIGIN,
+                half_size: Vec3::new(1.0, 2.0, 3.0),
+            },
+            SdfNode::RoundedBox {
+                center: Point3::ORIGIN,
+                half_size: Vec3::new(1.0, 2.0, 3.0),
+                radius: 0.5,
+            },
+            SdfNode::Torus {
+                center: Point3::ORIGIN,
+                major_radius: 0.5,
+                minor_radius: 0.5,
+            },
+            SdfNode::Capsule {
+                a: Point3::new(0.0, -1.0, 0.0),
+                b: Point3::new(0.0, 1.0, 0.0),
+                radius: 1.0,
+            },
+        ];
+        for shape in shapes {
+            assert_near(shape.distance(p), 2.0);
+        }
+    }
+
+    #[test]
+    fn smooth_combinations() {
+        // Spheres over [-2, 0] and [-0.5, 1.5] along the x axis
+        let union = |smoothness| SdfNode::SmoothUnion {
+            smoothness,
+            left: sphere(-1.0, 1.0),
+            right: sphere(0.5, 1.0),
+        };
+        let origin = Point3::new(-0.25, 5.0, 0.0);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        // The crease between the spheres fills up when blended
+        let sharp = trace(union(0.0), origin, down).unwrap();
+        let smooth = trace(union(0.5), origin, down).unwrap();
+        assert!(smooth.t < sharp.t - 0.05);
+
+        let from_the_left = Point3::new(-5.0, 0.0, 0.0);
+        let right = Vec3::new(1.0, 0.0, 0.0);
+        let subtraction = SdfNode::SmoothSubtraction {
+            smoothness: 0.0,
+            left: sphere(-1.0, 1.0),
+            right: sphere(0.5, 1.0),
+        };
+        let hit = trace(subtraction, Point3::new(5.0, 0.0, 0.0), -right).unwrap();
+        assert_near(hit.t, 5.5);
+
+        let intersection = SdfNode::SmoothIntersection {
+            smoothness: 0.0,
+            left: sphere(-1.0, 1.0),
+            right: sphere(0.5, 1.0),
+        };
+        assert_near(trace(intersection, from_the_left, right).unwrap().t, 4.5);
+    }
+
+    #[test]
+    fn repeated_copies() {
+        let row = SdfNode::Repeat {
+            period: Vec3::new(2.0, 0.0, 0.0),
+            count: [4, 1, 1],
+            shape: sphere(0.0, 0.5),
+        };
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        // Copies at -3, -1, 1 and 3
+        for x in [-3.0, -1.0, 1.0, 3.0] {
+            let hit = trace(row.clone(), Point3::new(x, 5.0, 0.0), down).unwrap();
+            assert_near(hit.t, 4.5);
+        }
+        assert!(trace(row.clone(), Point3::new(0.0, 5.0, 0.0), down).is_none());
+        assert!(trace(row, Point3::new(5.0, 5.0, 0.0), down).is_none());
+    }
+
+    #[test]
+    fn displaced_surfaces_stay_in_their_bounding_box() {
+        let node = SdfNode::Displace {
+            amplitude: 0.2,
+            frequency: 10.0,
+            shape: sphere(0.0, 1.0),
+        };
+        let bounding_box = node.bounding_box();
+        let sdf = Sdf::new(node, 0);
+        for i in 0..50 {
+            let angle = i as f64 * 0.37;
+            let origin = Point3::new(5.0 * angle.cos(), 0.3, 5.0 * angle.sin());
+            let hit = sdf
+                .intersect(
+                    &Ray::new(origin, Point3::ORIGIN - origin),
+                    Interval::new(0.001, f64::INFINITY),
+                )
+                .unwrap();
+            assert!(sdf.root.distance(hit.point).abs() < 1e-4);
+            assert!((hit.point.0.length() - 1.0).abs() <= 0.2 + 1e-4);
+            let inside = bounding_box
+                .intervals()
+                .iter()
+                .zip([hit.point.0.x, hit.point.0.y, hit.point.0.z])
+                .all(|(interval, x)| interval.contains(x));
+            assert!(inside);
+        }
+    }
+}
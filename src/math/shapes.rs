@@ -0,0 +1,987 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+
+use super::geometry::{AABB, Hit, Intersect};
+use super::interval::Interval;
+use super::ray::Ray;
+use super::vector::{Normal3, Point3, Vec3};
+
+/// Flat shapes get a bounding box this thick, the same padding triangles get
+const PADDING: f64 = 1e-4;
+
+/// Below this, the ray is taken to be parallel to a flat shape
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// A shape with a closed-form intersection, stored boxed in BVHs since most of them are larger
+/// than a packed triangle. Infinite [`Plane`]s have no bounding box and are kept out of BVHs.
+#[derive(Clone)]
+pub enum Shape {
+    Quad(Quad),
+    Disk(Disk),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+}
+
+impl Intersect for Shape {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        match self {
+            Shape::Quad(quad) => quad.intersect(ray, interval),
+            Shape::Disk(disk) => disk.intersect(ray, interval),
+            Shape::Cuboid(cuboid) => cuboid.intersect(ray, interval),
+            Shape::Cylinder(cylinder) => cylinder.intersect(ray, interval),
+            Shape::Cone(cone) => cone.intersect(ray, interval),
+            Shape::Torus(torus) => torus.intersect(ray, interval),
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        match self {
+            Shape::Quad(quad) => quad.bounding_box(),
+            Shape::Disk(disk) => disk.bounding_box(),
+            Shape::Cuboid(cuboid) => cuboid.bounding_box(),
+            Shape::Cylinder(cylinder) => cylinder.bounding_box(),
+            Shape::Cone(cone) => cone.bounding_box(),
+            Shape::Torus(torus) => torus.bounding_box(),
+        }
+    }
+}
+
+/// A hit on the side of the surface the ray comes from
+pub(crate) fn surface_hit(
+    ray: &Ray,
+    t: f64,
+    outward_normal: Normal3,
+    material_id: usize,
+    (u, v): (f64, f64),
+) -> Hit {
+    let front_face = outward_normal.dot(ray.direction) < 0.0;
+    let normal = if front_face {
+        outward_normal
+    } else {
+        -outward_normal
+    };
+
+    Hit {
+        point: ray.at(t),
+        normal,
+        shading_normal: normal,
+        t,
+        front_face,
+        material_id,
+        primitive_id: 0,
+        instance_id: None,
+        u,
+        v,
+        tangents: None,
+        color: Color::WHITE,
+    }
+}
+
+/// The angle of `(x, z)` around the y axis, mapped to `[0, 1)`
+fn turn(x: f64, z: f64) -> f64 {
+    let phi = z.atan2(x);
+    if phi < 0.0 {
+        (phi + 2.0 * PI) / (2.0 * PI)
+    } else {
+        phi / (2.0 * PI)
+    }
+}
+
+/// The extent of a disk with unit `normal` and `radius` along each axis
+fn disk_extent(normal: Vec3, radius: f64) -> Vec3 {
+    let extent = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt();
+    Vec3::new(extent(normal.x), extent(normal.y), extent(normal.z))
+}
+
+fn box_around(center: Point3, extent: Vec3) -> AABB {
+    let padding = Vec3::new(PADDING, PADDING, PADDING) / 2.0;
+    AABB::from_extrema(center - extent - padding, center + extent + padding)
+}
+
+/// A coordinate frame whose y axis points along a shape's axis. Shapes with an axis are
+/// intersected in their frame, where the axis is the y axis through the origin.
+#[derive(Clone, Copy)]
+struct Frame {
+    origin: Point3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+}
+
+impl Frame {
+    /// `axis` must be of unit length
+    fn new(origin: Point3, axis: Vec3) -> Self {
+        let (x, z) = axis.orthonormal_basis();
+        // `x`, `z` and `axis` form a right-handed basis in that order, so `z`, `axis` and `x` do
+        Self {
+            origin,
+            x: z,
+            y: axis,
+            z: x,
+        }
+    }
+
+    fn ray(&self, ray: &Ray) -> Ray {
+        let offset = ray.origin - self.origin;
+        Ray::new(
+            Point3::new(offset.dot(self.x), offset.dot(self.y), offset.dot(self.z)),
+            Vec3::new(
+                ray.direction.dot(self.x),
+                ray.direction.dot(self.y),
+                ray.direction.dot(self.z),
+            ),
+        )
+    }
+
+    fn vector(&self, local: Vec3) -> Vec3 {
+        local.x * self.x + local.y * self.y + local.z * self.z
+    }
+}
+
+/// The real roots of `a * t^2 + b * t + c`, in ascending order
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < PARALLEL_EPSILON {
+        if b.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    // Avoids cancellation between `b` and the square root
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// The real roots of the monic cubic `t^3 + a * t^2 + b * t + c`, after Schwarze, "Cubic and
+/// Quartic Roots", Graphics Gems.
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+
+    // Substituting t = y - a / 3 gives y^3 + 3 * p * y + 2 * q
+    let p = (b - a * a / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * a * a * a - a * b / 3.0 + c) / 2.0;
+    let cube_p = p * p * p;
+    let discriminant = q * q + cube_p;
+
+    let roots = if discriminant.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        let phi = (-q / (-cube_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.0).cos(),
+            -t * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+/// The real roots of the monic quartic `t^4 + a * t^3 + b * t^2 + c * t + d` with Ferrari's
+/// method, polished with a few Newton steps since the closed form loses precision.
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+
+    // Substituting t = y - a / 4 gives y^4 + p * y^2 + q * y + r
+    let a2 = a * a;
+    let p = -3.0 / 8.0 * a2 + b;
+    let q = a2 * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * a2 * a2 + a2 * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = Vec::with_capacity(4);
+    if r.abs() < EPSILON {
+        // y * (y^3 + p * y + q)
+        roots.push(0.0);
+        roots.extend(solve_cubic(0.0, p, q));
+    } else {
+        // Any real root of the resolvent cubic splits the quartic into two quadratics
+        let z = solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if u.abs() < EPSILON {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return roots;
+        };
+        let v = if v.abs() < EPSILON {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return roots;
+        };
+        let v = if q < 0.0 { -v } else { v };
+        for (b, c) in [(v, z - u), (-v, z + u)] {
+            if let Some((y0, y1)) = solve_quadratic(1.0, b, c) {
+                roots.extend([y0, y1]);
+            }
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|y| {
+            let mut t = y - a / 4.0;
+            for _ in 0..2 {
+                let value = (((t + a) * t + b) * t + c) * t + d;
+                let slope = ((4.0 * t + 3.0 * a) * t + 2.0 * b) * t + c;
+                if slope == 0.0 {
+                    break;
+                }
+                t -= value / slope;
+            }
+            t
+        })
+        .collect()
+}
+
+/// An infinite plane. Its texture coordinates are distances along two directions in the
+/// plane, so textures repeat every unit.
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Normal3,
+    pub material_id: usize,
+}
+
+impl Plane {
+    /// `normal` need not be of unit length
+    pub fn new(point: Point3, normal: Vec3, material_id: usize) -> Self {
+        Self {
+            point,
+            normal: Normal3(normal.normalized()),
+            material_id,
+        }
+    }
+}
+
+impl Intersect for Plane {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = self.normal.dot(self.point - ray.origin) / denominator;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let (tangent, bitangent) = self.normal.0.orthonormal_basis();
+        let offset = ray.at(t) - self.point;
+        let uv = (offset.dot(tangent), offset.dot(bitangent));
+        Some(surface_hit(ray, t, self.normal, self.material_id, uv))
+    }
+
+    /// Unbounded in every direction
+    fn bounding_box(&self) -> AABB {
+        AABB::from_intervals([Interval::UNIVERSE; 3])
+    }
+}
+
+/// A parallelogram spanned by two edges from one of its corners
+#[derive(Clone)]
+pub struct Quad {
+    pub corner: Point3,
+    pub edge1: Vec3,
+    pub edge2: Vec3,
+    pub material_id: usize,
+    normal: Normal3,
+    /// Maps a point in the plane to its coordinates along the edges
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, edge1: Vec3, edge2: Vec3, material_id: usize) -> Self {
+        let n = edge1.cross(edge2);
+        Self {
+            corner,
+            edge1,
+            edge2,
+            material_id,
+            normal: Normal3(n.normalized()),
+            w: n / n.length_squared(),
+        }
+    }
+}
+
+impl Intersect for Quad {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = self.normal.dot(self.corner - ray.origin) / denominator;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let offset = ray.at(t) - self.corner;
+        let alpha = self.w.dot(offset.cross(self.edge2));
+        let beta = self.w.dot(self.edge1.cross(offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(surface_hit(
+            ray,
+            t,
+            self.normal,
+            self.material_id,
+            (alpha, beta),
+        ))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let corners = [
+            self.corner,
+            self.corner + self.edge1,
+            self.corner + self.edge2,
+            self.corner + self.edge1 + self.edge2,
+        ];
+        let mut bounding_box = AABB::new();
+        for corner in corners {
+            bounding_box = AABB::from_boxes(bounding_box, box_around(corner, Vec3::ZERO));
+        }
+        bounding_box
+    }
+}
+
+/// A flat disk. `u` goes around the center and `v` outwards from it.
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Normal3,
+    pub radius: f64,
+    pub material_id: usize,
+}
+
+impl Disk {
+    /// `normal` need not be of unit length
+    pub fn new(center: Point3, normal: Vec3, radius: f64, material_id: usize) -> Self {
+        Self {
+            center,
+            normal: Normal3(normal.normalized()),
+            radius,
+            material_id,
+        }
+    }
+}
+
+impl Intersect for Disk {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = self.normal.dot(self.center - ray.origin) / denominator;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let offset = ray.at(t) - self.center;
+        let distance_squared = offset.length_squared();
+        if distance_squared > self.radius * self.radius {
+            return None;
+        }
+
+        let (tangent, bitangent) = self.normal.0.orthonormal_basis();
+        let uv = (
+            turn(offset.dot(tangent), offset.dot(bitangent)),
+            distance_squared.sqrt() / self.radius,
+        );
+        Some(surface_hit(ray, t, self.normal, self.material_id, uv))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        box_around(self.center, disk_extent(self.normal.0, self.radius))
+    }
+}
+
+/// An axis aligned box. Rotating the object turns it into an oriented box. Each face is
+/// textured with the whole texture.
+#[derive(Clone)]
+pub struct Cuboid {
+    pub min: Point3,
+    pub max: Point3,
+    pub material_id: usize,
+}
+
+impl Cuboid {
+    /// The corners need not be in any particular order
+    pub fn new(a: Point3, b: Point3, material_id: usize) -> Self {
+        Self {
+            min: Point3::new(a.0.x.min(b.0.x), a.0.y.min(b.0.y), a.0.z.min(b.0.z)),
+            max: Point3::new(a.0.x.max(b.0.x), a.0.y.max(b.0.y), a.0.z.max(b.0.z)),
+            material_id,
+        }
+    }
+}
+
+impl Intersect for Cuboid {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        // The slab method, remembering which faces the ray enters and leaves through
+        let mut near = (f64::NEG_INFINITY, 0, 0.0);
+        let mut far = (f64::INFINITY, 0, 0.0);
+        for axis in 0..=2 {
+            let origin = ray.origin.0.axis(axis);
+            let direction = ray.direction.axis(axis);
+            let (min, max) = (self.min.0.axis(axis), self.max.0.axis(axis));
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (min - origin) / direction;
+            let t1 = (max - origin) / direction;
+            let (entry, exit, sign) = if t0 < t1 {
+                (t0, t1, -1.0)
+            } else {
+                (t1, t0, 1.0)
+            };
+            if entry > near.0 {
+                near = (entry, axis, sign);
+            }
+            if exit < far.0 {
+                far = (exit, axis, -sign);
+            }
+        }
+        if near.0 > far.0 {
+            return None;
+        }
+
+        let (t, axis, sign) = if interval.surrounds(near.0) {
+            near
+        } else if interval.surrounds(far.0) {
+            far
+        } else {
+            return None;
+        };
+
+        let mut outward_normal = Vec3::ZERO;
+        match axis {
+            0 => outward_normal.x = sign,
+            1 => outward_normal.y = sign,
+            _ => outward_normal.z = sign,
+        }
+        let point = ray.at(t);
+        let face_coordinate = |axis: u32| {
+            let (min, max) = (self.min.0.axis(axis), self.max.0.axis(axis));
+            if max > min {
+                ((point.0.axis(axis) - min) / (max - min)).clamp(0.0, 1.0)
+            } else {
+                0.5
+            }
+        };
+        let uv = match axis {
+            0 => (face_coordinate(2), face_coordinate(1)),
+            1 => (face_coordinate(0), face_coordinate(2)),
+            _ => (face_coordinate(0), face_coordinate(1)),
+        };
+        Some(surface_hit(
+            ray,
+            t,
+            Normal3(outward_normal),
+            self.material_id,
+            uv,
+        ))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::from_extrema(self.min, self.max)
+    }
+}
+
+/// A cylinder closed by a disk at either end. On the side, `u` goes around the axis and `v`
+/// from the base to the top. On the caps, `v` goes outwards from the center.
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Point3,
+    pub top: Point3,
+    pub radius: f64,
+    pub material_id: usize,
+    frame: Frame,
+    height: f64,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, top: Point3, radius: f64, material_id: usize) -> Self {
+        let axis = top - base;
+        Self {
+            base,
+            top,
+            radius,
+            material_id,
+            frame: Frame::new(base, axis.normalized()),
+            height: axis.length(),
+        }
+    }
+}
+
+impl Intersect for Cylinder {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let local = self.frame.ray(ray);
+        let (o, d) = (local.origin.0, local.direction);
+        let mut closest: Option<(f64, Vec3, (f64, f64))> = None;
+        let mut consider = |t: f64, normal: Vec3, uv: (f64, f64)| {
+            if interval.surrounds(t) && closest.is_none_or(|(closest, ..)| t < closest) {
+                closest = Some((t, normal, uv));
+            }
+        };
+
+        let a = d.x * d.x + d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.z * d.z);
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+        if a > 0.0
+            && let Some((t0, t1)) = solve_quadratic(a, b, c)
+        {
+            for t in [t0, t1] {
+                let p = local.at(t).0;
+                if (0.0..=self.height).contains(&p.y) {
+                    let normal = Vec3::new(p.x, 0.0, p.z) / self.radius;
+                    consider(t, normal, (turn(p.x, p.z), p.y / self.height));
+                }
+            }
+        }
+
+        if d.y != 0.0 {
+            for (y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (y - o.y) / d.y;
+                let p = local.at(t).0;
+                let distance_squared = p.x * p.x + p.z * p.z;
+                if distance_squared <= self.radius * self.radius {
+                    let uv = (turn(p.x, p.z), distance_squared.sqrt() / self.radius);
+                    consider(t, Vec3::new(0.0, normal_y, 0.0), uv);
+                }
+            }
+        }
+
+        let (t, normal, uv) = closest?;
+        let outward_normal = Normal3(self.frame.vector(normal).normalized());
+        Some(surface_hit(ray, t, outward_normal, self.material_id, uv))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let extent = disk_extent(self.frame.y, self.radius);
+        AABB::from_boxes(box_around(self.base, extent), box_around(self.top, extent))
+    }
+}
+
+/// A cone from a disk at its base to its apex. On the side, `u` goes around the axis and `v`
+/// from the base to the apex. On the base, `v` goes outwards from the center.
+#[derive(Clone)]
+pub struct Cone {
+    pub base: Point3,
+    pub apex: Point3,
+    pub radius: f64,
+    pub material_id: usize,
+    frame: Frame,
+    height: f64,
+}
+
+impl Cone {
+    pub fn new(base: Point3, apex: Point3, radius: f64, material_id: usize) -> Self {
+        let axis = apex - base;
+        Self {
+            base,
+            apex,
+            radius,
+            material_id,
+            frame: Frame::new(base, axis.normalized()),
+            height: axis.length(),
+        }
+    }
+}
+
+impl Intersect for Cone {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let local = self.frame.ray(ray);
+        let (o, d) = (local.origin.0, local.direction);
+        let mut closest: Option<(f64, Vec3, (f64, f64))> = None;
+        let mut consider = |t: f64, normal: Vec3, uv: (f64, f64)| {
+            if interval.surrounds(t) && closest.is_none_or(|(closest, ..)| t < closest) {
+                closest = Some((t, normal, uv));
+            }
+        };
+
+        // The radius shrinks by `k` per unit of height, x^2 + z^2 = (k * (height - y))^2
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let below_apex = self.height - o.y;
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.z * d.z + k2 * below_apex * d.y);
+        let c = o.x * o.x + o.z * o.z - k2 * below_apex * below_apex;
+        if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+            for t in [t0, t1] {
+                let p = local.at(t).0;
+                if (0.0..=self.height).contains(&p.y) {
+                    let normal = Vec3::new(p.x, k2 * (self.height - p.y), p.z);
+                    let normal = if normal.length_squared() > 0.0 {
+                        normal
+                    } else {
+                        Vec3::new(0.0, 1.0, 0.0)
+                    };
+                    consider(t, normal, (turn(p.x, p.z), p.y / self.height));
+                }
+            }
+        }
+
+        if d.y != 0.0 {
+            let t = -o.y / d.y;
+            let p = local.at(t).0;
+            let distance_squared = p.x * p.x + p.z * p.z;
+            if distance_squared <= self.radius * self.radius {
+                let uv = (turn(p.x, p.z), distance_squared.sqrt() / self.radius);
+                consider(t, Vec3::new(0.0, -1.0, 0.0), uv);
+            }
+        }
+
+        let (t, normal, uv) = closest?;
+        let outward_normal = Normal3(self.frame.vector(normal).normalized());
+        Some(surface_hit(ray, t, outward_normal, self.material_id, uv))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let extent = disk_extent(self.frame.y, self.radius);
+        AABB::from_boxes(
+            box_around(self.base, extent),
+            box_around(self.apex, Vec3::ZERO),
+        )
+    }
+}
+
+/// A ring around `axis`, with the tube's center `major_radius` away from the torus' center.
+/// `u` goes around the axis and `v` around the tube.
+#[derive(Clone)]
+pub struct Torus {
+    pub center: Point3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material_id: usize,
+    frame: Frame,
+}
+
+impl Torus {
+    /// `axis` need not be of unit length
+    pub fn new(
+        center: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material_id: usize,
+    ) -> Self {
+        let axis = axis.normalized();
+        Self {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            material_id,
+            frame: Frame::new(center, axis),
+        }
+    }
+}
+
+impl Intersect for Torus {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        let local = self.frame.ray(ray);
+        let scale = local.direction.length();
+        let d = local.direction / scale;
+        let (big, small) = (self.major_radius, self.minor_radius);
+
+        // Starting close to the torus keeps the quartic's coefficients small
+        let start = (-local.origin.0.dot(d) - (big + small)).max(0.0);
+        let o = local.origin.0 + start * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 * R^2 * (x^2 + z^2) along the ray p = o + s * d
+        let n = o.dot(d);
+        let q = o.length_squared() + big * big - small * small;
+        let four_r2 = 4.0 * big * big;
+        let roots = solve_quartic(
+            4.0 * n,
+            4.0 * n * n + 2.0 * q - four_r2 * (d.x * d.x + d.z * d.z),
+            4.0 * n * q - 2.0 * four_r2 * (o.x * d.x + o.z * d.z),
+            q * q - four_r2 * (o.x * o.x + o.z * o.z),
+        );
+
+        let t = roots
+            .into_iter()
+            .map(|s| (start + s) / scale)
+            .filter(|&t| interval.surrounds(t))
+            .min_by(f64::total_cmp)?;
+
+        let p = local.at(t).0;
+        let ring = Vec3::new(p.x, 0.0, p.z);
+        let ring_distance = ring.length();
+        let tube_center = if ring_distance > 0.0 {
+            ring * (big / ring_distance)
+        } else {
+            Vec3::ZERO
+        };
+        let normal = (p - tube_center).normalized();
+        let uv = (turn(p.x, p.z), turn(ring_distance - big, p.y));
+        let outward_normal = Normal3(self.frame.vector(normal));
+        Some(surface_hit(ray, t, outward_normal, self.material_id, uv))
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let extent = disk_extent(self.axis, self.major_radius)
+            + Vec3::new(self.minor_radius, self.minor_radius, self.minor_radius);
+        box_around(self.center, extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(shape: &dyn Intersect, origin: Point3, direction: Vec3) -> Option<Hit> {
+        shape.intersect(
+            &Ray::new(origin, direction),
+            Interval::new(0.001, f64::INFINITY),
+        )
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn shapes() -> Vec<Shape> {
+        let origin = Point3::ORIGIN;
+        let tilted = Vec3::new(0.3, 1.0, -0.2);
+        vec![
+            Shape::Quad(Quad::new(
+                Point3::new(-1.0, -0.5, 0.2),
+                Vec3::new(2.0, 0.3, 0.0),
+                Vec3::new(0.1, 1.0, 0.8),
+                0,
+            )),
+            Shape::Disk(Disk::new(origin, tilted, 1.2, 0)),
+            Shape::Cuboid(Cuboid::new(
+                Point3::new(1.0, 0.5, -0.5),
+                Point3::new(-1.0, -0.7, 0.5),
+                0,
+            )),
+            Shape::Cylinder(Cylinder::new(
+                Point3::new(0.0, -1.0, 0.2),
+                Point3::new(0.4, 1.0, -0.1),
+                0.6,
+                0,
+            )),
+            Shape::Cone(Cone::new(
+                Point3::new(0.0, -1.0, 0.0),
+                Point3::new(-0.3, 1.0, 0.5),
+                0.9,
+                0,
+            )),
+            Shape::Torus(Torus::new(origin, tilted, 1.0, 0.3, 0)),
+        ]
+    }
+
+    #[test]
+    fn hits_lie_on_the_bounding_box_and_face_the_ray() {
+        let sample = |i: usize, j: usize| {
+            let phi = i as f64 * 0.77;
+            let theta = j as f64 * 0.41;
+            Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            )
+        };
+
+        for shape in shapes() {
+            let bounding_box = shape.bounding_box();
+            let mut hits = 0;
+            for i in 0..40 {
+                for j in 1..8 {
+                    let origin = Point3(sample(i, j) * 4.0);
+                    let target = Point3(sample(i + 17, j + 3) * 0.5);
+                    let direction = target - origin;
+                    let Some(hit) = hit(&shape, origin, direction) else {
+                        continue;
+                    };
+                    hits += 1;
+                    let inside = bounding_box
+                        .intervals()
+                        .iter()
+                        .zip([hit.point.0.x, hit.point.0.y, hit.point.0.z])
+                        .all(|(interval, x)| interval.expand(1e-6).contains(x));
+                    assert!(inside, "hit {:?} outside of the bounding box", hit.point);
+                    assert!(hit.normal.dot(direction) <= 0.0);
+                    assert_near(hit.normal.length(), 1.0);
+                    assert!((0.0..=1.0).contains(&hit.u) && (0.0..=1.0).contains(&hit.v));
+                }
+            }
+            assert!(hits > 20, "only {hits} rays hit");
+        }
+    }
+
+    #[test]
+    fn closed_shapes_are_hit_from_inside() {
+        let inside = Point3::new(0.0, -0.2, 0.0);
+        for shape in shapes() {
+            if matches!(shape, Shape::Quad(_) | Shape::Disk(_) | Shape::Torus(_)) {
+                continue;
+            }
+            for direction in [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(-0.3, -1.0, 0.2),
+            ] {
+                let hit = hit(&shape, inside, direction).expect("ray escaped");
+                assert!(!hit.front_face);
+            }
+        }
+    }
+
+    #[test]
+    fn plane() {
+        let plane = Plane::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 3);
+        let hit = hit(
+            &plane,
+            Point3::new(5.0, 1.0, 2.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert_near(hit.t, 2.0);
+        assert_near(hit.normal.y, 1.0);
+        assert!(hit.front_face);
+        assert_eq!(hit.material_id, 3);
+
+        let parallel = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(
+            plane
+                .intersect(&parallel, Interval::new(0.001, f64::INFINITY))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn cuboid_faces() {
+        let cuboid = Cuboid::new(Point3::new(-1.0, -2.0, -3.0), Point3::new(1.0, 2.0, 3.0), 0);
+        let hit = hit(
+            &cuboid,
+            Point3::new(0.5, 0.0, 10.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert_near(hit.t, 7.0);
+        assert_near(hit.normal.z, 1.0);
+        assert_near(hit.u, 0.75);
+        assert_near(hit.v, 0.5);
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let cylinder = Cylinder::new(Point3::ORIGIN, Point3::new(0.0, 2.0, 0.0), 0.5, 0);
+        let side = hit(
+            &cylinder,
+            Point3::new(3.0, 1.5, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert_near(side.t, 2.5);
+        assert_near(side.normal.x, 1.0);
+        assert_near(side.v, 0.75);
+
+        let top = hit(
+            &cylinder,
+            Point3::new(0.2, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert_near(top.t, 3.0);
+        assert_near(top.normal.y, 1.0);
+        assert!(
+            hit(
+                &cylinder,
+                Point3::new(0.6, 5.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0)
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn cone_side_and_base() {
+        let cone = Cone::new(Point3::ORIGIN, Point3::new(0.0, 1.0, 0.0), 1.0, 0);
+        let side = hit(&cone, Point3::new(3.0, 0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert_near(side.t, 2.5);
+        let slope = std::f64::consts::FRAC_1_SQRT_2;
+        assert_near(side.normal.x, slope);
+        assert_near(side.normal.y, slope);
+
+        let base = hit(&cone, Point3::new(0.5, -2.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert_near(base.t, 2.0);
+        assert_near(base.normal.y, -1.0);
+    }
+
+    #[test]
+    fn torus_is_hit_on_the_tube() {
+        let torus = Torus::new(Point3::ORIGIN, Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, 0);
+        // Along the x axis through both sides of the ring
+        let across = hit(
+            &torus,
+            Point3::new(-10.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert_near(across.t, 7.5);
+        assert_near(across.normal.x, -1.0);
+        // Through the hole
+        assert!(
+            hit(
+                &torus,
+                Point3::new(0.0, 5.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0)
+            )
+            .is_none()
+        );
+        // From far away, where the closed form alone loses precision
+        let far = hit(
+            &torus,
+            Point3::new(2.0, 1e4, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert_near(far.t, 1e4 - 0.5);
+        assert_near(far.normal.y, 1.0);
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (t - 1) (t - 2) (t + 3) (t - 0.5)
+        let mut roots = solve_quartic(-0.5, -7.0, 9.5, -3.0);
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.into_iter().zip([-3.0, 0.5, 1.0, 2.0]) {
+            assert_near(root, expected);
+        }
+    }
+}
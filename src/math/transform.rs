@@ -0,0 +1,291 @@
+use std::ops;
+
+use super::{Normal3, Point3, Vec3};
+
+/// A 4x4 matrix in row-major order
+pub type Matrix4 = [[f64; 4]; 4];
+
+const IDENTITY_MATRIX: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// An affine transformation, stored along with its inverse.
+///
+/// `a * b` is the transformation that applies `b` first and then `a`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        matrix: IDENTITY_MATRIX,
+        inverse: IDENTITY_MATRIX,
+    };
+
+    /// Creates a transformation from its matrix. Returns `None` if the matrix is not invertible.
+    pub fn from_matrix(matrix: Matrix4) -> Option<Self> {
+        let inverse = invert(&matrix)?;
+        Some(Self { matrix, inverse })
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = IDENTITY_MATRIX;
+        let mut inverse = IDENTITY_MATRIX;
+        for (i, value) in [offset.x, offset.y, offset.z].into_iter().enumerate() {
+            matrix[i][3] = value;
+            inverse[i][3] = -value;
+        }
+        Self { matrix, inverse }
+    }
+
+    /// Scales along the coordinate axes. All factors must be non-zero.
+    pub fn scale(factors: Vec3) -> Self {
+        let mut matrix = IDENTITY_MATRIX;
+        let mut inverse = IDENTITY_MATRIX;
+        for (i, value) in [factors.x, factors.y, factors.z].into_iter().enumerate() {
+            matrix[i][i] = value;
+            inverse[i][i] = 1.0 / value;
+        }
+        Self { matrix, inverse }
+    }
+
+    /// Rotation by the quaternion `w + xi + yj + zk`, which is normalized first.
+    pub fn rotation_quaternion(x: f64, y: f64, z: f64, w: f64) -> Self {
+        let length = (x * x + y * y + z * z + w * w).sqrt();
+        let (x, y, z, w) = (x / length, y / length, z / length, w / length);
+
+        let matrix = [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        // Rotations are orthogonal
+        Self {
+            matrix,
+            inverse: transpose(&matrix),
+        }
+    }
+
+    /// Rotation by `degrees` counterclockwise around `axis`, looking against the axis.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let axis = axis.normalized();
+        let half_angle = degrees.to_radians() / 2.0;
+        let s = half_angle.sin();
+        Self::rotation_quaternion(axis.x * s, axis.y * s, axis.z * s, half_angle.cos())
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, point: Point3) -> Point3 {
+        let m = &self.matrix;
+        let p = point.0;
+        Point3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    pub fn vector(&self, vector: Vec3) -> Vec3 {
+        multiply_vector(&self.matrix, vector)
+    }
+
+    /// Transforms a surface normal by the inverse transpose, keeping it perpendicular to
+    /// transformed tangents. The result is normalized.
+    pub fn normal(&self, normal: Normal3) -> Normal3 {
+        let n = normal.0;
+        let m = &self.inverse;
+        Normal3(
+            Vec3::new(
+                m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+                m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+                m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+            )
+            .normalized(),
+        )
+    }
+
+    /// Whether the transformation mirrors space, flipping the winding order of triangles.
+    pub fn swaps_handedness(&self) -> bool {
+        let m = &self.matrix;
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        determinant < 0.0
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl ops::Mul<Transform> for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            matrix: multiply(&self.matrix, &rhs.matrix),
+            inverse: multiply(&rhs.inverse, &self.inverse),
+        }
+    }
+}
+
+fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn multiply_vector(m: &Matrix4, v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+fn transpose(m: &Matrix4) -> Matrix4 {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    result
+}
+
+/// Gauss-Jordan elimination with partial pivoting
+fn invert(m: &Matrix4) -> Option<Matrix4> {
+    let mut a = *m;
+    let mut inverse = IDENTITY_MATRIX;
+
+    for column in 0..4 {
+        let pivot =
+            (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+        if a[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = 1.0 / a[column][column];
+        for j in 0..4 {
+            a[column][j] *= scale;
+            inverse[column][j] *= scale;
+        }
+
+        for row in 0..4 {
+            if row == column {
+                continue;
+            }
+            let factor = a[row][column];
+            for j in 0..4 {
+                a[row][j] -= factor * a[column][j];
+                inverse[row][j] -= factor * inverse[column][j];
+            }
+        }
+    }
+
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn rotation_is_counterclockwise() {
+        let rotation = Transform::rotation(Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert_near(
+            rotation.vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_near(
+            rotation.inverse().vector(Vec3::new(0.0, 0.0, -1.0)),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn composition_applies_right_hand_side_first() {
+        let transform = Transform::translation(Vec3::new(1.0, 2.0, 3.0))
+            * Transform::scale(Vec3::new(2.0, 2.0, 2.0));
+        let point = transform.point(Point3::new(1.0, 1.0, 1.0));
+        assert_near(point.0, Vec3::new(3.0, 4.0, 5.0));
+        assert_near(transform.inverse().point(point).0, Vec3::ONES);
+
+        // Vectors are not translated
+        assert_near(transform.vector(Vec3::ONES), Vec3::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn from_matrix_inverts() {
+        let transform = Transform::translation(Vec3::new(-1.0, 0.5, 4.0))
+            * Transform::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Transform::scale(Vec3::new(1.0, 3.0, 0.5));
+        let from_matrix = Transform::from_matrix(*transform.matrix()).unwrap();
+
+        let point = Point3::new(0.3, -2.0, 7.0);
+        let inverted = from_matrix.inverse().point(transform.point(point));
+        assert_near(inverted.0, point.0);
+
+        let mut singular = IDENTITY_MATRIX;
+        singular[2][2] = 0.0;
+        assert!(Transform::from_matrix(singular).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_scaling() {
+        let transform = Transform::scale(Vec3::new(4.0, 1.0, 1.0));
+        // The plane x + y = 0 and one of its tangents
+        let normal = Normal3(Vec3::new(1.0, 1.0, 0.0).normalized());
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+
+        let normal = transform.normal(normal);
+        assert!(normal.0.dot(transform.vector(tangent)).abs() < 1e-9);
+        assert!((normal.length() - 1.0).abs() < 1e-9);
+
+        assert!(!transform.swaps_handedness());
+        assert!(Transform::scale(Vec3::new(-1.0, 1.0, 1.0)).swaps_handedness());
+    }
+}
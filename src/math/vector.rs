@@ -60,6 +60,18 @@ impl Vec3 {
         self.dot(other) < h
     }
 
+    /// Returns two unit vectors that together with this one, which must be of unit length,
+    /// form an orthonormal basis.
+    pub fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        // Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1.0_f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        let u = Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x);
+        let v = Vec3::new(b, sign + self.y * self.y * a, -self.y);
+        (u, v)
+    }
+
     pub fn axis(&self, axis: u32) -> f64 {
         match axis {
             1 => self.y,
@@ -275,6 +287,22 @@ pub mod tests {
         assert!(b.is_orthogonal_to(c));
     }
 
+    #[test]
+    fn test_orthonormal_basis() {
+        for w in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 2.0, 3.0).normalized(),
+        ] {
+            let (u, v) = w.orthonormal_basis();
+            assert!((u.length() - 1.0).abs() < 1e-9);
+            assert!((v.length() - 1.0).abs() < 1e-9);
+            assert!(u.dot(v).abs() < 1e-9);
+            assert!(u.dot(w).abs() < 1e-9);
+            assert!(v.dot(w).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn test_negate() {
         assert_eq!(-Vec3::ZERO, Vec3::ZERO);
@@ -0,0 +1,722 @@
+//! An on-disk cache of mesh BVHs, so that repeat renders of the same asset skip parsing the
+//! mesh and building its BVH.
+//!
+//! A cache file holds the flattened triangles and nodes of one BVH. It is keyed on the mesh
+//! file's path, modification time, length and content hash, and on the BVH options. Materials
+//! are stored by name and resolved again when the file is read, since material IDs depend on the
+//! order a scene registers its materials in.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+
+use log::{error, info, warn};
+
+use crate::color::Color;
+use crate::material::MaterialLibrary;
+use crate::math::interval::Interval;
+use crate::math::{
+    AABB, BVH, BVHNode, BVHNodeKind, BVHOptions, Normal3, Point3, SplitMethod, Triangle,
+};
+
+use super::mtl::load_mtl;
+
+const MAGIC: &[u8; 8] = b"YARTBVH\0";
+/// Bumped whenever the layout of cache files changes
+const VERSION: u32 = 2;
+
+pub struct MeshCache {
+    directory: PathBuf,
+}
+
+impl MeshCache {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    /// The cached BVH of the mesh at `path`, or `None` if there is no up to date cache file.
+    ///
+    /// # Arguments
+    /// * `path` the mesh file
+    /// * `default_material_id` the material of triangles the mesh assigns no material to
+    /// * `options` the options the BVH must have been built with
+    /// * `material_library` resolves the stored material names. The MTL files the mesh referred
+    ///   to are loaded into it first, just like parsing the mesh would.
+    pub fn load(
+        &self,
+        path: &Path,
+        default_material_id: usize,
+        options: &BVHOptions,
+        material_library: &mut MaterialLibrary,
+    ) -> Option<BVH> {
+        let start_time = Instant::now();
+        let default_material = material_library.material_name(default_material_id);
+        let cache_path = self.cache_path(path, default_material);
+        let contents = match fs::read(&cache_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("unable to read {}: {e}", cache_path.display());
+                return None;
+            }
+        };
+
+        let Some(mut reader) = Reader::verified(&contents) else {
+            warn!("{} is corrupt, rebuilding it", cache_path.display());
+            return None;
+        };
+        let key = Key::read(&mut reader);
+        let current = Key::new(path, default_material, options);
+        if key.is_none() || current.is_none() || key != current {
+            info!("{} is out of date, rebuilding it", cache_path.display());
+            return None;
+        }
+
+        let Some(bvh) = read_bvh(&mut reader, options, material_library, start_time) else {
+            warn!("{} is corrupt, rebuilding it", cache_path.display());
+            return None;
+        };
+        Some(bvh)
+    }
+
+    /// Writes the BVH of the mesh at `path` to the cache. Failures are logged, a missing cache
+    /// file only costs the next render a rebuild.
+    ///
+    /// # Arguments
+    /// * `path` the mesh file
+    /// * `default_material_id` the material of triangles the mesh assigns no material to
+    /// * `bvh` the mesh's BVH, holding nothing but triangles
+    /// * `material_libraries` the MTL files the mesh referred to
+    /// * `material_library` names the triangles' materials
+    pub fn store(
+        &self,
+        path: &Path,
+        default_material_id: usize,
+        bvh: &BVH,
+        material_libraries: &[PathBuf],
+        material_library: &MaterialLibrary,
+    ) {
+        let default_material = material_library.material_name(default_material_id);
+        let Some(key) = Key::new(path, default_material, bvh.options()) else {
+            warn!(
+                "unable to cache {}: the file cannot be read",
+                path.display()
+            );
+            return;
+        };
+        let Some(contents) = write_cache(&key, bvh, material_libraries, material_library) else {
+            warn!(
+                "unable to cache {}: it is not a triangle mesh",
+                path.display()
+            );
+            return;
+        };
+
+        // Write to a temporary file first so that readers never see a partial cache file
+        let cache_path = self.cache_path(path, default_material);
+        let temporary_path = cache_path.with_extension("tmp");
+        let result = fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(&temporary_path, contents))
+            .and_then(|_| fs::rename(&temporary_path, &cache_path));
+        if let Err(e) = result {
+            error!("unable to write {}: {e}", cache_path.display());
+        }
+    }
+
+    /// Meshes placed with different default materials are cached separately.
+    fn cache_path(&self, path: &Path, default_material: Option<&str>) -> PathBuf {
+        let mut hash = Fnv::new();
+        hash.write(path.to_string_lossy().as_bytes());
+        hash.write(&[0]);
+        hash.write(default_material.unwrap_or_default().as_bytes());
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        self.directory
+            .join(format!("{stem}-{:016x}.bvh", hash.finish()))
+    }
+}
+
+/// What a cache file was built from. Reading it back is only valid if nothing changed.
+#[derive(Debug, PartialEq)]
+struct Key {
+    path: String,
+    default_material: Option<String>,
+    modified_seconds: u64,
+    modified_nanoseconds: u32,
+    length: u64,
+    content_hash: u64,
+    split_method: u8,
+    max_leaf_size: u64,
+    traversal_cost: f64,
+    intersection_cost: f64,
+}
+
+impl Key {
+    fn new(path: &Path, default_material: Option<&str>, options: &BVHOptions) -> Option<Self> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?;
+
+        let mut file = File::open(path).ok()?;
+        let mut hash = Fnv::new();
+        let mut length = 0;
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            let read = file.read(&mut buffer).ok()?;
+            if read == 0 {
+                break;
+            }
+            hash.write(&buffer[..read]);
+            length += read as u64;
+        }
+
+        Some(Self {
+            path: path.to_string_lossy().into_owned(),
+            default_material: default_material.map(str::to_owned),
+            modified_seconds: modified.as_secs(),
+            modified_nanoseconds: modified.subsec_nanos(),
+            length,
+            content_hash: hash.finish(),
+            split_method: match options.split_method {
+                SplitMethod::Median => 0,
+                SplitMethod::Sah => 1,
+            },
+            max_leaf_size: options.max_leaf_size as u64,
+            traversal_cost: options.traversal_cost,
+            intersection_cost: options.intersection_cost,
+        })
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            path: reader.string()?,
+            default_material: reader.optional_string()?,
+            modified_seconds: reader.u64()?,
+            modified_nanoseconds: reader.u32()?,
+            length: reader.u64()?,
+            content_hash: reader.u64()?,
+            split_method: reader.u8()?,
+            max_leaf_size: reader.u64()?,
+            traversal_cost: reader.f64()?,
+            intersection_cost: reader.f64()?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.string(&self.path);
+        writer.optional_string(self.default_material.as_deref());
+        writer.u64(self.modified_seconds);
+        writer.u32(self.modified_nanoseconds);
+        writer.u64(self.length);
+        writer.u64(self.content_hash);
+        writer.u8(self.split_method);
+        writer.u64(self.max_leaf_size);
+        writer.f64(self.traversal_cost);
+        writer.f64(self.intersection_cost);
+    }
+}
+
+const HAS_NORMALS: u8 = 1;
+const HAS_UVS: u8 = 2;
+const HAS_COLORS: u8 = 4;
+
+/// The contents of a cache file, or `None` if the BVH holds anything but triangles.
+fn write_cache(
+    key: &Key,
+    bvh: &BVH,
+    material_libraries: &[PathBuf],
+    material_library: &MaterialLibrary,
+) -> Option<Vec<u8>> {
+    let mut writer = Writer(Vec::new());
+    writer.0.extend_from_slice(MAGIC);
+    writer.u32(VERSION);
+    key.write(&mut writer);
+
+    writer.u32(material_libraries.len() as u32);
+    for path in material_libraries {
+        writer.string(&path.to_string_lossy());
+    }
+
+    // Number the materials in the order they are first used
+    let mut material_indices: HashMap<usize, u32> = HashMap::new();
+    let mut material_names = Vec::new();
+    let mut triangles = Writer(Vec::new());
+    for index in 0..bvh.primitives.len() {
+        let triangle = bvh.triangle(index)?;
+        let material_index = *material_indices
+            .entry(triangle.material_id)
+            .or_insert_with(|| {
+                material_names.push(material_library.material_name(triangle.material_id));
+                material_names.len() as u32 - 1
+            });
+        triangles.triangle(&triangle, material_index);
+    }
+
+    writer.u32(material_names.len() as u32);
+    for name in material_names {
+        writer.optional_string(name);
+    }
+    writer.u64(bvh.primitives.len() as u64);
+    writer.0.extend_from_slice(&triangles.0);
+
+    writer.u64(bvh.nodes.len() as u64);
+    for node in &bvh.nodes {
+        for interval in node.bounding_box.intervals() {
+            writer.f64(interval.min);
+            writer.f64(interval.max);
+        }
+        let (tag, a, b) = match node.kind {
+            BVHNodeKind::Leaf { start, count } => (0, start, count),
+            BVHNodeKind::Inner { left, right } => (1, left, right),
+        };
+        writer.u8(tag);
+        writer.u32(a);
+        writer.u32(b);
+    }
+    writer.u64(bvh.root_index() as u64);
+
+    let mut checksum = Fnv::new();
+    checksum.write(&writer.0);
+    writer.u64(checksum.finish());
+    Some(writer.0)
+}
+
+/// Reads everything following the key.
+fn read_bvh(
+    reader: &mut Reader,
+    options: &BVHOptions,
+    material_library: &mut MaterialLibrary,
+    start_time: Instant,
+) -> Option<BVH> {
+    let library_count = reader.u32()?;
+    for _ in 0..library_count {
+        let path = PathBuf::from(reader.string()?);
+        if let Err(e) = load_mtl(&path, material_library) {
+            error!("unable to load {}: {e}", path.display());
+        }
+    }
+
+    let material_count = reader.u32()?;
+    let mut material_ids = Vec::new();
+    for _ in 0..material_count {
+        let id = match reader.optional_string()? {
+            Some(name) => material_library.lookup_material_id(&name),
+            None => 0,
+        };
+        material_ids.push(id);
+    }
+
+    let triangle_count = reader.u64()? as usize;
+    // Never trust a count to be small enough to allocate up front
+    let mut triangles = Vec::with_capacity(triangle_count.min(reader.remaining() / 41));
+    for _ in 0..triangle_count {
+        triangles.push(reader.triangle(&material_ids)?);
+    }
+
+    let node_count = reader.u64()? as usize;
+    let mut nodes = Vec::with_capacity(node_count.min(reader.remaining() / 57));
+    for _ in 0..node_count {
+        let mut intervals = [Interval::EMPTY; 3];
+        for interval in &mut intervals {
+            *interval = Interval::new(reader.f64()?, reader.f64()?);
+        }
+        let (tag, a, b) = (reader.u8()?, reader.u32()?, reader.u32()?);
+        let kind = match tag {
+            0 => BVHNodeKind::Leaf { start: a, count: b },
+            1 => BVHNodeKind::Inner { left: a, right: b },
+            _ => return None,
+        };
+        nodes.push(BVHNode {
+            bounding_box: AABB::from_intervals(intervals),
+            kind,
+        });
+    }
+
+    let root = reader.u64()? as usize;
+    if reader.remaining() != 0 {
+        return None;
+    }
+    BVH::from_parts(nodes, triangles, root, *options, start_time.elapsed())
+}
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions unlike the standard hasher.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Little-endian encoding of cache files
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn optional_string(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.string(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Triangle data is stored in the single precision BVHs keep it in
+    fn triple(&mut self, [a, b, c]: [f64; 3]) {
+        self.f32(a as f32);
+        self.f32(b as f32);
+        self.f32(c as f32);
+    }
+
+    fn triangle(&mut self, triangle: &Triangle, material_index: u32) {
+        for p in [triangle.p1, triangle.p2, triangle.p3] {
+            self.triple([p.0.x, p.0.y, p.0.z]);
+        }
+        self.u32(material_index);
+
+        let mut flags = 0;
+        if triangle.normals.is_some() {
+            flags |= HAS_NORMALS;
+        }
+        if triangle.uvs.is_some() {
+            flags |= HAS_UVS;
+        }
+        if triangle.colors.is_some() {
+            flags |= HAS_COLORS;
+        }
+        self.u8(flags);
+
+        if let Some(normals) = triangle.normals {
+            for n in normals {
+                self.triple([n.0.x, n.0.y, n.0.z]);
+            }
+        }
+        if let Some(uvs) = triangle.uvs {
+            for (u, v) in uvs {
+                self.f32(u as f32);
+                self.f32(v as f32);
+            }
+        }
+        if let Some(colors) = triangle.colors {
+            for color in colors {
+                self.triple([color.r, color.g, color.b]);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// A reader positioned after the header, if the file is a complete cache file of the
+    /// current version.
+    fn verified(contents: &'a [u8]) -> Option<Self> {
+        let (body, checksum) = contents.split_at_checked(contents.len().checked_sub(8)?)?;
+        let mut hash = Fnv::new();
+        hash.write(body);
+        if checksum != hash.finish().to_le_bytes() {
+            return None;
+        }
+
+        let mut reader = Reader {
+            bytes: body,
+            position: 0,
+        };
+        if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+            return None;
+        }
+        Some(reader)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    /// `Some(None)` for an absent string, `None` if the data is malformed
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(self.string()?)),
+            _ => None,
+        }
+    }
+
+    fn f32(&mut self) -> Option<f64> {
+        Some(f32::from_le_bytes(self.array()?) as f64)
+    }
+
+    fn triple(&mut self) -> Option<[f64; 3]> {
+        Some([self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    fn triangle(&mut self, material_ids: &[usize]) -> Option<Triangle> {
+        let point = |[x, y, z]: [f64; 3]| Point3::new(x, y, z);
+        let p1 = point(self.triple()?);
+        let p2 = point(self.triple()?);
+        let p3 = point(self.triple()?);
+        let material_id = *material_ids.get(self.u32()? as usize)?;
+        let mut triangle = Triangle::new(p1, p2, p3, material_id);
+
+        let flags = self.u8()?;
+        if flags & HAS_NORMALS != 0 {
+            let normal = |[x, y, z]: [f64; 3]| Normal3::new(x, y, z);
+            triangle.normals = Some([
+                normal(self.triple()?),
+                normal(self.triple()?),
+                normal(self.triple()?),
+            ]);
+        }
+        if flags & HAS_UVS != 0 {
+            triangle.uvs = Some([
+                (self.f32()?, self.f32()?),
+                (self.f32()?, self.f32()?),
+                (self.f32()?, self.f32()?),
+            ]);
+            // Tangents aren't stored, they follow from the positions and texture coordinates
+            triangle.tangents = triangle.uv_tangents();
+        }
+        if flags & HAS_COLORS != 0 {
+            let color = |[r, g, b]: [f64; 3]| Color::new(r, g, b);
+            triangle.colors = Some([
+                color(self.triple()?),
+                color(self.triple()?),
+                color(self.triple()?),
+            ]);
+        }
+        Some(triangle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::math::{Intersect, Primitive, Ray, Vec3};
+    use crate::mesh::Mesh;
+
+    const SOURCE: &str = "mtllib colors.mtl
+                          v 0 0 0
+                          v 1 0 0
+                          v 0 1 0
+                          v 1 1 0
+                          vt 0 0
+                          vt 1 0
+                          vt 0 1
+                          f 1/1 2/2 3/3
+                          usemtl green
+                          f 2 4 3";
+
+    /// Parses the mesh in `directory`, builds its BVH and caches it.
+    fn parse_and_store(directory: &Path, cache: &MeshCache, options: &BVHOptions) -> BVH {
+        let path = directory.join("mesh.obj");
+        let mut library = MaterialLibrary::new();
+        library.register_material("gray", Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let default_id = library.lookup_material_id("gray");
+
+        let mesh = Mesh::read_from_obj(
+            &mut File::open(&path).unwrap(),
+            directory,
+            &mut library,
+            default_id,
+        )
+        .unwrap();
+        let bvh = BVH::build_triangles(mesh.triangles, options);
+        cache.store(&path, default_id, &bvh, &mesh.material_libraries, &library);
+        bvh
+    }
+
+    /// Reads the mesh back with a library that registers its materials in another order.
+    fn load(directory: &Path, cache: &MeshCache, options: &BVHOptions) -> Option<BVH> {
+        let mut library = MaterialLibrary::new();
+        library.register_material(
+            "unused",
+            Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0))),
+        );
+        library.register_material("gray", Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let default_id = library.lookup_material_id("gray");
+
+        let bvh = cache.load(
+            &directory.join("mesh.obj"),
+            default_id,
+            options,
+            &mut library,
+        )?;
+        // The green material comes from the MTL file, which the cache had to load again
+        let material_ids: Vec<usize> = bvh
+            .primitives
+            .iter()
+            .map(|primitive| match primitive {
+                Primitive::Triangle(triangle) => triangle.material_id(),
+                _ => unreachable!(),
+            })
+            .collect();
+        let mut expected = vec![default_id, library.lookup_material_id("green")];
+        expected.sort();
+        let mut sorted = material_ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, expected);
+        Some(bvh)
+    }
+
+    fn setup(name: &str) -> (PathBuf, MeshCache) {
+        let directory = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("colors.mtl"), "newmtl green\nKd 0 1 0\n").unwrap();
+        fs::write(directory.join("mesh.obj"), SOURCE).unwrap();
+        let cache = MeshCache::new(&directory.join("cache"));
+        (directory, cache)
+    }
+
+    fn cache_file(directory: &Path) -> PathBuf {
+        fs::read_dir(directory.join("cache"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path()
+    }
+
+    #[test]
+    fn cached_bvh_matches_built_bvh() {
+        let (directory, cache) = setup("yart_cached_bvh_matches_built_bvh");
+        let options = BVHOptions::new();
+        assert!(load(&directory, &cache, &options).is_none());
+
+        let built = parse_and_store(&directory, &cache, &options);
+        let loaded = load(&directory, &cache, &options).unwrap();
+
+        assert_eq!(built.nodes.len(), loaded.nodes.len());
+        assert_eq!(built.statistics().sah_cost, loaded.statistics().sah_cost);
+        let ray = Ray::new(Point3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = |bvh: &BVH| {
+            bvh.intersect(&ray, Interval::new(0.001, f64::INFINITY))
+                .map(|hit| (hit.t, hit.u, hit.v))
+        };
+        assert_eq!(hit(&built), hit(&loaded));
+
+        // Built differently, so the cache no longer applies
+        let median = BVHOptions {
+            split_method: SplitMethod::Median,
+            ..options
+        };
+        assert!(load(&directory, &cache, &median).is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn stale_and_corrupt_caches_are_rejected() {
+        let (directory, cache) = setup("yart_stale_and_corrupt_caches_are_rejected");
+        let options = BVHOptions::new();
+        parse_and_store(&directory, &cache, &options);
+        let path = cache_file(&directory);
+        let contents = fs::read(&path).unwrap();
+
+        // Flipping any byte breaks the checksum
+        let mut corrupt = contents.clone();
+        let middle = corrupt.len() / 2;
+        corrupt[middle] ^= 0xff;
+        fs::write(&path, &corrupt).unwrap();
+        assert!(load(&directory, &cache, &options).is_none());
+
+        fs::write(&path, &contents[..contents.len() - 1]).unwrap();
+        assert!(load(&directory, &cache, &options).is_none());
+
+        fs::write(&path, &contents).unwrap();
+        assert!(load(&directory, &cache, &options).is_some());
+
+        // Any change to the mesh, even one that keeps its length, invalidates the cache
+        fs::write(
+            directory.join("mesh.obj"),
+            SOURCE.replace("v 1 1 0", "v 1 1 1"),
+        )
+        .unwrap();
+        assert!(load(&directory, &cache, &options).is_none());
+
+        parse_and_store(&directory, &cache, &options);
+        assert!(load(&directory, &cache, &options).is_some());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,1201 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{error, info, warn};
+
+use crate::{
+    color::Color,
+    material::{Dielectric, DiffuseLight, Material, MaterialLibrary, NormalMapped, Principled},
+    math::{Normal3, Point3, Transform, Triangle, Vec3},
+    rendering::texture::{ImageTexture, SolidColor, Texture, WrapMode},
+};
+
+use serde_json::Value as Json;
+
+/// Primitive mode for lists of triangles, the only one we render
+const MODE_TRIANGLES: usize = 4;
+
+/// Index of refraction used for transmissive materials without `KHR_materials_ior`
+const DEFAULT_INDEX_OF_REFRACTION: f64 = 1.5;
+
+/// Node hierarchies deeper than this are assumed to contain a cycle
+const MAX_NODE_DEPTH: usize = 256;
+
+/// Accessors without a buffer view are zero-filled; files asking for more values than this are
+/// rejected instead of allocating whatever they claim
+const MAX_ZERO_ACCESSOR_VALUES: usize = 1 << 26;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+#[derive(Debug)]
+enum GltfErrorKind {
+    OpenError(PathBuf),
+    BadContainer(&'static str),
+    BadJson(serde_json::Error),
+    Invalid(String),
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct GltfError {
+    kind: GltfErrorKind,
+}
+
+impl GltfError {
+    pub fn unable_to_open_file(path: PathBuf) -> Self {
+        Self {
+            kind: GltfErrorKind::OpenError(path),
+        }
+    }
+
+    fn bad_container(message: &'static str) -> Self {
+        Self {
+            kind: GltfErrorKind::BadContainer(message),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self {
+            kind: GltfErrorKind::Invalid(message.into()),
+        }
+    }
+
+    fn unsupported(message: impl Into<String>) -> Self {
+        Self {
+            kind: GltfErrorKind::Unsupported(message.into()),
+        }
+    }
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            GltfErrorKind::OpenError(path) => {
+                write!(f, "unable to open file: {}", path.to_str().unwrap_or(""))
+            }
+            GltfErrorKind::BadContainer(message) => write!(f, "invalid GLB file: {message}"),
+            GltfErrorKind::BadJson(error) => write!(f, "{error}"),
+            GltfErrorKind::Invalid(message) => write!(f, "invalid glTF: {message}"),
+            GltfErrorKind::Unsupported(message) => write!(f, "unsupported glTF: {message}"),
+        }
+    }
+}
+
+/// A perspective camera placed by a node of a glTF scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GltfCamera {
+    pub position: Point3,
+    pub look_at: Point3,
+    /// Vertical field of view in degrees
+    pub field_of_view: f64,
+    pub aspect_ratio: Option<f64>,
+}
+
+/// The triangles and cameras of a glTF scene, with their node transforms applied.
+pub struct GltfScene {
+    pub triangles: Vec<Triangle>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+impl GltfScene {
+    /// Load the default scene of a `.gltf` or `.glb` file.
+    ///
+    /// # Arguments
+    /// * `path` the file to load, external buffers and images are relative to it
+    /// * `material_library` the material library to add the file's materials to
+    /// * `default_material_id` the ID of the material in `material_library` to use for
+    ///   primitives without a material
+    ///
+    /// Materials are registered under their name, or `<file name>#<index>` if they have none.
+    /// A material that already exists in the library is used instead of the file's definition.
+    pub fn load(
+        path: &Path,
+        material_library: &mut MaterialLibrary,
+        default_material_id: usize,
+    ) -> Result<Self, GltfError> {
+        let bytes =
+            fs::read(path).map_err(|_| GltfError::unable_to_open_file(path.to_path_buf()))?;
+        let base_path = path.parent().unwrap_or(Path::new(""));
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Self::read(
+            &bytes,
+            base_path,
+            &file_name,
+            material_library,
+            default_material_id,
+        )
+    }
+
+    /// Like `load`, for a file that has already been read into memory.
+    pub fn read(
+        bytes: &[u8],
+        base_path: &Path,
+        file_name: &str,
+        material_library: &mut MaterialLibrary,
+        default_material_id: usize,
+    ) -> Result<Self, GltfError> {
+        let document = Document::parse(bytes, base_path)?;
+        let materials = document.register_materials(file_name, material_library);
+
+        let mut scene = Self {
+            triangles: Vec::new(),
+            cameras: Vec::new(),
+        };
+        for root in document.scene_roots()? {
+            scene.add_node(
+                &document,
+                root,
+                Transform::IDENTITY,
+                &materials,
+                default_material_id,
+                0,
+            )?;
+        }
+
+        info!(
+            "loaded glTF scene: {} triangles, {} cameras",
+            scene.triangles.len(),
+            scene.cameras.len()
+        );
+        Ok(scene)
+    }
+
+    fn add_node(
+        &mut self,
+        document: &Document,
+        index: usize,
+        parent: Transform,
+        materials: &[usize],
+        default_material_id: usize,
+        depth: usize,
+    ) -> Result<(), GltfError> {
+        if depth > MAX_NODE_DEPTH {
+            return Err(GltfError::invalid("node hierarchy is cyclic or too deep"));
+        }
+
+        let node = document.element("nodes", index)?;
+        let transform = parent * node_transform(node)?;
+
+        if let Some(mesh) = node.get("mesh").and_then(as_usize) {
+            self.add_mesh(document, mesh, &transform, materials, default_material_id)?;
+        }
+
+        if let Some(camera) = node.get("camera").and_then(as_usize)
+            && let Some(camera) = document.camera(camera, &transform)?
+        {
+            self.cameras.push(camera);
+        }
+
+        for child in indices(node.get("children"))? {
+            self.add_node(
+                document,
+                child,
+                transform,
+                materials,
+                default_material_id,
+                depth + 1,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn add_mesh(
+        &mut self,
+        document: &Document,
+        index: usize,
+        transform: &Transform,
+        materials: &[usize],
+        default_material_id: usize,
+    ) -> Result<(), GltfError> {
+        let mesh = document.element("meshes", index)?;
+
+        for primitive in array(mesh, "primitives") {
+            let mode = primitive
+                .get("mode")
+                .and_then(as_usize)
+                .unwrap_or(MODE_TRIANGLES);
+            if mode != MODE_TRIANGLES {
+                warn!(
+                    "skipping primitive of mesh {index} with mode {mode}, only triangles are supported"
+                );
+                continue;
+            }
+
+            let attributes = primitive
+                .get("attributes")
+                .ok_or_else(|| GltfError::invalid("mesh primitive without attributes"))?;
+            let attribute = |name: &str, components: usize| {
+                attributes
+                    .get(name)
+                    .and_then(as_usize)
+                    .map(|accessor| document.attribute(accessor, components))
+                    .transpose()
+            };
+
+            let positions: Vec<Point3> = attribute("POSITION", 3)?
+                .ok_or_else(|| GltfError::invalid("mesh primitive without positions"))?
+                .chunks_exact(3)
+                .map(|p| transform.point(Point3::new(p[0], p[1], p[2])))
+                .collect();
+            let normals: Option<Vec<Normal3>> = attribute("NORMAL", 3)?.map(|normals| {
+                normals
+                    .chunks_exact(3)
+                    .map(|n| transform.normal(Normal3::new(n[0], n[1], n[2])))
+                    .collect()
+            });
+            // glTF puts the origin of texture coordinates at the top left of the image
+            let uvs: Option<Vec<(f64, f64)>> = attribute("TEXCOORD_0", 2)?
+                .map(|uvs| uvs.chunks_exact(2).map(|uv| (uv[0], 1.0 - uv[1])).collect());
+
+            let vertex_count = positions.len();
+            if normals.as_ref().is_some_and(|n| n.len() != vertex_count)
+                || uvs.as_ref().is_some_and(|uv| uv.len() != vertex_count)
+            {
+                return Err(GltfError::invalid(
+                    "vertex attributes of a primitive differ in length",
+                ));
+            }
+
+            let vertex_indices: Vec<usize> = match primitive.get("indices").and_then(as_usize) {
+                Some(accessor) => document
+                    .attribute(accessor, 1)?
+                    .into_iter()
+                    .map(|i| i as usize)
+                    .collect(),
+                None => (0..vertex_count).collect(),
+            };
+            if vertex_indices.iter().any(|&i| i >= vertex_count) {
+                return Err(GltfError::invalid("vertex index out of range"));
+            }
+
+            let material_id = primitive
+                .get("material")
+                .and_then(as_usize)
+                .and_then(|material| materials.get(material).copied())
+                .unwrap_or(default_material_id);
+
+            // Mirroring transforms turn counterclockwise triangles clockwise, so their corners
+            // are swapped to keep them facing the same way as their vertex normals
+            let mirrored = transform.swaps_handedness();
+            for corners in vertex_indices.chunks_exact(3) {
+                let [a, b, c] = if mirrored {
+                    [corners[0], corners[2], corners[1]]
+                } else {
+                    [corners[0], corners[1], corners[2]]
+                };
+                let mut triangle =
+                    Triangle::new(positions[a], positions[b], positions[c], material_id);
+                if let Some(normals) = &normals {
+                    triangle.normals = Some([normals[a], normals[b], normals[c]]);
+                }
+                if let Some(uvs) = &uvs {
+                    triangle.uvs = Some([uvs[a], uvs[b], uvs[c]]);
+                    triangle.tangents = triangle.uv_tangents();
+                }
+                self.triangles.push(triangle);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The parsed JSON of a glTF file along with the contents of its buffers
+struct Document {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    base_path: PathBuf,
+}
+
+impl Document {
+    fn parse(bytes: &[u8], base_path: &Path) -> Result<Self, GltfError> {
+        let (json_chunk, binary_chunk) = if bytes.starts_with(GLB_MAGIC) {
+            split_glb(bytes)?
+        } else {
+            (bytes, None)
+        };
+
+        let text = std::str::from_utf8(json_chunk)
+            .map_err(|_| GltfError::invalid("JSON is not valid UTF-8"))?;
+        let json = serde_json::from_str::<Json>(text).map_err(|e| GltfError {
+            kind: GltfErrorKind::BadJson(e),
+        })?;
+
+        let version = json
+            .get("asset")
+            .and_then(|asset| asset.get("version"))
+            .and_then(Json::as_str)
+            .ok_or_else(|| GltfError::invalid("missing asset version"))?;
+        if !version.starts_with("2.") {
+            return Err(GltfError::unsupported(format!("version {version}")));
+        }
+
+        let mut buffers = Vec::new();
+        for (index, buffer) in array(&json, "buffers").iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => read_uri(uri, base_path)?,
+                // The first buffer of a GLB file may refer to its binary chunk
+                None if index == 0 => binary_chunk
+                    .ok_or_else(|| GltfError::invalid("buffer 0 has no data"))?
+                    .to_vec(),
+                None => return Err(GltfError::invalid(format!("buffer {index} has no uri"))),
+            };
+            let length = usize_field(buffer, "byteLength")?;
+            if data.len() < length {
+                return Err(GltfError::invalid(format!(
+                    "buffer {index} is shorter than its byteLength"
+                )));
+            }
+            buffers.push(data);
+        }
+
+        Ok(Self {
+            json,
+            buffers,
+            base_path: base_path.to_path_buf(),
+        })
+    }
+
+    /// The element at `index` of the top-level array `key`, such as a node or an accessor
+    fn element(&self, key: &str, index: usize) -> Result<&Json, GltfError> {
+        array(&self.json, key)
+            .get(index)
+            .ok_or_else(|| GltfError::invalid(format!("{key}[{index}] does not exist")))
+    }
+
+    /// The nodes to start traversing from
+    fn scene_roots(&self) -> Result<Vec<usize>, GltfError> {
+        if array(&self.json, "scenes").is_empty() {
+            // Without scenes, every node that is not a child of another one is a root
+            let nodes = array(&self.json, "nodes");
+            let mut is_child = vec![false; nodes.len()];
+            for node in nodes {
+                for child in indices(node.get("children"))? {
+                    if let Some(is_child) = is_child.get_mut(child) {
+                        *is_child = true;
+                    }
+                }
+            }
+            return Ok((0..nodes.len()).filter(|&i| !is_child[i]).collect());
+        }
+
+        let scene = self.json.get("scene").and_then(as_usize).unwrap_or(0);
+        indices(self.element("scenes", scene)?.get("nodes"))
+    }
+
+    fn buffer_view(&self, index: usize) -> Result<&[u8], GltfError> {
+        let view = self.element("bufferViews", index)?;
+        let buffer = usize_field(view, "buffer")?;
+        let buffer = self
+            .buffers
+            .get(buffer)
+            .ok_or_else(|| GltfError::invalid(format!("buffers[{buffer}] does not exist")))?;
+        let offset = view.get("byteOffset").and_then(as_usize).unwrap_or(0);
+        let length = usize_field(view, "byteLength")?;
+
+        offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| GltfError::invalid(format!("bufferViews[{index}] exceeds its buffer")))
+    }
+
+    /// Reads the elements of an accessor with `components` values each, flattened into a
+    /// single list. Normalized integers are mapped to [0, 1] or [-1, 1].
+    fn attribute(&self, index: usize, components: usize) -> Result<Vec<f64>, GltfError> {
+        let accessor = self.element("accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(GltfError::unsupported("sparse accessors"));
+        }
+
+        let count = usize_field(accessor, "count")?;
+        let accessor_components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => 0,
+        };
+        if accessor_components != components {
+            return Err(GltfError::invalid(format!(
+                "accessors[{index}] should have {components} components"
+            )));
+        }
+
+        let component_type = usize_field(accessor, "componentType")?;
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => {
+                return Err(GltfError::invalid(format!(
+                    "unknown component type {component_type}"
+                )));
+            }
+        };
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        // Accessors without a buffer view are all zeros
+        let Some(view_index) = accessor.get("bufferView").and_then(as_usize) else {
+            return count
+                .checked_mul(components)
+                .filter(|&length| length <= MAX_ZERO_ACCESSOR_VALUES)
+                .map(|length| vec![0.0; length])
+                .ok_or_else(|| GltfError::invalid(format!("accessors[{index}] is too large")));
+        };
+        let view = self.buffer_view(view_index)?;
+        let element_size = component_size * components;
+        let stride = self
+            .element("bufferViews", view_index)?
+            .get("byteStride")
+            .and_then(as_usize)
+            .unwrap_or(element_size);
+        let offset = accessor.get("byteOffset").and_then(as_usize).unwrap_or(0);
+
+        if stride < element_size {
+            return Err(GltfError::invalid(format!(
+                "bufferViews[{view_index}] has a stride smaller than its elements"
+            )));
+        }
+        // Sizes come from the file, so the end of the last element may not even fit in a usize
+        let end = match count.checked_sub(1) {
+            None => Some(0),
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(element_size)),
+        };
+        if end.is_none_or(|end| end > view.len()) {
+            return Err(GltfError::invalid(format!(
+                "accessors[{index}] exceeds its buffer view"
+            )));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * component_size;
+                let bytes = &view[start..start + component_size];
+                values.push(read_component(bytes, component_type, normalized));
+            }
+        }
+        Ok(values)
+    }
+
+    fn camera(&self, index: usize, transform: &Transform) -> Result<Option<GltfCamera>, GltfError> {
+        let camera = self.element("cameras", index)?;
+        let Some(perspective) = camera.get("perspective") else {
+            warn!("skipping camera {index}, only perspective cameras are supported");
+            return Ok(None);
+        };
+
+        let y_fov = perspective
+            .get("yfov")
+            .and_then(Json::as_f64)
+            .ok_or_else(|| GltfError::invalid(format!("cameras[{index}] has no yfov")))?;
+        let position = transform.point(Point3::ORIGIN);
+        // Cameras look down their local -z axis
+        let forward = transform.vector(Vec3::new(0.0, 0.0, -1.0)).normalized();
+
+        Ok(Some(GltfCamera {
+            position,
+            look_at: position + forward,
+            field_of_view: y_fov.to_degrees(),
+            aspect_ratio: perspective.get("aspectRatio").and_then(Json::as_f64),
+        }))
+    }
+
+    /// Adds the file's materials to the library. Returns the ID of each material.
+    fn register_materials(
+        &self,
+        file_name: &str,
+        material_library: &mut MaterialLibrary,
+    ) -> Vec<usize> {
+        let mut ids = Vec::new();
+
+        for (index, material) in array(&self.json, "materials").iter().enumerate() {
+            let name = material
+                .get("name")
+                .and_then(Json::as_str)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("{file_name}#{index}"));
+
+            if material_library.contains(&name) {
+                info!("material '{name}' is already defined, ignoring the one in {file_name}");
+            } else {
+                material_library.register_material(&name, self.material(material, &name));
+            }
+            ids.push(material_library.lookup_material_id(&name));
+        }
+
+        ids
+    }
+
+    /// Maps a metallic-roughness material onto the closest yart material, see `base_material`,
+    /// and applies its normal map unless it is a light.
+    fn material(&self, material: &Json, name: &str) -> Arc<dyn Material> {
+        let base = self.base_material(material, name);
+        let Some(normal_texture) = material.get("normalTexture") else {
+            return base;
+        };
+        if base.is_emissive() {
+            return base;
+        }
+
+        let texture = normal_texture
+            .get("index")
+            .and_then(as_usize)
+            .ok_or_else(|| GltfError::invalid("normalTexture has no index"))
+            .and_then(|index| self.texture(index, TextureData::Linear));
+        match texture {
+            Ok(texture) => Arc::new(NormalMapped {
+                normal_map: Some(Arc::new(texture)),
+                normal_strength: normal_texture
+                    .get("scale")
+                    .and_then(Json::as_f64)
+                    .unwrap_or(1.0),
+                ..NormalMapped::new(base)
+            }),
+            Err(e) => {
+                error!("material '{name}': {e}");
+                base
+            }
+        }
+    }
+
+    /// Emissive materials become diffuse lights, transmissive ones dielectrics and everything
+    /// else is principled.
+    fn base_material(&self, material: &Json, name: &str) -> Arc<dyn Material> {
+        let pbr = material.get("pbrMetallicRoughness");
+        let pbr_factor = |key: &str| pbr.and_then(|pbr| pbr.get(key)).and_then(Json::as_f64);
+        let extension = |extension: &str, key: &str| {
+            material
+                .get("extensions")
+                .and_then(|extensions| extensions.get(extension))
+                .and_then(|extension| extension.get(key))
+                .and_then(Json::as_f64)
+        };
+
+        let emissive = color_field(material, "emissiveFactor").unwrap_or(Color::BLACK);
+        if emissive.luminance() > 0.0 {
+            let strength =
+                extension("KHR_materials_emissive_strength", "emissiveStrength").unwrap_or(1.0);
+            return Arc::new(DiffuseLight::new(emissive, strength));
+        }
+
+        let transmission =
+            extension("KHR_materials_transmission", "transmissionFactor").unwrap_or(0.0);
+        if transmission > 0.0 {
+            let ior = extension("KHR_materials_ior", "ior").unwrap_or(DEFAULT_INDEX_OF_REFRACTION);
+            let base_color = pbr
+                .and_then(|pbr| color_field(pbr, "baseColorFactor"))
+                .unwrap_or(Color::WHITE);
+            let volume = material
+                .get("extensions")
+                .and_then(|extensions| extensions.get("KHR_materials_volume"));
+            // Without a volume, or with a thickness of zero, the surface is a thin sheet tinted
+            // by the base color
+            let thickness = extension("KHR_materials_volume", "thicknessFactor").unwrap_or(0.0);
+            let dielectric = if thickness > 0.0 {
+                Dielectric {
+                    absorption_color: volume
+                        .and_then(|volume| color_field(volume, "attenuationColor"))
+                        .unwrap_or(Color::WHITE),
+                    absorption_distance: extension("KHR_materials_volume", "attenuationDistance")
+                        .unwrap_or(f64::INFINITY),
+                    ..Dielectric::new(ior)
+                }
+            } else {
+                Dielectric {
+                    absorption_color: base_color,
+                    thin_walled: true,
+                    ..Dielectric::new(ior)
+                }
+            };
+            return Arc::new(Dielectric {
+                roughness: pbr_factor("roughnessFactor").unwrap_or(1.0),
+                ..dielectric
+            });
+        }
+
+        let base_color = pbr
+            .and_then(|pbr| color_field(pbr, "baseColorFactor"))
+            .unwrap_or(Color::WHITE);
+        let base_color_texture = pbr
+            .and_then(|pbr| pbr.get("baseColorTexture"))
+            .and_then(|texture| texture.get("index"))
+            .and_then(as_usize);
+        let albedo: Arc<dyn Texture> =
+            match base_color_texture.map(|index| self.texture(index, TextureData::Color)) {
+                Some(Ok(mut texture)) => {
+                    texture.tint(base_color);
+                    Arc::new(texture)
+                }
+                Some(Err(e)) => {
+                    error!("material '{name}': {e}");
+                    Arc::new(SolidColor { color: base_color })
+                }
+                None => Arc::new(SolidColor { color: base_color }),
+            };
+
+        // Both factors default to one
+        let metallic = pbr_factor("metallicFactor").unwrap_or(1.0);
+        let roughness = pbr_factor("roughnessFactor").unwrap_or(1.0);
+        Arc::new(Principled::textured(albedo, metallic, roughness, 0.5))
+    }
+
+    fn texture(&self, index: usize, data: TextureData) -> Result<ImageTexture, GltfError> {
+        let texture = self.element("textures", index)?;
+        let source = texture
+            .get("source")
+            .and_then(as_usize)
+            .ok_or_else(|| GltfError::unsupported(format!("textures[{index}] has no source")))?;
+        let image = self.element("images", source)?;
+
+        // Our textures wrap both axes the same way
+        let wrap = match texture
+            .get("sampler")
+            .and_then(as_usize)
+            .map(|sampler| self.element("samplers", sampler))
+            .transpose()?
+            .and_then(|sampler| sampler.get("wrapS"))
+            .and_then(as_usize)
+        {
+            Some(33071) => WrapMode::Clamp,
+            Some(33648) => WrapMode::Mirror,
+            _ => WrapMode::Repeat,
+        };
+
+        let bytes = match (
+            image.get("uri").and_then(Json::as_str),
+            image.get("bufferView").and_then(as_usize),
+        ) {
+            (Some(uri), _) => read_uri(uri, &self.base_path)?,
+            (None, Some(view)) => self.buffer_view(view)?.to_vec(),
+            (None, None) => {
+                return Err(GltfError::invalid(format!("images[{source}] has no data")));
+            }
+        };
+
+        match data {
+            TextureData::Color => ImageTexture::from_memory(&bytes, wrap),
+            TextureData::Linear => ImageTexture::from_memory_linear(&bytes, wrap),
+        }
+        .map_err(|e| GltfError::invalid(format!("unable to decode images[{source}]: {e}")))
+    }
+}
+
+/// What a texture holds, which decides how its pixels are decoded
+#[derive(Clone, Copy)]
+enum TextureData {
+    /// sRGB-encoded colors
+    Color,
+    /// Linear values such as normals
+    Linear,
+}
+
+/// Splits a binary glTF file into its JSON and binary chunks
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfError> {
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| GltfError::bad_container("unexpected end of file"))
+    };
+
+    if read_u32(4)? != 2 {
+        return Err(GltfError::bad_container("only version 2 is supported"));
+    }
+    let length = (read_u32(8)? as usize).min(bytes.len());
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset < length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let start = offset + 8;
+        // The length comes from the file and may not fit in a 32-bit usize
+        let end = start
+            .checked_add(chunk_length)
+            .ok_or_else(|| GltfError::invalid("GLB chunk length overflows"))?;
+        let chunk = bytes
+            .get(start..end)
+            .ok_or_else(|| GltfError::bad_container("chunk exceeds the file"))?;
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if binary.is_none() => binary = Some(chunk),
+            // Unknown chunks must be ignored
+            _ => {}
+        }
+        offset = end;
+    }
+
+    let json = json.ok_or_else(|| GltfError::bad_container("missing JSON chunk"))?;
+    Ok((json, binary))
+}
+
+/// Reads a data URI or a file relative to `base_path`
+fn read_uri(uri: &str, base_path: &Path) -> Result<Vec<u8>, GltfError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, payload) = data
+            .split_once(',')
+            .ok_or_else(|| GltfError::invalid("malformed data URI"))?;
+        if !header.ends_with(";base64") {
+            return Err(GltfError::unsupported("data URIs without base64 encoding"));
+        }
+        return decode_base64(payload).ok_or_else(|| GltfError::invalid("malformed base64 data"));
+    }
+
+    let path = base_path.join(decode_percent(uri));
+    fs::read(&path).map_err(|_| GltfError::unable_to_open_file(path))
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+
+    for c in data.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+            accumulator &= (1 << bits) - 1;
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Replaces `%XX` escapes of a relative URI with the bytes they stand for
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = uri
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn read_component(bytes: &[u8], component_type: usize, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        5120 => (bytes[0] as i8 as f64, i8::MAX as f64),
+        5121 => (bytes[0] as f64, u8::MAX as f64),
+        5122 => (
+            i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            i16::MAX as f64,
+        ),
+        5123 => (
+            u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            u16::MAX as f64,
+        ),
+        5125 => {
+            let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            return value as f64;
+        }
+        _ => {
+            let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            return value as f64;
+        }
+    };
+
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
+
+/// The local transformation of a node, given either as a matrix or as translation, rotation
+/// and scale
+fn node_transform(node: &Json) -> Result<Transform, GltfError> {
+    if let Some(values) = node.get("matrix").and_then(f64_array) {
+        if values.len() != 16 {
+            return Err(GltfError::invalid("node matrix needs 16 values"));
+        }
+        // glTF matrices are stored column by column
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, value) in values.into_iter().enumerate() {
+            matrix[i % 4][i / 4] = value;
+        }
+        return Transform::from_matrix(matrix)
+            .ok_or_else(|| GltfError::invalid("node matrix is not invertible"));
+    }
+
+    let vector = |key: &str, default: [f64; 4], length: usize| match node.get(key) {
+        Some(value) => f64_array(value)
+            .filter(|values| values.len() == length)
+            .ok_or_else(|| GltfError::invalid(format!("node {key} needs {length} values"))),
+        None => Ok(default[..length].to_vec()),
+    };
+    let t = vector("translation", [0.0, 0.0, 0.0, 0.0], 3)?;
+    let r = vector("rotation", [0.0, 0.0, 0.0, 1.0], 4)?;
+    let s = vector("scale", [1.0, 1.0, 1.0, 0.0], 3)?;
+
+    if s.contains(&0.0) {
+        return Err(GltfError::unsupported("nodes with zero scale"));
+    }
+
+    Ok(Transform::translation(Vec3::new(t[0], t[1], t[2]))
+        * Transform::rotation_quaternion(r[0], r[1], r[2], r[3])
+        * Transform::scale(Vec3::new(s[0], s[1], s[2])))
+}
+
+/// The elements of the array `key` of an object, empty if there is no such array
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key)
+        .and_then(Json::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// A list of indices such as the children of a node, empty if absent
+fn indices(json: Option<&Json>) -> Result<Vec<usize>, GltfError> {
+    json.and_then(Json::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|index| as_usize(index).ok_or_else(|| GltfError::invalid("expected an index")))
+        .collect()
+}
+
+/// A non-negative integer, `None` for fractions and values that don't fit in a `usize`
+fn as_usize(json: &Json) -> Option<usize> {
+    json.as_u64().and_then(|value| usize::try_from(value).ok())
+}
+
+/// An array of numbers, `None` if any element is not a number
+fn f64_array(json: &Json) -> Option<Vec<f64>> {
+    json.as_array()?.iter().map(Json::as_f64).collect()
+}
+
+fn usize_field(json: &Json, key: &str) -> Result<usize, GltfError> {
+    json.get(key)
+        .and_then(as_usize)
+        .ok_or_else(|| GltfError::invalid(format!("missing or invalid {key}")))
+}
+
+/// An RGB or RGBA color, ignoring alpha
+fn color_field(json: &Json, key: &str) -> Option<Color> {
+    json.get(key)
+        .and_then(f64_array)
+        .filter(|values| values.len() >= 3)
+        .map(|values| Color::new(values[0], values[1], values[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::material::Lambertian;
+
+    /// Encodes `data` in base64, for embedding buffers in test files
+    fn encode_base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in data.chunks(3) {
+            let bytes = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+
+    /// A right triangle in the xy plane, facing +z, with normals, texture coordinates and
+    /// 16-bit indices
+    fn triangle_buffer() -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let floats: [f32; 24] = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, // positions
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, // normals
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0, // texture coordinates
+        ];
+        for value in floats {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn gltf_source(buffer_uri: &str, buffer_length: usize) -> String {
+        format!(
+            r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [
+                {{"translation": [0, 0, -5], "children": [1, 2]}},
+                {{"mesh": 0, "scale": [2, 2, 2]}},
+                {{"camera": 0, "rotation": [0, 0.7071068, 0, 0.7071068], "translation": [0, 1, 0]}}
+            ],
+            "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.5, "znear": 0.1}}}}],
+            "meshes": [{{"primitives": [{{
+                "attributes": {{"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2}},
+                "indices": 3,
+                "material": 0
+            }}]}}],
+            "materials": [{{
+                "name": "gold",
+                "pbrMetallicRoughness": {{"baseColorFactor": [1, 0.8, 0.3, 1], "roughnessFactor": 0.2}}
+            }}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 72}},
+                {{"buffer": 0, "byteOffset": 72, "byteLength": 24}},
+                {{"buffer": 0, "byteOffset": 96, "byteLength": 6}}
+            ],
+            "buffers": [{{"uri": "{buffer_uri}", "byteLength": {buffer_length}}}]
+        }}"#
+        )
+    }
+
+    fn assert_near(a: Point3, b: Point3) {
+        assert!((a - b).length() < 1e-6, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn load_gltf_with_embedded_buffer() {
+        let buffer = triangle_buffer();
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&buffer)
+        );
+        let source = gltf_source(&uri, buffer.len());
+
+        let mut material_library = MaterialLibrary::new();
+        let scene = GltfScene::read(
+            source.as_bytes(),
+            Path::new(""),
+            "test.gltf",
+            &mut material_library,
+            0,
+        )
+        .unwrap();
+
+        // The mesh is scaled by its own node and translated by its parent
+        assert_eq!(scene.triangles.len(), 1);
+        let triangle = &scene.triangles[0];
+        assert_near(triangle.p1, Point3::new(0.0, 0.0, -5.0));
+        assert_near(triangle.p2, Point3::new(2.0, 0.0, -5.0));
+        assert_near(triangle.p3, Point3::new(0.0, 2.0, -5.0));
+        assert_eq!(triangle.normals, Some([Normal3::new(0.0, 0.0, 1.0); 3]));
+        // Texture coordinates are flipped vertically
+        assert_eq!(triangle.uvs, Some([(0.0, 1.0), (1.0, 1.0), (0.0, 0.0)]));
+
+        assert_eq!(material_library.size(), 1);
+        assert_eq!(
+            triangle.material_id,
+            material_library.lookup_material_id("gold")
+        );
+
+        // The camera is turned 90 degrees to the left, looking down -x
+        assert_eq!(scene.cameras.len(), 1);
+        let camera = scene.cameras[0];
+        assert_near(camera.position, Point3::new(0.0, 1.0, -5.0));
+        assert_near(camera.look_at, Point3::new(-1.0, 1.0, -5.0));
+        assert!((camera.field_of_view - 0.5f64.to_degrees()).abs() < 1e-9);
+        assert_eq!(camera.aspect_ratio, None);
+    }
+
+    #[test]
+    fn mirrored_nodes_keep_triangles_facing_out() {
+        let buffer = triangle_buffer();
+        let uri = format!("data:;base64,{}", encode_base64(&buffer));
+        let source = gltf_source(&uri, buffer.len()).replace(
+            r#"{"mesh": 0, "scale": [2, 2, 2]}"#,
+            r#"{"mesh": 0, "scale": [-2, 2, 2]}"#,
+        );
+
+        let mut material_library = MaterialLibrary::new();
+        let scene = GltfScene::read(
+            source.as_bytes(),
+            Path::new(""),
+            "test.gltf",
+            &mut material_library,
+            0,
+        )
+        .unwrap();
+
+        let triangle = &scene.triangles[0];
+        assert_near(triangle.p2, Point3::new(0.0, 2.0, -5.0));
+        assert_near(triangle.p3, Point3::new(-2.0, 0.0, -5.0));
+        // The winding agrees with the vertex normals, which still face +z
+        let facing = (triangle.p2 - triangle.p1).cross(triangle.p3 - triangle.p1);
+        assert!(facing.z > 0.0);
+        assert_eq!(triangle.normals, Some([Normal3::new(0.0, 0.0, 1.0); 3]));
+        // Texture coordinates follow their corners
+        assert_eq!(triangle.uvs, Some([(0.0, 1.0), (0.0, 0.0), (1.0, 1.0)]));
+    }
+
+    #[test]
+    fn reject_sizes_beyond_the_buffers() {
+        let buffer = triangle_buffer();
+        let uri = format!("data:;base64,{}", encode_base64(&buffer));
+        let source = gltf_source(&uri, buffer.len());
+        let positions = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        let cases = [
+            source.replace(
+                r#""byteOffset": 0, "byteLength": 72"#,
+                r#""byteOffset": 18446744073709551615, "byteLength": 72"#,
+            ),
+            source.replace(
+                positions,
+                r#"{"bufferView": 0, "byteOffset": 18446744073709551615, "componentType": 5126, "count": 3, "type": "VEC3"}"#,
+            ),
+            source.replace(
+                positions,
+                r#"{"bufferView": 0, "componentType": 5126, "count": 9223372036854775807, "type": "VEC3"}"#,
+            ),
+            source.replace(
+                positions,
+                r#"{"bufferView": 0, "componentType": 5126, "count": 1e300, "type": "VEC3"}"#,
+            ),
+            // Without a buffer view the accessor would be zero-filled
+            source.replace(
+                positions,
+                r#"{"componentType": 5126, "count": 6148914691236517205, "type": "VEC3"}"#,
+            ),
+        ];
+
+        for source in cases {
+            let mut material_library = MaterialLibrary::new();
+            let result = GltfScene::read(
+                source.as_bytes(),
+                Path::new(""),
+                "test.gltf",
+                &mut material_library,
+                0,
+            );
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn load_glb_with_binary_chunk() {
+        let buffer = triangle_buffer();
+        let mut json = gltf_source("", buffer.len()).replace(r#""uri": "", "#, "");
+        // Chunks are padded to multiples of four bytes
+        while !json.len().is_multiple_of(4) {
+            json.push(' ');
+        }
+        let mut binary = buffer.clone();
+        while !binary.len().is_multiple_of(4) {
+            binary.push(0);
+        }
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(GLB_MAGIC);
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        let length = 12 + 8 + json.len() + 8 + binary.len();
+        glb.extend_from_slice(&(length as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+        glb.extend_from_slice(json.as_bytes());
+        glb.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+        glb.extend_from_slice(&binary);
+
+        let mut material_library = MaterialLibrary::new();
+        let scene =
+            GltfScene::read(&glb, Path::new(""), "test.glb", &mut material_library, 0).unwrap();
+        assert_eq!(scene.triangles.len(), 1);
+        assert_eq!(scene.cameras.len(), 1);
+    }
+
+    #[test]
+    fn existing_materials_take_precedence() {
+        let buffer = triangle_buffer();
+        let uri = format!("data:;base64,{}", encode_base64(&buffer));
+        let source = gltf_source(&uri, buffer.len());
+
+        let mut material_library = MaterialLibrary::new();
+        material_library.register_material("gold", Arc::new(Lambertian::new(Color::WHITE)));
+        let scene = GltfScene::read(
+            source.as_bytes(),
+            Path::new(""),
+            "test.gltf",
+            &mut material_library,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(material_library.size(), 1);
+        assert_eq!(scene.triangles[0].material_id, 1);
+    }
+
+    #[test]
+    fn glb_chunks_beyond_the_file_are_an_error() {
+        let mut glb = Vec::new();
+        glb.extend_from_slice(GLB_MAGIC);
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&28u32.to_le_bytes());
+        glb.extend_from_slice(&u32::MAX.to_le_bytes());
+        glb.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+        glb.extend_from_slice(b"{}  ");
+
+        assert!(split_glb(&glb).is_err());
+    }
+
+    #[test]
+    fn decode_uris() {
+        assert_eq!(decode_base64("eWFydA=="), Some(b"yart".to_vec()));
+        assert_eq!(
+            decode_base64(&encode_base64(b"glTF 2.0")),
+            Some(b"glTF 2.0".to_vec())
+        );
+        assert_eq!(decode_base64("not base64!"), None);
+        assert_eq!(decode_percent("my%20model.bin"), "my model.bin");
+        assert_eq!(decode_percent("100%"), "100%");
+    }
+}
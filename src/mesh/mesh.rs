@@ -1,112 +1,137 @@
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::{error, info, warn};
 
 use crate::{
+    color::Color,
     material::MaterialLibrary,
-    math::{Point3, Triangle},
+    math::{Normal3, Point3, Triangle},
 };
 
+use super::mtl::load_mtl;
+
 #[derive(Debug)]
-enum ObjParseErrorKind {
+enum MeshParseErrorKind {
     OpenError(PathBuf),
     ReadError,
     BadLine,
+    BadHeader(String),
     BadFloat(String),
     BadIndex(String),
     BadVertex(usize),
+    UnexpectedEnd(String),
 }
 
+/// An error while parsing a mesh file. Errors in text formats carry the line they occurred on.
 #[derive(Debug)]
-pub struct ObjParseError {
-    kind: ObjParseErrorKind,
+pub struct MeshParseError {
+    kind: MeshParseErrorKind,
     line: u32,
 }
 
-impl ObjParseError {
+impl MeshParseError {
     pub fn unable_to_open_file(path: PathBuf) -> Self {
         Self {
-            kind: ObjParseErrorKind::OpenError(path),
+            kind: MeshParseErrorKind::OpenError(path),
+            line: 0,
+        }
+    }
+
+    pub(super) fn unable_to_read_file() -> Self {
+        Self {
+            kind: MeshParseErrorKind::ReadError,
             line: 0,
         }
     }
 
-    fn unable_to_read_file(context: &Context) -> Self {
+    pub(super) fn bad_line(context: &Context) -> Self {
         Self {
-            kind: ObjParseErrorKind::ReadError,
+            kind: MeshParseErrorKind::BadLine,
             line: context.line,
         }
     }
 
-    fn bad_line(context: &Context) -> Self {
+    pub(super) fn bad_header(context: &Context, message: &str) -> Self {
         Self {
-            kind: ObjParseErrorKind::BadLine,
+            kind: MeshParseErrorKind::BadHeader(message.to_owned()),
             line: context.line,
         }
     }
 
-    fn bad_float(context: &Context, value: &str) -> Self {
+    pub(super) fn bad_float(context: &Context, value: &str) -> Self {
         Self {
-            kind: ObjParseErrorKind::BadFloat(value.to_owned()),
+            kind: MeshParseErrorKind::BadFloat(value.to_owned()),
             line: context.line,
         }
     }
 
-    fn bad_index(context: &Context, value: &str) -> Self {
+    pub(super) fn bad_index(context: &Context, value: &str) -> Self {
         Self {
-            kind: ObjParseErrorKind::BadIndex(value.to_owned()),
+            kind: MeshParseErrorKind::BadIndex(value.to_owned()),
             line: context.line,
         }
     }
 
-    fn bad_vertex(context: &Context, index: usize) -> Self {
+    pub(super) fn bad_vertex(context: &Context, index: usize) -> Self {
         Self {
-            kind: ObjParseErrorKind::BadVertex(index),
+            kind: MeshParseErrorKind::BadVertex(index),
+            line: context.line,
+        }
+    }
+
+    /// The file ended while reading `what`, such as "face 12"
+    pub(super) fn unexpected_end(context: &Context, what: &str) -> Self {
+        Self {
+            kind: MeshParseErrorKind::UnexpectedEnd(what.to_owned()),
             line: context.line,
         }
     }
 }
 
-impl std::fmt::Display for ObjParseError {
+impl std::fmt::Display for MeshParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Binary formats have no lines
+        if self.line > 0 {
+            write!(f, "line {}: ", self.line)?;
+        }
         match &self.kind {
-            ObjParseErrorKind::OpenError(path) => {
+            MeshParseErrorKind::OpenError(path) => {
                 write!(f, "unable to open file: {}", path.to_str().unwrap_or(""))
             }
-            ObjParseErrorKind::ReadError => write!(f, "unable to read OBJ file"),
-            ObjParseErrorKind::BadLine => write!(f, "line {}: badly formatted line", self.line),
-            ObjParseErrorKind::BadFloat(value) => {
-                write!(f, "line {}: unable to parse float '{}'", self.line, value)
+            MeshParseErrorKind::ReadError => write!(f, "unable to read file"),
+            MeshParseErrorKind::BadLine => write!(f, "badly formatted line"),
+            MeshParseErrorKind::BadHeader(message) => write!(f, "invalid header: {message}"),
+            MeshParseErrorKind::BadFloat(value) => write!(f, "unable to parse float '{value}'"),
+            MeshParseErrorKind::BadIndex(value) => write!(f, "unable to parse index '{value}'"),
+            MeshParseErrorKind::BadVertex(index) => {
+                write!(f, "unable to get vertex at index {index}")
             }
-            ObjParseErrorKind::BadIndex(value) => {
-                write!(f, "line {}: unable to parse index '{}'", self.line, value)
+            MeshParseErrorKind::UnexpectedEnd(what) => {
+                write!(f, "unexpected end of file while reading {what}")
             }
-            ObjParseErrorKind::BadVertex(index) => write!(
-                f,
-                "line {}: unable to get vertex at index {}",
-                self.line, index
-            ),
         }
     }
 }
 
 pub struct Context {
-    material_id: usize,
-    line: u32,
+    pub(super) material_id: usize,
+    pub(super) line: u32,
 }
 
 impl Context {
     pub fn new(material_id: usize) -> Self {
-        return Self {
+        Self {
             material_id,
             line: 1,
-        };
+        }
     }
 }
 
 pub struct Mesh {
     pub triangles: Vec<Triangle>,
+    /// The MTL files the mesh referred to, whose materials were added to the material library
+    pub material_libraries: Vec<PathBuf>,
 }
 
 impl Mesh {
@@ -114,27 +139,32 @@ impl Mesh {
     ///
     /// # Arguments
     /// * `reader` OBJ source
+    /// * `base_path` the directory that `mtllib` paths are relative to
     /// * `material_library` the material library to use when resolving materials
     /// * `default_material_id` the ID of the material in `material_library` to use in the absence
-    ///     of `usemtl`
+    ///   of `usemtl`
     ///
-    /// `usemtl` directives are looked up in the provided material library.
+    /// Materials from `mtllib` files are added to the material library, unless a material with
+    /// the same name already exists. `usemtl` directives are looked up in the material library.
     pub fn read_from_obj<R: Read>(
         reader: &mut R,
-        material_library: &MaterialLibrary,
+        base_path: &Path,
+        material_library: &mut MaterialLibrary,
         default_material_id: usize,
-    ) -> Result<Self, ObjParseError> {
+    ) -> Result<Self, MeshParseError> {
         let mut context = Context::new(default_material_id);
 
         let mut contents = String::new();
         reader
             .read_to_string(&mut contents)
-            .map_err(|_| ObjParseError::unable_to_read_file(&context))?;
+            .map_err(|_| MeshParseError::unable_to_read_file())?;
 
         let mut vertices: Vec<Point3> = Vec::new();
-        let mut normal_vertices: Vec<Point3> = Vec::new();
+        // Zero-length normals, which some exporters write for degenerate vertices, are `None`
+        let mut normal_vertices: Vec<Option<Normal3>> = Vec::new();
         let mut texture_coordinates: Vec<(f64, f64)> = Vec::new();
         let mut triangles: Vec<Triangle> = Vec::new();
+        let mut material_libraries: Vec<PathBuf> = Vec::new();
 
         for line in contents.lines() {
             let line = line.trim();
@@ -144,7 +174,7 @@ impl Mesh {
             }
             let (directive, args) = line
                 .split_once(char::is_whitespace)
-                .ok_or(ObjParseError::bad_line(&context))?;
+                .ok_or(MeshParseError::bad_line(&context))?;
             match directive {
                 "v" => {
                     let parts: Vec<&str> = args.split_whitespace().collect();
@@ -154,7 +184,9 @@ impl Mesh {
                 "vn" => {
                     let parts: Vec<&str> = args.split_whitespace().collect();
                     let point = try_parse_point(parts, &context)?;
-                    normal_vertices.push(point);
+                    let normal =
+                        (point.0.length_squared() > 0.0).then(|| Normal3(point.0.normalized()));
+                    normal_vertices.push(normal);
                 }
                 "vt" => {
                     let parts: Vec<&str> = args.split_whitespace().collect();
@@ -175,12 +207,26 @@ impl Mesh {
                         continue;
                     }
 
-                    let mut polygon: Vec<Point3> = Vec::with_capacity(indices.len());
+                    let mut polygon: Vec<PolygonVertex> = Vec::with_capacity(indices.len());
                     for face_vertex in &indices {
-                        let v = *vertices
-                            .get(face_vertex.v - 1)
-                            .ok_or(ObjParseError::bad_vertex(&context, face_vertex.v))?;
-                        polygon.push(v);
+                        let position = *lookup(&vertices, face_vertex.v, &context)?;
+                        let normal = face_vertex
+                            .vn
+                            .map(|i| lookup(&normal_vertices, i, &context))
+                            .transpose()?
+                            .copied()
+                            .flatten();
+                        let uv = face_vertex
+                            .vt
+                            .map(|i| lookup(&texture_coordinates, i, &context))
+                            .transpose()?
+                            .copied();
+                        polygon.push(PolygonVertex {
+                            position,
+                            normal,
+                            uv,
+                            color: None,
+                        });
                     }
 
                     let tris = triangulate_fan(&polygon, &context);
@@ -193,8 +239,13 @@ impl Mesh {
                     continue;
                 }
                 "mtllib" => {
-                    // We don't support materials defined in the mtl format.
-                    continue;
+                    for file_name in args.split_whitespace() {
+                        let path = base_path.join(file_name);
+                        if let Err(e) = load_mtl(&path, material_library) {
+                            error!("unable to load {}: {e}", path.display());
+                        }
+                        material_libraries.push(path);
+                    }
                 }
                 "usemtl" => {
                     let material_name = args;
@@ -208,16 +259,19 @@ impl Mesh {
         }
 
         info!("loaded mesh: {} triangles", triangles.len());
-        Ok(Self { triangles })
+        Ok(Self {
+            triangles,
+            material_libraries,
+        })
     }
 }
 
-fn try_parse_f64(s: &str, context: &Context) -> Result<f64, ObjParseError> {
-    s.parse().map_err(|_| ObjParseError::bad_float(context, s))
+pub(super) fn try_parse_f64(s: &str, context: &Context) -> Result<f64, MeshParseError> {
+    s.parse().map_err(|_| MeshParseError::bad_float(context, s))
 }
 
-fn try_parse_usize(s: &str, context: &Context) -> Result<usize, ObjParseError> {
-    s.parse().map_err(|_| ObjParseError::bad_index(&context, s))
+pub(super) fn try_parse_usize(s: &str, context: &Context) -> Result<usize, MeshParseError> {
+    s.parse().map_err(|_| MeshParseError::bad_index(context, s))
 }
 
 #[derive(Default)]
@@ -227,7 +281,7 @@ struct ObjFaceVertex {
     vn: Option<usize>,
 }
 
-fn try_parse_face_vertex(s: &str, context: &Context) -> Result<ObjFaceVertex, ObjParseError> {
+fn try_parse_face_vertex(s: &str, context: &Context) -> Result<ObjFaceVertex, MeshParseError> {
     let parts: Vec<&str> = s.split('/').collect();
     let v = try_parse_usize(parts[0], context)?;
 
@@ -247,24 +301,57 @@ fn try_parse_face_vertex(s: &str, context: &Context) -> Result<ObjFaceVertex, Ob
     Ok(face_vertex)
 }
 
-fn try_parse_point(parts: Vec<&str>, context: &Context) -> Result<Point3, ObjParseError> {
+/// Looks up an element by its one-based OBJ index.
+fn lookup<'a, T>(
+    elements: &'a [T],
+    index: usize,
+    context: &Context,
+) -> Result<&'a T, MeshParseError> {
+    index
+        .checked_sub(1)
+        .and_then(|i| elements.get(i))
+        .ok_or(MeshParseError::bad_vertex(context, index))
+}
+
+fn try_parse_point(parts: Vec<&str>, context: &Context) -> Result<Point3, MeshParseError> {
     let x = try_parse_f64(parts[0], context)?;
     let y = try_parse_f64(parts[1], context)?;
     let z = try_parse_f64(parts[2], context)?;
     Ok(Point3::new(x, y, z))
 }
 
-fn triangulate_fan(polygon: &[Point3], context: &Context) -> Vec<Triangle> {
+#[derive(Clone, Copy)]
+pub(super) struct PolygonVertex {
+    pub(super) position: Point3,
+    pub(super) normal: Option<Normal3>,
+    pub(super) uv: Option<(f64, f64)>,
+    pub(super) color: Option<Color>,
+}
+
+/// Splits a convex polygon into triangles that share its first vertex
+pub(super) fn triangulate_fan(polygon: &[PolygonVertex], context: &Context) -> Vec<Triangle> {
     let mut triangles = Vec::new();
     let n = polygon.len();
 
     for i in 1..n - 1 {
-        let tri = Triangle {
-            p1: polygon[0],
-            p2: polygon[i],
-            p3: polygon[i + 1],
-            material_id: context.material_id,
-        };
+        let corners = [&polygon[0], &polygon[i], &polygon[i + 1]];
+        let mut tri = Triangle::new(
+            corners[0].position,
+            corners[1].position,
+            corners[2].position,
+            context.material_id,
+        );
+        // Vertex attributes are only used if every corner has them
+        if let [Some(n1), Some(n2), Some(n3)] = corners.map(|c| c.normal) {
+            tri.normals = Some([n1, n2, n3]);
+        }
+        if let [Some(uv1), Some(uv2), Some(uv3)] = corners.map(|c| c.uv) {
+            tri.uvs = Some([uv1, uv2, uv3]);
+            tri.tangents = tri.uv_tangents();
+        }
+        if let [Some(c1), Some(c2), Some(c3)] = corners.map(|c| c.color) {
+            tri.colors = Some([c1, c2, c3]);
+        }
         triangles.push(tri);
     }
 
@@ -275,7 +362,7 @@ fn triangulate_fan(polygon: &[Point3], context: &Context) -> Vec<Triangle> {
 mod tests {
     use std::sync::Arc;
 
-    use crate::{color::Color, material::Lambertian};
+    use crate::{material::Lambertian, math::Vec3};
 
     use super::*;
 
@@ -290,7 +377,8 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &MaterialLibrary::new(), 0).unwrap();
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
         assert_eq!(mesh.triangles.len(), 1);
     }
 
@@ -304,7 +392,8 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &MaterialLibrary::new(), 0).unwrap();
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
         assert_eq!(mesh.triangles.len(), 2);
     }
 
@@ -320,7 +409,8 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &MaterialLibrary::new(), 0).unwrap();
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
         assert_eq!(mesh.triangles.len(), 3);
     }
 
@@ -343,8 +433,76 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &MaterialLibrary::new(), 0).unwrap();
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
         assert_eq!(mesh.triangles.len(), 2);
+
+        let second = &mesh.triangles[1];
+        assert_eq!(second.uvs, Some([(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]));
+        assert_eq!(second.normals, Some([Normal3::new(0.0, 0.0, 1.0); 3]));
+        // Texture coordinates line up with the axes
+        assert_eq!(
+            second.tangents,
+            Some([Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)])
+        );
+    }
+
+    #[test]
+    fn load_obj_zero_normals_fall_back_to_flat_shading() {
+        let source = "v 0.0 0.0 0.0
+                     v 1.0 0.0 0.0
+                     v 1.0 1.0 0.0
+                     v 0.0 1.0 0.0
+                     vn 0.0 0.0 1.0
+                     vn 0.0 0.0 0.0
+                     f 1//1 2//1 3//1
+                     f 1//1 3//2 4//1";
+
+        let mut cursor = std::io::Cursor::new(source);
+
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
+        assert_eq!(
+            mesh.triangles[0].normals,
+            Some([Normal3::new(0.0, 0.0, 1.0); 3])
+        );
+        assert_eq!(mesh.triangles[1].normals, None);
+    }
+
+    #[test]
+    fn load_obj_vertex_normals_are_normalized() {
+        let source = "v 0.0 0.0 0.0
+                     v 1.0 0.0 0.0
+                     v 1.0 1.0 0.0
+                     vn 0.0 0.0 2.0
+                     vn 0.0 3.0 0.0
+                     vn 0.0 0.0 1.0
+                     f 1//1 2//2 3//3";
+
+        let mut cursor = std::io::Cursor::new(source);
+
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
+        let normals = mesh.triangles[0].normals.unwrap();
+        assert_eq!(normals[0], Normal3::new(0.0, 0.0, 1.0));
+        assert_eq!(normals[1], Normal3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.triangles[0].uvs, None);
+    }
+
+    #[test]
+    fn load_obj_missing_texture_coordinate_is_an_error() {
+        let source = "v 0.0 0.0 0.0
+                     v 1.0 0.0 0.0
+                     v 1.0 1.0 0.0
+                     vt 0.0 0.0
+                     f 1/1 2/2 3/1";
+
+        let mut cursor = std::io::Cursor::new(source);
+
+        assert!(
+            Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+                .is_err()
+        );
     }
 
     #[test]
@@ -359,7 +517,8 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &MaterialLibrary::new(), 0).unwrap();
+        let mesh = Mesh::read_from_obj(&mut cursor, Path::new(""), &mut MaterialLibrary::new(), 0)
+            .unwrap();
         assert_eq!(mesh.triangles.len(), 1);
     }
 
@@ -395,7 +554,8 @@ mod tests {
 
         let mut cursor = std::io::Cursor::new(source);
 
-        let mesh = Mesh::read_from_obj(&mut cursor, &material_library, 0).unwrap();
+        let mesh =
+            Mesh::read_from_obj(&mut cursor, Path::new(""), &mut material_library, 0).unwrap();
         assert_eq!(mesh.triangles.len(), 3);
 
         // Default material
@@ -409,4 +569,29 @@ mod tests {
             material_library.lookup_material_id("blue")
         );
     }
+
+    #[test]
+    fn load_obj_registers_mtllib_materials() {
+        let directory = std::env::temp_dir().join("yart_load_obj_registers_mtllib_materials");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("colors.mtl"), "newmtl green\nKd 0 1 0\n").unwrap();
+
+        let source = "mtllib colors.mtl
+                      v 0.0 0.0 0.0
+                      v 1.0 0.0 0.0
+                      v 0.0 1.0 0.0
+                      usemtl green
+                      f 1 2 3";
+
+        let mut cursor = std::io::Cursor::new(source);
+        let mut material_library = MaterialLibrary::new();
+        let mesh = Mesh::read_from_obj(&mut cursor, &directory, &mut material_library, 0).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(material_library.size(), 1);
+        assert_eq!(
+            mesh.triangles[0].material_id,
+            material_library.lookup_material_id("green")
+        );
+    }
 }
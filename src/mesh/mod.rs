@@ -1,4 +1,12 @@
+pub mod cache;
+pub mod gltf;
+#[allow(clippy::module_inception)]
 pub mod mesh;
+pub mod mtl;
+pub mod ply;
+pub mod stl;
 
+pub use cache::MeshCache;
+pub use gltf::{GltfCamera, GltfError, GltfScene};
 pub use mesh::Mesh;
-pub use mesh::ObjParseError;
+pub use mesh::MeshParseError;
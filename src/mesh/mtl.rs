@@ -0,0 +1,398 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{error, info, warn};
+
+use crate::{
+    color::Color,
+    material::{
+        Dielectric, DiffuseLight, Lambertian, Material, MaterialLibrary, Metal, NormalMapped,
+    },
+    rendering::texture::{ImageTexture, Texture, WrapMode},
+};
+
+use super::mesh::{Context, MeshParseError, try_parse_f64, try_parse_usize};
+
+/// Index of refraction used for transparent materials that do not specify `Ni`
+const DEFAULT_INDEX_OF_REFRACTION: f64 = 1.5;
+
+/// How far white lies above black in bump maps, before scaling by `-bm`. MTL files don't say.
+const BUMP_HEIGHT: f64 = 0.01;
+
+/// A material as described by a Wavefront MTL file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Color,
+    /// `Ks`
+    pub specular: Color,
+    /// `Ns`, the Phong exponent of the specular highlight
+    pub specular_exponent: f64,
+    /// `Ni`
+    pub index_of_refraction: Option<f64>,
+    /// `d`, or one minus `Tr`. Zero is fully transparent.
+    pub dissolve: f64,
+    /// `Ke`
+    pub emission: Color,
+    /// `illum`, the illumination model
+    pub illumination: usize,
+    /// `map_Kd`, relative to the MTL file
+    pub diffuse_map: Option<PathBuf>,
+    /// `map_Bump` or `bump`, relative to the MTL file
+    pub bump_map: Option<PathBuf>,
+    /// The `-bm` option of the bump map
+    pub bump_multiplier: f64,
+    /// `norm`, a tangent-space normal map relative to the MTL file
+    pub normal_map: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::BLACK,
+            specular_exponent: 0.0,
+            index_of_refraction: None,
+            dissolve: 1.0,
+            emission: Color::BLACK,
+            illumination: 2,
+            diffuse_map: None,
+            bump_map: None,
+            bump_multiplier: 1.0,
+            normal_map: None,
+        }
+    }
+
+    /// Maps the material onto the closest yart material:
+    /// - emissive materials (`Ke`) become diffuse lights,
+    /// - transparent ones (`d` < 1 or a refracting `illum`) become dielectrics,
+    /// - reflective ones (`illum` 3, 5 or 8) become metals tinted by `Ks`, rougher the lower `Ns`,
+    /// - everything else is Lambertian with the color of `Kd` or `map_Kd`.
+    ///
+    /// Normal and bump maps are added to all but the lights. Many exporters write normal maps
+    /// as `map_Bump`, so bump maps that look like normal maps are used as such.
+    ///
+    /// `base_path` is the directory that texture paths are relative to.
+    pub fn to_material(&self, base_path: &Path) -> Arc<dyn Material> {
+        let material = self.base_material(base_path);
+        if material.is_emissive() {
+            return material;
+        }
+
+        let load = |path: &PathBuf| {
+            let path = base_path.join(path);
+            ImageTexture::load_linear(&path, WrapMode::Repeat)
+                .map_err(|e| error!("unable to load texture {}: {e}", path.display()))
+                .ok()
+        };
+        let mut normal_map = self.normal_map.as_ref().and_then(load);
+        let mut bump_map = self.bump_map.as_ref().and_then(load);
+        if normal_map.is_none() && bump_map.as_ref().is_some_and(looks_like_normal_map) {
+            normal_map = bump_map.take();
+        }
+        if normal_map.is_none() && bump_map.is_none() {
+            return material;
+        }
+
+        let into_texture = |texture: ImageTexture| -> Arc<dyn Texture> { Arc::new(texture) };
+        let strength = if self.normal_map.is_some() {
+            1.0
+        } else {
+            self.bump_multiplier
+        };
+        Arc::new(NormalMapped {
+            normal_map: normal_map.map(into_texture),
+            normal_strength: strength,
+            bump_map: bump_map.map(into_texture),
+            bump_height: BUMP_HEIGHT * self.bump_multiplier,
+            ..NormalMapped::new(material)
+        })
+    }
+
+    fn base_material(&self, base_path: &Path) -> Arc<dyn Material> {
+        if self.emission.luminance() > 0.0 {
+            return Arc::new(DiffuseLight::new(self.emission, 1.0));
+        }
+
+        if self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9) {
+            let ior = self
+                .index_of_refraction
+                .unwrap_or(DEFAULT_INDEX_OF_REFRACTION);
+            return Arc::new(Dielectric::new(ior));
+        }
+
+        if matches!(self.illumination, 3 | 5 | 8) {
+            // Roughness with a similar highlight to a Phong lobe of the given exponent
+            let fuzz = (2.0 / (self.specular_exponent.max(0.0) + 2.0)).sqrt();
+            return Arc::new(Metal::new(self.specular, fuzz));
+        }
+
+        match &self.diffuse_map {
+            Some(path) => {
+                let path = base_path.join(path);
+                match ImageTexture::load(&path, WrapMode::Repeat) {
+                    Ok(texture) => {
+                        let texture: Arc<dyn Texture> = Arc::new(texture);
+                        Arc::new(Lambertian::textured(texture))
+                    }
+                    Err(e) => {
+                        error!("unable to load texture {}: {e}", path.display());
+                        Arc::new(Lambertian::new(self.diffuse))
+                    }
+                }
+            }
+            None => Arc::new(Lambertian::new(self.diffuse)),
+        }
+    }
+}
+
+/// Parse an MTL file into its materials, in the order they are defined.
+pub fn read_from_mtl<R: Read>(reader: &mut R) -> Result<Vec<MtlMaterial>, MeshParseError> {
+    let mut context = Context::new(0);
+
+    let mut contents = String::new();
+    reader
+        .read_to_string(&mut contents)
+        .map_err(|_| MeshParseError::unable_to_read_file())?;
+
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if !(line.starts_with("#") || line.is_empty()) {
+            let (directive, args) = line
+                .split_once(char::is_whitespace)
+                .ok_or(MeshParseError::bad_line(&context))?;
+            let args = args.trim();
+
+            if directive == "newmtl" {
+                materials.push(MtlMaterial::new(args));
+            } else {
+                // Every other directive describes the most recently declared material
+                let material = materials
+                    .last_mut()
+                    .ok_or(MeshParseError::bad_line(&context))?;
+                match directive {
+                    "Kd" => material.diffuse = try_parse_color(args, &context)?,
+                    "Ks" => material.specular = try_parse_color(args, &context)?,
+                    "Ke" => material.emission = try_parse_color(args, &context)?,
+                    "Ns" => material.specular_exponent = try_parse_f64(args, &context)?,
+                    "Ni" => material.index_of_refraction = Some(try_parse_f64(args, &context)?),
+                    "d" => material.dissolve = try_parse_f64(args, &context)?,
+                    "Tr" => material.dissolve = 1.0 - try_parse_f64(args, &context)?,
+                    "illum" => material.illumination = try_parse_usize(args, &context)?,
+                    "map_Kd" => material.diffuse_map = Some(map_path(args, &context)?),
+                    "map_Bump" | "map_bump" | "bump" => {
+                        material.bump_map = Some(map_path(args, &context)?);
+                        if let Some(multiplier) = map_option(args, "-bm") {
+                            material.bump_multiplier = try_parse_f64(multiplier, &context)?;
+                        }
+                    }
+                    "norm" => material.normal_map = Some(map_path(args, &context)?),
+                    // Ambient and transmission colors have no counterpart
+                    "Ka" | "Tf" => {}
+                    _ => {
+                        warn!("unsupported MTL directive: {}", directive);
+                    }
+                }
+            }
+        }
+        context.line += 1;
+    }
+
+    Ok(materials)
+}
+
+/// Reads the MTL file at `path` and registers its materials in `material_library`.
+/// Materials whose name is already taken, for example by the scene config, are skipped so that
+/// the existing definition wins.
+pub fn load_mtl(path: &Path, material_library: &mut MaterialLibrary) -> Result<(), MeshParseError> {
+    let mut file =
+        File::open(path).map_err(|_| MeshParseError::unable_to_open_file(path.to_path_buf()))?;
+    let materials = read_from_mtl(&mut file)?;
+    let base_path = path.parent().unwrap_or(Path::new(""));
+
+    for mtl_material in &materials {
+        if material_library.contains(&mtl_material.name) {
+            info!(
+                "material '{}' is already defined, ignoring the one in {}",
+                mtl_material.name,
+                path.display()
+            );
+            continue;
+        }
+        material_library.register_material(&mtl_material.name, mtl_material.to_material(base_path));
+    }
+
+    info!(
+        "loaded {} materials from {}",
+        materials.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn try_parse_color(args: &str, context: &Context) -> Result<Color, MeshParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let r = try_parse_f64(
+        parts.first().ok_or(MeshParseError::bad_line(context))?,
+        context,
+    )?;
+    // A single value is used for all three channels
+    let g = parts.get(1).map_or(Ok(r), |s| try_parse_f64(s, context))?;
+    let b = parts.get(2).map_or(Ok(r), |s| try_parse_f64(s, context))?;
+    Ok(Color::new(r, g, b))
+}
+
+/// The value of an option such as `-bm 1.0` of a texture map statement
+fn map_option<'a>(args: &'a str, option: &str) -> Option<&'a str> {
+    let mut parts = args.split_whitespace();
+    parts.find(|part| *part == option)?;
+    parts.next()
+}
+
+/// Whether a map is mostly the light blue of normals facing straight out of the surface,
+/// rather than the gray of heights
+fn looks_like_normal_map(texture: &ImageTexture) -> bool {
+    let average = texture.average();
+    average.b > 0.7 && (average.r - 0.5).abs() < 0.15 && (average.g - 0.5).abs() < 0.15
+}
+
+/// The file name of a texture map statement, skipping options such as `-bm 1.0`.
+fn map_path(args: &str, context: &Context) -> Result<PathBuf, MeshParseError> {
+    args.split_whitespace()
+        .last()
+        .map(PathBuf::from)
+        .ok_or(MeshParseError::bad_line(context))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::math::{Hit, Normal3, Point3, Ray, Vec3};
+    use crate::rendering::sampler::RandomSampler;
+
+    const SOURCE: &str = "# Blender MTL File
+                          newmtl Wood
+                          Ns 250.0
+                          Ka 1.0 1.0 1.0
+                          Kd 0.8 0.5 0.2
+                          Ks 0.5 0.5 0.5
+                          Ni 1.45
+                          d 1.0
+                          illum 2
+                          map_Kd textures/wood.png
+                          map_Bump -bm 0.5 textures/wood_bump.png
+
+                          newmtl Glass
+                          Ni 1.33
+                          d 0.1
+                          illum 4
+
+                          newmtl Chrome
+                          Ks 0.9 0.9 0.9
+                          Ns 1000
+                          illum 3
+
+                          newmtl Lamp
+                          Ke 4 4 4";
+
+    #[test]
+    fn parse_mtl_materials() {
+        let mut cursor = std::io::Cursor::new(SOURCE);
+        let materials = read_from_mtl(&mut cursor).unwrap();
+
+        assert_eq!(materials.len(), 4);
+        let wood = &materials[0];
+        assert_eq!(wood.name, "Wood");
+        assert_eq!(wood.diffuse, Color::new(0.8, 0.5, 0.2));
+        assert_eq!(wood.specular_exponent, 250.0);
+        assert_eq!(wood.index_of_refraction, Some(1.45));
+        assert_eq!(wood.diffuse_map, Some(PathBuf::from("textures/wood.png")));
+        assert_eq!(wood.bump_map, Some(PathBuf::from("textures/wood_bump.png")));
+        assert_eq!(wood.bump_multiplier, 0.5);
+
+        assert_eq!(materials[1].dissolve, 0.1);
+        assert_eq!(materials[1].illumination, 4);
+        assert_eq!(materials[3].emission, Color::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn directive_before_newmtl_is_an_error() {
+        let mut cursor = std::io::Cursor::new("Kd 1 1 1\nnewmtl Late");
+        assert!(read_from_mtl(&mut cursor).is_err());
+    }
+
+    /// The attenuation of a ray scattered head-on by the material
+    fn attenuation(material: &dyn Material) -> Option<Color> {
+        let hit = Hit {
+            point: Point3::ORIGIN,
+            normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            front_face: true,
+            material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
+        };
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(42));
+        material
+            .scatter(ray, &hit, &mut sampler)
+            .map(|(attenuation, _)| attenuation)
+    }
+
+    #[test]
+    fn mtl_materials_map_to_closest_material() {
+        let mut cursor = std::io::Cursor::new(SOURCE);
+        let materials = read_from_mtl(&mut cursor).unwrap();
+        let base_path = Path::new("");
+
+        let wood = MtlMaterial {
+            diffuse_map: None,
+            bump_map: None,
+            ..materials[0].clone()
+        };
+        let wood = wood.to_material(base_path);
+        let glass = materials[1].to_material(base_path);
+        let chrome = materials[2].to_material(base_path);
+        let lamp = materials[3].to_material(base_path);
+
+        assert_eq!(attenuation(wood.as_ref()), Some(Color::new(0.8, 0.5, 0.2)));
+        assert_eq!(attenuation(glass.as_ref()), Some(Color::WHITE));
+        assert_eq!(
+            attenuation(chrome.as_ref()),
+            Some(Color::new(0.9, 0.9, 0.9))
+        );
+        assert_eq!(attenuation(lamp.as_ref()), None);
+        assert!(lamp.is_emissive());
+        assert!(!wood.is_emissive());
+    }
+
+    #[test]
+    fn load_mtl_keeps_existing_materials() {
+        let path = std::env::temp_dir().join("yart_load_mtl_keeps_existing_materials.mtl");
+        std::fs::write(&path, "newmtl Red\nKd 1 0 0\nnewmtl Green\nKd 0 1 0\n").unwrap();
+
+        let mut material_library = MaterialLibrary::new();
+        material_library.register_material("Red", Arc::new(Lambertian::new(Color::WHITE)));
+        load_mtl(&path, &mut material_library).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(material_library.size(), 2);
+        let red = material_library.lookup_material(material_library.lookup_material_id("Red"));
+        let green = material_library.lookup_material(material_library.lookup_material_id("Green"));
+        assert_eq!(attenuation(red.as_ref()), Some(Color::WHITE));
+        assert_eq!(attenuation(green.as_ref()), Some(Color::new(0.0, 1.0, 0.0)));
+    }
+}
@@ -0,0 +1,664 @@
+use std::io::Read;
+use std::str::{Lines, SplitWhitespace};
+
+use log::{info, warn};
+
+use crate::{
+    color::Color,
+    math::{Normal3, Point3, Triangle, Vec3},
+    rendering::texture::srgb_to_linear,
+};
+
+use super::mesh::{Context, Mesh, MeshParseError, PolygonVertex, triangulate_fan};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Self::Int8),
+            "uchar" | "uint8" => Some(Self::UInt8),
+            "short" | "int16" => Some(Self::Int16),
+            "ushort" | "uint16" => Some(Self::UInt16),
+            "int" | "int32" => Some(Self::Int32),
+            "uint" | "uint32" => Some(Self::UInt32),
+            "float" | "float32" => Some(Self::Float32),
+            "double" | "float64" => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    /// The largest value of integer types, which stands for full intensity in colors
+    fn max_value(self) -> Option<f64> {
+        match self {
+            Self::Int8 => Some(i8::MAX as f64),
+            Self::UInt8 => Some(u8::MAX as f64),
+            Self::Int16 => Some(i16::MAX as f64),
+            Self::UInt16 => Some(u16::MAX as f64),
+            Self::Int32 => Some(i32::MAX as f64),
+            Self::UInt32 => Some(u32::MAX as f64),
+            Self::Float32 | Self::Float64 => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PropertyType {
+    Scalar(ScalarType),
+    /// A count followed by that many items
+    List {
+        count: ScalarType,
+        item: ScalarType,
+    },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    }
+
+    fn scalar_type(&self, property: usize) -> Option<ScalarType> {
+        match self.properties[property].kind {
+            PropertyType::Scalar(scalar) => Some(scalar),
+            PropertyType::List { .. } => None,
+        }
+    }
+}
+
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+impl Value {
+    fn scalar(&self) -> f64 {
+        match self {
+            Value::Scalar(value) => *value,
+            Value::List(values) => values.first().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+impl Mesh {
+    /// Parse an ASCII or binary PLY file into a mesh.
+    ///
+    /// # Arguments
+    /// * `reader` PLY source
+    /// * `material_id` the ID of the material to use for every triangle
+    ///
+    /// Vertices may carry normals (`nx`, `ny`, `nz`), colors (`red`, `green`, `blue`) and
+    /// texture coordinates (`u`, `v` or `s`, `t`). Integer colors are taken to be sRGB encoded.
+    /// Polygonal faces are split into triangles, other elements are skipped.
+    pub fn read_from_ply<R: Read>(
+        reader: &mut R,
+        material_id: usize,
+    ) -> Result<Self, MeshParseError> {
+        let mut context = Context::new(material_id);
+
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .map_err(|_| MeshParseError::unable_to_read_file())?;
+
+        let (format, elements, body) = parse_header(&contents, &mut context)?;
+        let mut body = match format {
+            Format::Ascii => {
+                let text =
+                    std::str::from_utf8(body).map_err(|_| MeshParseError::unable_to_read_file())?;
+                let mut lines = text.lines();
+                let first = lines.next().unwrap_or_default();
+                context.line += 1;
+                Body::Ascii {
+                    lines,
+                    tokens: first.split_whitespace(),
+                }
+            }
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+                // Binary data has no lines to report
+                context.line = 0;
+                Body::Binary {
+                    bytes: body,
+                    position: 0,
+                    big_endian: format == Format::BinaryBigEndian,
+                }
+            }
+        };
+
+        let mut vertices: Option<Vec<PolygonVertex>> = None;
+        let mut triangles: Vec<Triangle> = Vec::new();
+
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => {
+                    vertices = Some(read_vertices(element, &mut body, &mut context)?);
+                }
+                "face" => {
+                    let vertices = vertices.as_deref().ok_or_else(|| {
+                        MeshParseError::bad_header(&context, "faces must follow the vertices")
+                    })?;
+                    read_faces(element, vertices, &mut body, &mut context, &mut triangles)?;
+                }
+                _ => {
+                    for index in 0..element.count {
+                        body.read_instance(element, index, &mut context)?;
+                    }
+                }
+            }
+        }
+
+        info!("loaded mesh: {} triangles", triangles.len());
+        Ok(Self {
+            triangles,
+            material_libraries: Vec::new(),
+        })
+    }
+}
+
+/// Reads the header, returning the format, the elements and the data that follows
+fn parse_header<'a>(
+    contents: &'a [u8],
+    context: &mut Context,
+) -> Result<(Format, Vec<Element>, &'a [u8]), MeshParseError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+
+    loop {
+        let end = contents[position..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|offset| position + offset)
+            .ok_or_else(|| MeshParseError::unexpected_end(context, "the header"))?;
+        let line = std::str::from_utf8(&contents[position..end])
+            .map_err(|_| MeshParseError::bad_header(context, "header is not text"))?
+            .trim();
+        position = end + 1;
+
+        let mut parts = line.split_whitespace();
+        let keyword = parts.next().unwrap_or_default();
+        if context.line == 1 {
+            if line != "ply" {
+                return Err(MeshParseError::bad_header(context, "missing 'ply' magic"));
+            }
+        } else {
+            match keyword {
+                "format" => {
+                    format = Some(match parts.next() {
+                        Some("ascii") => Format::Ascii,
+                        Some("binary_little_endian") => Format::BinaryLittleEndian,
+                        Some("binary_big_endian") => Format::BinaryBigEndian,
+                        _ => return Err(MeshParseError::bad_header(context, "unknown format")),
+                    });
+                }
+                "element" => {
+                    let name = parts
+                        .next()
+                        .ok_or_else(|| MeshParseError::bad_line(context))?;
+                    let count = parts.next().unwrap_or_default();
+                    let count = count
+                        .parse()
+                        .map_err(|_| MeshParseError::bad_index(context, count))?;
+                    elements.push(Element {
+                        name: name.to_owned(),
+                        count,
+                        properties: Vec::new(),
+                    });
+                }
+                "property" => {
+                    let property = parse_property(&mut parts, context)?;
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| {
+                            MeshParseError::bad_header(context, "property outside of an element")
+                        })?
+                        .properties
+                        .push(property);
+                }
+                "end_header" => break,
+                "comment" | "obj_info" | "" => {}
+                _ => return Err(MeshParseError::bad_line(context)),
+            }
+        }
+        context.line += 1;
+    }
+
+    let format = format.ok_or_else(|| MeshParseError::bad_header(context, "missing format"))?;
+    Ok((format, elements, &contents[position..]))
+}
+
+fn parse_property(
+    parts: &mut SplitWhitespace,
+    context: &Context,
+) -> Result<Property, MeshParseError> {
+    let scalar_type = |name: Option<&str>| {
+        let name = name.unwrap_or_default();
+        ScalarType::parse(name).ok_or_else(|| {
+            MeshParseError::bad_header(context, &format!("unknown property type '{name}'"))
+        })
+    };
+
+    let kind = match parts.next() {
+        // `property list <count type> <item type> <name>`
+        Some("list") => PropertyType::List {
+            count: scalar_type(parts.next())?,
+            item: scalar_type(parts.next())?,
+        },
+        name => PropertyType::Scalar(scalar_type(name)?),
+    };
+    let name = parts
+        .next()
+        .ok_or_else(|| MeshParseError::bad_line(context))?;
+
+    Ok(Property {
+        name: name.to_owned(),
+        kind,
+    })
+}
+
+fn read_vertices(
+    element: &Element,
+    body: &mut Body,
+    context: &mut Context,
+) -> Result<Vec<PolygonVertex>, MeshParseError> {
+    let find = |names: &[&str]| element.property(names);
+    let find_all = |names: [&[&str]; 3]| match names.map(find) {
+        [Some(a), Some(b), Some(c)] => Some([a, b, c]),
+        _ => None,
+    };
+
+    let position = find_all([&["x"], &["y"], &["z"]])
+        .ok_or_else(|| MeshParseError::bad_header(context, "vertices need x, y and z"))?;
+    let normal = find_all([&["nx"], &["ny"], &["nz"]]);
+    let color = find_all([
+        &["red", "r", "diffuse_red"],
+        &["green", "g", "diffuse_green"],
+        &["blue", "b", "diffuse_blue"],
+    ]);
+    let uv = match (
+        find(&["u", "s", "texture_u", "texture_s"]),
+        find(&["v", "t", "texture_v", "texture_t"]),
+    ) {
+        (Some(u), Some(v)) => Some((u, v)),
+        _ => None,
+    };
+
+    // Integer colors are sRGB encoded, floating point ones linear
+    let color_scale = color.map(|[r, _, _]| element.scalar_type(r).and_then(ScalarType::max_value));
+
+    // The count comes from the header, so don't reserve memory for vertices that may not exist
+    let mut vertices = Vec::new();
+    for index in 0..element.count {
+        let values = body.read_instance(element, index, context)?;
+        let vector =
+            |[a, b, c]: [usize; 3]| (values[a].scalar(), values[b].scalar(), values[c].scalar());
+
+        let (x, y, z) = vector(position);
+        let normal = normal.map(|n| {
+            let (x, y, z) = vector(n);
+            Normal3(Vec3::new(x, y, z).normalized())
+        });
+        let color = color.map(|c| {
+            let (r, g, b) = vector(c);
+            match color_scale.flatten() {
+                Some(max) => Color::new(
+                    srgb_to_linear(r / max),
+                    srgb_to_linear(g / max),
+                    srgb_to_linear(b / max),
+                ),
+                None => Color::new(r, g, b),
+            }
+        });
+        let uv = uv.map(|(u, v)| (values[u].scalar(), values[v].scalar()));
+
+        vertices.push(PolygonVertex {
+            position: Point3::new(x, y, z),
+            normal,
+            uv,
+            color,
+        });
+    }
+
+    Ok(vertices)
+}
+
+fn read_faces(
+    element: &Element,
+    vertices: &[PolygonVertex],
+    body: &mut Body,
+    context: &mut Context,
+    triangles: &mut Vec<Triangle>,
+) -> Result<(), MeshParseError> {
+    let indices = element
+        .property(&["vertex_indices", "vertex_index"])
+        .ok_or_else(|| MeshParseError::bad_header(context, "faces need vertex_indices"))?;
+
+    for index in 0..element.count {
+        let values = body.read_instance(element, index, context)?;
+        let Value::List(corners) = &values[indices] else {
+            return Err(MeshParseError::bad_header(
+                context,
+                "vertex_indices must be a list",
+            ));
+        };
+
+        if corners.len() < 3 {
+            warn!("face with fewer than 3 vertices, skipping");
+            continue;
+        }
+
+        let polygon: Result<Vec<PolygonVertex>, _> = corners
+            .iter()
+            .map(|&corner| {
+                if corner < 0.0 || corner.fract() != 0.0 {
+                    return Err(MeshParseError::bad_index(context, &corner.to_string()));
+                }
+                let index = corner as usize;
+                vertices
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| MeshParseError::bad_vertex(context, index))
+            })
+            .collect();
+        triangles.extend(triangulate_fan(&polygon?, context));
+    }
+
+    Ok(())
+}
+
+/// The data following the header, read one element instance at a time
+enum Body<'a> {
+    Ascii {
+        lines: Lines<'a>,
+        tokens: SplitWhitespace<'a>,
+    },
+    Binary {
+        bytes: &'a [u8],
+        position: usize,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read_instance(
+        &mut self,
+        element: &Element,
+        index: usize,
+        context: &mut Context,
+    ) -> Result<Vec<Value>, MeshParseError> {
+        let mut values = Vec::with_capacity(element.properties.len());
+
+        for property in &element.properties {
+            let mut read = |scalar| self.read_scalar(scalar, element, index, context);
+            let value = match property.kind {
+                PropertyType::Scalar(scalar) => Value::Scalar(read(scalar)?),
+                PropertyType::List { count, item } => {
+                    let count = read(count)?;
+                    if count < 0.0 || count.fract() != 0.0 {
+                        return Err(MeshParseError::bad_index(context, &count.to_string()));
+                    }
+                    let items: Result<Vec<f64>, _> =
+                        (0..count as usize).map(|_| read(item)).collect();
+                    Value::List(items?)
+                }
+            };
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    fn read_scalar(
+        &mut self,
+        scalar: ScalarType,
+        element: &Element,
+        index: usize,
+        context: &mut Context,
+    ) -> Result<f64, MeshParseError> {
+        let unexpected_end = |context: &Context| {
+            MeshParseError::unexpected_end(context, &format!("{} {index}", element.name))
+        };
+
+        match self {
+            Body::Ascii { lines, tokens } => {
+                let token = loop {
+                    if let Some(token) = tokens.next() {
+                        break token;
+                    }
+                    let line = lines.next().ok_or_else(|| unexpected_end(context))?;
+                    context.line += 1;
+                    *tokens = line.split_whitespace();
+                };
+                token
+                    .parse()
+                    .map_err(|_| MeshParseError::bad_float(context, token))
+            }
+            Body::Binary {
+                bytes,
+                position,
+                big_endian,
+            } => {
+                let size = scalar.size();
+                let data = bytes
+                    .get(*position..*position + size)
+                    .ok_or_else(|| unexpected_end(context))?;
+                *position += size;
+
+                let mut buffer = [0; 8];
+                buffer[..size].copy_from_slice(data);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+
+                Ok(match scalar {
+                    ScalarType::Int8 => b0 as i8 as f64,
+                    ScalarType::UInt8 => b0 as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII_SOURCE: &str = "ply
+format ascii 1.0
+comment a colored quad and a triangle
+element vertex 5
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+property float s
+property float t
+element face 2
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 0 0 2 255 0 0 0 0
+1 0 0 0 0 1 0 255 0 1 0
+1 1 0 0 0 1 0 0 255 1 1
+0 1 0 0 0 1 255 255 255 0 1
+2 0.5 0 0 0 1 0 0 0 0.5 0.5
+4 0 1 2 3
+3 1 4 2
+0 1
+";
+
+    #[test]
+    fn load_ascii_ply() {
+        let mut cursor = std::io::Cursor::new(ASCII_SOURCE);
+        let mesh = Mesh::read_from_ply(&mut cursor, 3).unwrap();
+
+        assert_eq!(mesh.triangles.len(), 3);
+        let first = &mesh.triangles[0];
+        assert_eq!(first.material_id, 3);
+        assert_eq!(first.p3, Point3::new(1.0, 1.0, 0.0));
+        // Normals are normalized
+        assert_eq!(first.normals.unwrap()[0], Normal3::new(0.0, 0.0, 1.0));
+        assert_eq!(first.uvs, Some([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]));
+        assert_eq!(
+            first.colors,
+            Some([
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0)
+            ])
+        );
+        assert_eq!(mesh.triangles[2].p2, Point3::new(2.0, 0.5, 0.0));
+    }
+
+    /// A single triangle with double precision positions and 16-bit indices
+    fn binary_source(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut source = format!(
+            "ply\nformat {format} 1.0\nelement vertex 3\nproperty double x\nproperty double y\n\
+             property double z\nelement face 1\nproperty list uchar ushort vertex_indices\nend_header\n"
+        )
+        .into_bytes();
+
+        for value in [0.0f64, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, -1.0] {
+            if big_endian {
+                source.extend_from_slice(&value.to_be_bytes());
+            } else {
+                source.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        source.push(3);
+        for index in [0u16, 1, 2] {
+            if big_endian {
+                source.extend_from_slice(&index.to_be_bytes());
+            } else {
+                source.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        source
+    }
+
+    #[test]
+    fn load_binary_ply() {
+        for big_endian in [false, true] {
+            let mut cursor = std::io::Cursor::new(binary_source(big_endian));
+            let mesh = Mesh::read_from_ply(&mut cursor, 0).unwrap();
+
+            assert_eq!(mesh.triangles.len(), 1);
+            assert_eq!(mesh.triangles[0].p3, Point3::new(0.0, 1.0, -1.0));
+            assert_eq!(mesh.triangles[0].normals, None);
+            assert_eq!(mesh.triangles[0].colors, None);
+        }
+    }
+
+    #[test]
+    fn truncated_binary_ply_is_an_error() {
+        let mut source = binary_source(false);
+        source.truncate(source.len() - 1);
+        let mut cursor = std::io::Cursor::new(source);
+
+        let error = Mesh::read_from_ply(&mut cursor, 0).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of file while reading face 0"
+        );
+    }
+
+    #[test]
+    fn huge_counts_in_truncated_files_are_an_error() {
+        let source = binary_source(false);
+        let header_end = source
+            .windows(11)
+            .position(|w| w == b"end_header\n")
+            .unwrap();
+        let mut source = String::from_utf8(source[..header_end + 11].to_vec())
+            .unwrap()
+            .replace("element vertex 3", "element vertex 99999999999999")
+            .into_bytes();
+        source.extend_from_slice(&1.0f64.to_le_bytes());
+
+        let error = Mesh::read_from_ply(&mut std::io::Cursor::new(source), 0)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of file while reading vertex 0"
+        );
+    }
+
+    #[test]
+    fn ply_errors_report_lines() {
+        let bad_vertex = ASCII_SOURCE.replace("3 1 4 2", "3 1 5 2");
+        let error = Mesh::read_from_ply(&mut std::io::Cursor::new(bad_vertex), 0)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 28: unable to get vertex at index 5"
+        );
+
+        let bad_type = ASCII_SOURCE.replace("property float nx", "property half nx");
+        let error = Mesh::read_from_ply(&mut std::io::Cursor::new(bad_type), 0)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 8: invalid header: unknown property type 'half'"
+        );
+    }
+}
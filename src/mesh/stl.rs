@@ -0,0 +1,205 @@
+use std::io::Read;
+
+use log::{info, warn};
+
+use crate::math::{Point3, Triangle};
+
+use super::mesh::{Context, Mesh, MeshParseError, PolygonVertex, triangulate_fan, try_parse_f64};
+
+/// 80 byte header followed by the triangle count
+const BINARY_HEADER_SIZE: usize = 84;
+/// Normal, three vertices and a 16-bit attribute
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+impl Mesh {
+    /// Parse an ASCII or binary STL file into a mesh.
+    ///
+    /// # Arguments
+    /// * `reader` STL source
+    /// * `material_id` the ID of the material to use for every triangle
+    ///
+    /// Facet normals are ignored, triangles are shaded flat from their vertices.
+    pub fn read_from_stl<R: Read>(
+        reader: &mut R,
+        material_id: usize,
+    ) -> Result<Self, MeshParseError> {
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .map_err(|_| MeshParseError::unable_to_read_file())?;
+
+        let triangles = if is_binary(&contents) {
+            read_binary(&contents, material_id)?
+        } else {
+            let text = std::str::from_utf8(&contents)
+                .map_err(|_| MeshParseError::unable_to_read_file())?;
+            read_ascii(text, material_id)?
+        };
+
+        info!("loaded mesh: {} triangles", triangles.len());
+        Ok(Self {
+            triangles,
+            material_libraries: Vec::new(),
+        })
+    }
+}
+
+/// Binary files may also start with "solid", so the size implied by the triangle count
+/// decides first.
+fn is_binary(contents: &[u8]) -> bool {
+    if let Some(count) = contents.get(80..BINARY_HEADER_SIZE) {
+        let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+        if count
+            .checked_mul(BINARY_TRIANGLE_SIZE)
+            .and_then(|size| size.checked_add(BINARY_HEADER_SIZE))
+            == Some(contents.len())
+        {
+            return true;
+        }
+    }
+    !contents.trim_ascii_start().starts_with(b"solid")
+}
+
+fn read_binary(contents: &[u8], material_id: usize) -> Result<Vec<Triangle>, MeshParseError> {
+    // Binary data has no lines to report
+    let mut context = Context::new(material_id);
+    context.line = 0;
+
+    let count = contents
+        .get(80..BINARY_HEADER_SIZE)
+        .ok_or_else(|| MeshParseError::unexpected_end(&context, "the header"))?;
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+
+    let mut triangles = Vec::new();
+    for index in 0..count {
+        let start = BINARY_HEADER_SIZE + index * BINARY_TRIANGLE_SIZE;
+        let data = contents
+            .get(start..start + BINARY_TRIANGLE_SIZE)
+            .ok_or_else(|| {
+                MeshParseError::unexpected_end(&context, &format!("triangle {index}"))
+            })?;
+
+        let float = |i: usize| {
+            let bytes = &data[4 * i..4 * i + 4];
+            f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+        };
+        // The first three floats are the facet normal
+        let vertex = |i: usize| Point3::new(float(3 * i + 3), float(3 * i + 4), float(3 * i + 5));
+
+        triangles.push(Triangle::new(vertex(0), vertex(1), vertex(2), material_id));
+    }
+
+    Ok(triangles)
+}
+
+fn read_ascii(text: &str, material_id: usize) -> Result<Vec<Triangle>, MeshParseError> {
+    let mut context = Context::new(material_id);
+    let mut triangles = Vec::new();
+    let mut polygon: Vec<PolygonVertex> = Vec::new();
+
+    for line in text.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["vertex", x, y, z] => polygon.push(PolygonVertex {
+                position: Point3::new(
+                    try_parse_f64(x, &context)?,
+                    try_parse_f64(y, &context)?,
+                    try_parse_f64(z, &context)?,
+                ),
+                normal: None,
+                uv: None,
+                color: None,
+            }),
+            ["vertex", ..] => return Err(MeshParseError::bad_line(&context)),
+            ["endloop"] => {
+                if polygon.len() < 3 {
+                    return Err(MeshParseError::bad_line(&context));
+                }
+                triangles.extend(triangulate_fan(&polygon, &context));
+                polygon.clear();
+            }
+            ["solid", ..]
+            | ["endsolid", ..]
+            | ["facet", ..]
+            | ["endfacet"]
+            | ["outer", "loop"]
+            | [] => {}
+            [keyword, ..] => warn!("line {}: unknown keyword '{keyword}'", context.line),
+        }
+        context.line += 1;
+    }
+
+    if !polygon.is_empty() {
+        return Err(MeshParseError::unexpected_end(&context, "a facet"));
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_ascii_stl() {
+        let source = "solid pyramid
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid pyramid
+";
+        let mut cursor = std::io::Cursor::new(source);
+        let mesh = Mesh::read_from_stl(&mut cursor, 2).unwrap();
+
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(mesh.triangles[1].p3, Point3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.triangles[1].material_id, 2);
+        assert_eq!(mesh.triangles[1].normals, None);
+
+        let truncated = source.replace("      vertex 0 0 1\n", "");
+        let error = Mesh::read_from_stl(&mut std::io::Cursor::new(truncated), 0)
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "line 13: badly formatted line");
+    }
+
+    #[test]
+    fn load_binary_stl() {
+        // Binary files whose header starts with "solid" must still be read as binary
+        let mut source = b"solid but actually binary".to_vec();
+        source.resize(80, 0);
+        source.extend_from_slice(&1u32.to_le_bytes());
+        for value in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0, 0.0,
+        ] {
+            source.extend_from_slice(&value.to_le_bytes());
+        }
+        source.extend_from_slice(&[0, 0]);
+
+        let mesh = Mesh::read_from_stl(&mut std::io::Cursor::new(&source), 0).unwrap();
+        assert_eq!(mesh.triangles.len(), 1);
+        assert_eq!(mesh.triangles[0].p2, Point3::new(2.0, 0.0, 0.0));
+
+        // Claims two triangles but only holds one
+        source[80] = 2;
+        source[0] = b'x';
+        let error = Mesh::read_from_stl(&mut std::io::Cursor::new(&source), 0)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of file while reading triangle 1"
+        );
+    }
+}
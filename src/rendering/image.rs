@@ -7,7 +7,7 @@ pub enum Error {
     PPMParseError,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
@@ -107,6 +107,52 @@ impl Image {
     }
 }
 
+/// Per-pixel sums of radiance samples, averaged into an [`Image`] on demand.
+/// Used to build up an image over several rendering passes.
+pub struct AccumulationBuffer {
+    pub width: u32,
+    pub height: u32,
+    sums: Vec<Color>,
+    samples_per_pixel: u32,
+}
+
+impl AccumulationBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            sums: vec![Color::BLACK; width as usize * height as usize],
+            samples_per_pixel: 0,
+        }
+    }
+
+    /// Adds the sum of one or more samples to the pixel at `(x, y)`.
+    pub fn accumulate(&mut self, x: u32, y: u32, color: Color) {
+        let index = (y * self.width + x) as usize;
+        self.sums[index] = self.sums[index] + color;
+    }
+
+    /// Marks a pass that added `samples` samples to every pixel as complete.
+    pub fn complete_pass(&mut self, samples: u32) {
+        self.samples_per_pixel += samples;
+    }
+
+    /// The number of samples per pixel accumulated so far.
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    /// Returns the average of the samples accumulated so far.
+    pub fn to_image(&self) -> Image {
+        let scale = 1.0 / self.samples_per_pixel.max(1) as f64;
+        let mut image = Image::new(self.width, self.height);
+        for sum in &self.sums {
+            image.add_pixel(*sum * scale);
+        }
+        image
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{io::Cursor, iter::zip};
@@ -155,6 +201,25 @@ mod tests {
         assert_images_are_close(&read_image, &image);
     }
 
+    #[test]
+    fn accumulation_buffer_averages_passes() {
+        let mut buffer = AccumulationBuffer::new(2, 1);
+
+        buffer.accumulate(0, 0, Color::new(1.0, 0.0, 0.0));
+        buffer.accumulate(1, 0, Color::new(0.0, 0.0, 1.0));
+        buffer.complete_pass(1);
+
+        buffer.accumulate(0, 0, Color::new(0.0, 1.0, 0.0));
+        buffer.accumulate(1, 0, Color::new(0.0, 0.0, 1.0));
+        buffer.complete_pass(1);
+
+        let image = buffer.to_image();
+
+        assert_eq!(buffer.samples_per_pixel(), 2);
+        assert_eq!(image.pixels[0], Color::new(0.5, 0.5, 0.0));
+        assert_eq!(image.pixels[1], Color::new(0.0, 0.0, 1.0));
+    }
+
     #[test]
     fn write_and_read_gradient() {
         let width = 64;
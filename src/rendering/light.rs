@@ -0,0 +1,476 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::math::interval::Interval;
+use crate::math::{AABB, Hit, Intersect, Point3, Ray, Sphere, Triangle, Vec3};
+use crate::rendering::Material;
+use crate::rendering::sampler::Sampler;
+use crate::rendering::sky::SkyBox;
+
+/// A direction towards a light, chosen by [`Light::sample`].
+pub struct LightSample {
+    /// Unit vector from the shaded point towards the light
+    pub direction: Vec3,
+    /// Distance to the sampled point on the light
+    pub distance: f64,
+    /// Radiance arriving at the shaded point, ignoring occlusion
+    pub radiance: Color,
+    /// Probability density of the direction with respect to solid angle
+    pub pdf: f64,
+}
+
+/// A light source that the renderer can sample directly.
+pub trait Light: Send + Sync {
+    /// Picks a direction from `point` towards the light.
+    fn sample(&self, point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample>;
+
+    /// The solid angle density with which `sample` picks the direction from `origin` to `hit`,
+    /// where `hit` lies on the light.
+    fn pdf(&self, origin: Point3, hit: &Hit) -> f64;
+
+    /// Whether the light is described by a delta distribution, such as a point light.
+    /// Such lights can only be reached by sampling them, never by chance.
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// Light arriving from an importance sampled sky.
+pub struct SkyLight {
+    pub sky: Arc<dyn SkyBox>,
+}
+
+impl Light for SkyLight {
+    fn sample(&self, _point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        self.sky.sample(sampler)
+    }
+
+    /// The sky lies beyond all geometry and is never hit, see [`SkyBox::pdf`] instead.
+    fn pdf(&self, _origin: Point3, _hit: &Hit) -> f64 {
+        0.0
+    }
+}
+
+/// Shines equally in all directions from a single point.
+pub struct PointLight {
+    pub position: Point3,
+    /// Radiant intensity, i.e. power per solid angle
+    pub intensity: Color,
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity * (1.0 / (distance * distance)),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _origin: Point3, _hit: &Hit) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// A point light that only shines within a cone around its direction.
+pub struct SpotLight {
+    pub position: Point3,
+    pub direction: Vec3,
+    pub intensity: Color,
+    /// Cosine of the angle within which the light shines at full intensity
+    pub cos_inner: f64,
+    /// Cosine of the angle beyond which the light is dark
+    pub cos_outer: f64,
+}
+
+impl SpotLight {
+    /// Creates a spot light. The cone angles are in degrees.
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self {
+            position,
+            direction: direction.normalized(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    /// The fraction of the intensity emitted at an angle with the given cosine.
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / (distance * distance)),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _origin: Point3, _hit: &Hit) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Parallel light arriving from infinitely far away.
+pub struct DirectionalLight {
+    /// The direction the light travels in
+    pub direction: Vec3,
+    /// Irradiance on a surface facing the light
+    pub irradiance: Color,
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction.normalized(),
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _origin: Point3, _hit: &Hit) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// The surface of an [`AreaLight`], in world space
+// Scenes hold few area lights, boxing triangles would only add an indirection
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum AreaLightShape {
+    Sphere(Sphere),
+    Triangle(Triangle),
+}
+
+impl Intersect for AreaLightShape {
+    fn intersect(&self, ray: &Ray, interval: Interval) -> Option<Hit> {
+        match self {
+            AreaLightShape::Sphere(sphere) => sphere.intersect(ray, interval),
+            AreaLightShape::Triangle(triangle) => triangle.intersect(ray, interval),
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        match self {
+            AreaLightShape::Sphere(sphere) => sphere.bounding_box(),
+            AreaLightShape::Triangle(triangle) => triangle.bounding_box(),
+        }
+    }
+}
+
+/// Geometry with an emissive material.
+pub struct AreaLight {
+    shape: AreaLightShape,
+    material: Arc<dyn Material>,
+}
+
+impl AreaLight {
+    pub fn new(shape: AreaLightShape, material: Arc<dyn Material>) -> Self {
+        Self { shape, material }
+    }
+
+    fn sample_direction(&self, point: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        match &self.shape {
+            AreaLightShape::Sphere(sphere) => sample_sphere(sphere, point, sampler),
+            AreaLightShape::Triangle(triangle) => sample_triangle(triangle, sampler) - point,
+        }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let direction = self.sample_direction(point, sampler);
+        // Find the sampled point on the surface, along with its normal
+        let ray = Ray::new(point, direction);
+        let hit = self
+            .shape
+            .intersect(&ray, Interval::new(1e-6, f64::INFINITY))?;
+
+        let pdf = self.pdf(point, &hit);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: ray.direction,
+            distance: hit.t,
+            radiance: self.material.emitted(&hit),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, origin: Point3, hit: &Hit) -> f64 {
+        match &self.shape {
+            AreaLightShape::Sphere(sphere) => sphere_pdf(sphere, origin, hit),
+            AreaLightShape::Triangle(triangle) => {
+                let area = 0.5
+                    * (triangle.p2 - triangle.p1)
+                        .cross(triangle.p3 - triangle.p1)
+                        .length();
+                area_to_solid_angle(1.0 / area, origin, hit)
+            }
+        }
+    }
+}
+
+/// Converts a density with respect to surface area into one with respect to solid angle as
+/// seen from `origin`.
+fn area_to_solid_angle(pdf: f64, origin: Point3, hit: &Hit) -> f64 {
+    let to_light = hit.point - origin;
+    let distance_squared = to_light.length_squared();
+    let cosine = hit.normal.dot(to_light).abs() / distance_squared.sqrt();
+    if cosine == 0.0 {
+        return 0.0;
+    }
+    pdf * distance_squared / cosine
+}
+
+/// Samples the cone of directions that the sphere subtends as seen from `point`, or the
+/// sphere's surface uniformly if `point` is inside it.
+fn sample_sphere(sphere: &Sphere, point: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+    let to_center = sphere.center - point;
+    let distance_squared = to_center.length_squared();
+    let radius_squared = sphere.radius * sphere.radius;
+
+    if distance_squared <= radius_squared {
+        let on_surface = sphere.center + sphere.radius * sampler.unit_vector();
+        return on_surface - point;
+    }
+
+    let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+    sample_cone(to_center.normalized(), cos_theta_max, sampler)
+}
+
+/// Samples a direction uniformly within the cone around the unit vector `axis` whose half
+/// angle has the cosine `cos_theta_max`.
+pub fn sample_cone(axis: Vec3, cos_theta_max: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let cos_theta = 1.0 - sampler.next_f64() * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * sampler.next_f64();
+
+    let (u, v) = axis.orthonormal_basis();
+    (sin_theta * phi.cos()) * u + (sin_theta * phi.sin()) * v + cos_theta * axis
+}
+
+/// The solid angle density of [`sample_cone`].
+pub fn cone_pdf(cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+fn sphere_pdf(sphere: &Sphere, origin: Point3, hit: &Hit) -> f64 {
+    let distance_squared = (sphere.center - origin).length_squared();
+    let radius_squared = sphere.radius * sphere.radius;
+
+    if distance_squared <= radius_squared {
+        let area = 4.0 * PI * radius_squared;
+        return area_to_solid_angle(1.0 / area, origin, hit);
+    }
+
+    let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+    cone_pdf(cos_theta_max)
+}
+
+/// Samples a point uniformly on the triangle's surface.
+fn sample_triangle(triangle: &Triangle, sampler: &mut dyn Sampler) -> Point3 {
+    let su = sampler.next_f64().sqrt();
+    let b0 = 1.0 - su;
+    let b1 = sampler.next_f64() * su;
+    let b2 = 1.0 - b0 - b1;
+    Point3(b0 * triangle.p1.0 + b1 * triangle.p2.0 + b2 * triangle.p3.0)
+}
+
+/// Weight for combining two sampling strategies, as per Veach's power heuristic.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::rendering::sampler::RandomSampler;
+
+    fn light(shape: AreaLightShape) -> AreaLight {
+        let material = Arc::new(DiffuseLight::new(Color::WHITE, 2.0));
+        AreaLight::new(shape, material)
+    }
+
+    /// Integrates the light's pdf over the sphere of directions by uniform sampling
+    fn integrate_pdf(light: &AreaLight, origin: Point3, sampler: &mut dyn Sampler) -> f64 {
+        let n = 1_000_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let direction = sampler.unit_vector();
+            let ray = Ray::new(origin, direction);
+            if let Some(hit) = light
+                .shape
+                .intersect(&ray, Interval::new(1e-6, f64::INFINITY))
+            {
+                sum += light.pdf(origin, &hit);
+            }
+        }
+        sum * 4.0 * PI / n as f64
+    }
+
+    #[test]
+    fn sphere_light_samples_hit_sphere() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = light(AreaLightShape::Sphere(Sphere {
+            center: Point3::new(0.0, 5.0, 0.0),
+            radius: 1.0,
+            material_id: 0,
+        }));
+
+        for _ in 0..100 {
+            let sample = light.sample(Point3::ORIGIN, &mut sampler).unwrap();
+            assert!(sample.distance >= 4.0 - 1e-9 && sample.distance <= 5.0);
+            assert!(sample.pdf > 0.0);
+            assert_eq!(sample.radiance, Color::new(2.0, 2.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn sphere_light_pdf_integrates_to_one() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = light(AreaLightShape::Sphere(Sphere {
+            center: Point3::new(0.0, 3.0, 0.0),
+            radius: 1.0,
+            material_id: 0,
+        }));
+
+        let integral = integrate_pdf(&light, Point3::ORIGIN, &mut sampler);
+        assert!((integral - 1.0).abs() < 0.02, "integral was {integral}");
+    }
+
+    #[test]
+    fn triangle_light_pdf_integrates_to_one() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = light(AreaLightShape::Triangle(Triangle::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Point3::new(1.0, 2.0, -1.0),
+            Point3::new(0.0, 2.0, 1.0),
+            0,
+        )));
+
+        let integral = integrate_pdf(&light, Point3::ORIGIN, &mut sampler);
+        assert!((integral - 1.0).abs() < 0.02, "integral was {integral}");
+    }
+
+    #[test]
+    fn point_light_falls_off_with_distance_squared() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = PointLight {
+            position: Point3::new(0.0, 4.0, 0.0),
+            intensity: Color::new(16.0, 16.0, 16.0),
+        };
+
+        let sample = light.sample(Point3::ORIGIN, &mut sampler).unwrap();
+
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 4.0);
+        assert_eq!(sample.radiance, Color::WHITE);
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Color::WHITE,
+            20.0,
+            40.0,
+        );
+
+        // Inside the inner cone
+        let inner = light.sample(Point3::ORIGIN, &mut sampler).unwrap();
+        assert_eq!(inner.radiance, Color::WHITE);
+
+        // Between the cones, 30 degrees off axis
+        let between = light
+            .sample(
+                Point3::new(30f64.to_radians().tan(), 0.0, 0.0),
+                &mut sampler,
+            )
+            .unwrap();
+        assert!(between.radiance.r > 0.0 && between.radiance.r < 1.0);
+
+        // Outside the outer cone
+        assert!(
+            light
+                .sample(Point3::new(1.0, 0.0, 0.0), &mut sampler)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn directional_light_points_against_its_direction() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let light = DirectionalLight {
+            direction: Vec3::new(0.0, -2.0, 0.0),
+            irradiance: Color::WHITE,
+        };
+
+        let sample = light.sample(Point3::ORIGIN, &mut sampler).unwrap();
+
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, f64::INFINITY);
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        let (a, b) = (0.3, 1.7);
+        assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-12);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+}
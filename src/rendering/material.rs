@@ -1,13 +1,19 @@
 use std::collections::HashMap;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 use log::warn;
 
 use crate::color::Color;
 use crate::math::Ray;
-use crate::math::{Hit, Vec3};
+use crate::math::{Hit, Normal3, Vec3};
+use crate::rendering::microfacet::{
+    Ggx, ShadingFrame, fresnel_conductor, reflect, refract, sample_cosine_hemisphere, schlick,
+};
 use crate::rendering::sampler::Sampler;
+use crate::rendering::texture::{SolidColor, Texture};
 
+#[derive(Clone)]
 pub struct MaterialLibrary {
     materials: Vec<Arc<dyn Material>>,
     material_name_to_id: HashMap<String, usize>,
@@ -44,6 +50,11 @@ impl MaterialLibrary {
         self.materials.push(material)
     }
 
+    /// Whether a material with the given name has been registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.material_name_to_id.contains_key(name)
+    }
+
     /// Lookup a material's ID by it's name.
     /// The ID corresponding to the fallback maratial is returned if a no material with the
     /// specified name exists.
@@ -54,6 +65,14 @@ impl MaterialLibrary {
         })
     }
 
+    /// The name a material was registered under, `None` for the fallback material.
+    pub fn material_name(&self, id: usize) -> Option<&str> {
+        self.material_name_to_id
+            .iter()
+            .find(|(_, material_id)| **material_id == id)
+            .map(|(name, _)| name.as_str())
+    }
+
     /// Lookup a material by it's ID.
     /// The fallback material is returned if no material with the given ID exists.
     pub fn lookup_material(&self, id: usize) -> Arc<dyn Material> {
@@ -64,7 +83,13 @@ impl MaterialLibrary {
     }
 }
 
-pub trait Material {
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait Material: Send + Sync {
     /// Returns the scannered ray and the color attenuation.
     /// If none, then incoming ray has been absorbed by the material.
     fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)>;
@@ -73,6 +98,26 @@ pub trait Material {
     fn emitted(&self, _hit: &Hit) -> Color {
         Color::BLACK
     }
+
+    /// Evaluates the material for light arriving from `direction` and leaving towards the
+    /// origin of `ray`.
+    /// Returns the scattering function times the cosine term, together with the probability
+    /// density with which `scatter` picks `direction`.
+    /// Materials that cannot be evaluated for arbitrary directions, such as mirrors, return
+    /// `None` and are skipped when sampling lights directly.
+    fn evaluate(&self, _ray: Ray, _hit: &Hit, _direction: Vec3) -> Option<(Color, f64)> {
+        None
+    }
+
+    /// Whether geometry with this material should be sampled as a light source.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+/// The color of a material's texture at the hit point, tinted by the vertex color
+fn albedo_at(texture: &Arc<dyn Texture>, hit: &Hit) -> Color {
+    texture.value(hit.u, hit.v, hit.point) * hit.color
 }
 
 /// Dummy material that absorbs all light
@@ -85,11 +130,15 @@ impl Material for DummyMaterial {
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor { color: albedo }))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
@@ -98,13 +147,27 @@ impl Material for Lambertian {
     fn scatter(&self, _ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         // If we get a random direction directly opposite the normal bad things can happen
         let scatter_direction = {
-            let d = hit.normal.0 + sampler.unit_vector();
-            if d.is_near_zero() { hit.normal.0 } else { d }
+            let d = hit.shading_normal.0 + sampler.unit_vector();
+            if d.is_near_zero() {
+                hit.shading_normal.0
+            } else {
+                d
+            }
         };
 
         let scattered = Ray::new(hit.point, scatter_direction);
         // We attenuate by the albedo
-        Some((self.albedo, scattered))
+        Some((albedo_at(&self.albedo, hit), scattered))
+    }
+
+    fn evaluate(&self, _ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        // Scattering around the normal is cosine weighted, so the cosine terms cancel out
+        let cosine = direction.normalized().dot(hit.shading_normal.0);
+        if cosine <= 0.0 {
+            return Some((Color::BLACK, 0.0));
+        }
+        let albedo = albedo_at(&self.albedo, hit);
+        Some((albedo * (cosine / PI), cosine / PI))
     }
 }
 
@@ -116,45 +179,278 @@ impl Material for NormalVisualizer {
     }
 
     fn emitted(&self, hit: &Hit) -> Color {
-        Color::from(0.5 * (hit.normal.0 + Vec3::ONES))
+        Color::from(0.5 * (hit.shading_normal.0 + Vec3::ONES))
     }
 }
 
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::textured(Arc::new(SolidColor { color: albedo }), fuzz)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Self { albedo, fuzz }
     }
 }
 
 impl Material for Metal {
     fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
-        let reflected =
-            ray.direction.reflect(hit.normal).normalized() + (self.fuzz * sampler.unit_vector());
+        let reflected = ray.direction.reflect(hit.shading_normal).normalized()
+            + (self.fuzz * sampler.unit_vector());
         let scattered = Ray::new(hit.point, reflected);
 
         // Absorb the ray we scatter below the surface
         if scattered.direction.dot(hit.normal.0) > 0.0 {
-            Some((self.albedo, scattered))
+            Some((albedo_at(&self.albedo, hit), scattered))
         } else {
             None
         }
     }
 }
 
-/// Dielectric material (glass, water, etc.) that refracts and reflects light
+/// A metallic-roughness material in the style of Disney's principled BRDF: a GGX specular
+/// lobe over a diffuse base. Metals tint their reflections with the base color and have no
+/// diffuse part, dielectrics reflect `specular` times 8% of the light head-on.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64, specular: f64) -> Self {
+        Self::textured(
+            Arc::new(SolidColor { color: base_color }),
+            metallic,
+            roughness,
+            specular,
+        )
+    }
+
+    pub fn textured(
+        base_color: Arc<dyn Texture>,
+        metallic: f64,
+        roughness: f64,
+        specular: f64,
+    ) -> Self {
+        Self {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            specular: specular.max(0.0),
+        }
+    }
+
+    /// The reflectance at normal incidence
+    fn f0(&self, base_color: Color) -> Color {
+        let dielectric = Color::new(1.0, 1.0, 1.0) * (0.08 * self.specular);
+        dielectric * (1.0 - self.metallic) + base_color * self.metallic
+    }
+
+    /// How often `scatter` samples the specular lobe rather than the diffuse one, roughly in
+    /// proportion to the light each reflects towards `wo`
+    fn specular_probability(&self, base_color: Color, wo: Vec3) -> f64 {
+        let specular = schlick(self.f0(base_color), wo.z).luminance();
+        let diffuse = (1.0 - self.metallic) * base_color.luminance();
+        if specular + diffuse > 0.0 {
+            specular / (specular + diffuse)
+        } else {
+            1.0
+        }
+    }
+
+    /// The value and pdf of `evaluate` in shading space
+    fn evaluate_local(&self, base_color: Color, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (Color::BLACK, 0.0);
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = (wo + wi).normalized();
+        let fresnel = schlick(self.f0(base_color), wo.dot(h));
+
+        let specular = fresnel * (ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z * wi.z));
+        // The diffuse base only receives the light the specular layer lets through
+        let diffuse = (Color::WHITE - fresnel) * base_color * ((1.0 - self.metallic) / PI);
+
+        let specular_probability = self.specular_probability(base_color, wo);
+        let pdf = specular_probability * ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h))
+            + (1.0 - specular_probability) * wi.z / PI;
+        ((specular + diffuse) * wi.z, pdf)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let base_color = albedo_at(&self.base_color, hit);
+        let wi = if sampler.next_f64() < self.specular_probability(base_color, wo) {
+            let h = Ggx::from_roughness(self.roughness).sample_visible_normal(wo, sampler);
+            reflect(wo, h)
+        } else {
+            sample_cosine_hemisphere(sampler)
+        };
+
+        let direction = frame.to_world(wi);
+        // Absorb rays that would leave below the surface
+        if direction.dot(hit.normal.0) <= 0.0 {
+            return None;
+        }
+        let (value, pdf) = self.evaluate_local(base_color, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((value * (1.0 / pdf), Ray::new(hit.point, direction)))
+    }
+
+    fn evaluate(&self, ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(direction.normalized());
+        let base_color = albedo_at(&self.base_color, hit);
+        Some(self.evaluate_local(base_color, wo, wi))
+    }
+}
+
+/// Metals with a measured complex index of refraction, averaged over the red, green and blue
+/// parts of the spectrum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConductorPreset {
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+    Chromium,
+    Iron,
+}
+
+impl ConductorPreset {
+    /// The real part `eta` and the imaginary part `k` of the index of refraction
+    pub fn ior(self) -> (Color, Color) {
+        let (eta, k) = match self {
+            ConductorPreset::Gold => ([0.143, 0.375, 1.442], [3.983, 2.386, 1.603]),
+            ConductorPreset::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            ConductorPreset::Copper => ([0.200, 0.924, 1.102], [3.913, 2.453, 2.142]),
+            ConductorPreset::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            ConductorPreset::Chromium => ([4.370, 2.917, 1.655], [5.206, 4.231, 3.755]),
+            ConductorPreset::Iron => ([2.911, 2.950, 2.585], [3.089, 2.932, 2.768]),
+        };
+        (
+            Color::new(eta[0], eta[1], eta[2]),
+            Color::new(k[0], k[1], k[2]),
+        )
+    }
+}
+
+/// A metal whose color comes from its complex index of refraction through the exact Fresnel
+/// equations, with GGX microfacets. Anisotropic metals look brushed, with highlights
+/// stretched along the direction in which the texture coordinate `u` grows, or along
+/// horizontal circles around the vertical axis on surfaces without texture coordinates.
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+    pub anisotropy: f64,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        Self {
+            eta,
+            k,
+            roughness,
+            anisotropy,
+        }
+    }
+
+    pub fn preset(preset: ConductorPreset, roughness: f64, anisotropy: f64) -> Self {
+        let (eta, k) = preset.ior();
+        Self::new(eta, k, roughness, anisotropy)
+    }
+
+    fn ggx(&self) -> Ggx {
+        Ggx::anisotropic(self.roughness, self.anisotropy)
+    }
+
+    fn frame(hit: &Hit) -> ShadingFrame {
+        let normal = hit.shading_normal.0;
+        let tangent = match hit.tangents {
+            Some([dpdu, _]) => dpdu,
+            None => Vec3::new(0.0, 1.0, 0.0).cross(normal),
+        };
+        ShadingFrame::with_tangent(normal, tangent)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = self.ggx();
+        let h = ggx.sample_visible_normal(wo, sampler);
+        let wi = reflect(wo, h);
+        let direction = frame.to_world(wi);
+        if wi.z <= 0.0 || direction.dot(hit.normal.0) <= 0.0 {
+            return None;
+        }
+
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        let attenuation = fresnel * (ggx.g(wo, wi) / ggx.g1(wo));
+        Some((attenuation, Ray::new(hit.point, direction)))
+    }
+
+    fn evaluate(&self, ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(direction.normalized());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Some((Color::BLACK, 0.0));
+        }
+        let ggx = self.ggx();
+        let h = (wo + wi).normalized();
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        let value = fresnel * (ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z));
+        let pdf = ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h));
+        Some((value, pdf))
+    }
+}
+
+/// Dielectric material (glass, water, etc.) that refracts and reflects light.
+/// Rough dielectrics scatter through GGX microfacets, giving frosted glass. Light travelling
+/// through the material is absorbed following the Beer-Lambert law: after
+/// `absorption_distance` only `absorption_color` of it is left.
 pub struct Dielectric {
     pub index_of_refraction: f64,
+    pub roughness: f64,
+    pub absorption_color: Color,
+    pub absorption_distance: f64,
+    /// Treats every surface as a thin sheet with air on both sides, like a window pane. Light
+    /// passes through without bending and is tinted by `absorption_color` once.
+    pub thin_walled: bool,
 }
 
 impl Dielectric {
+    /// Smooth and clear
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             index_of_refraction,
+            roughness: 0.0,
+            absorption_color: Color::WHITE,
+            absorption_distance: 1.0,
+            thin_walled: false,
         }
     }
 
@@ -165,10 +461,22 @@ impl Dielectric {
         let r0 = r0 * r0;
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+    /// The fraction of light left after travelling `distance` through the material
+    fn transmittance(&self, distance: f64) -> Color {
+        if self.absorption_distance <= 0.0 {
+            return Color::WHITE;
+        }
+        let exponent = distance / self.absorption_distance;
+        let c = self.absorption_color;
+        Color::new(
+            c.r.max(0.0).powf(exponent),
+            c.g.max(0.0).powf(exponent),
+            c.b.max(0.0).powf(exponent),
+        )
+    }
+
+    fn scatter_smooth(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Ray {
         // Determine the refractive indices based on whether we're entering or exiting the material
         let (eta_i, eta_t) = if hit.front_face {
             // Ray is entering the material (air -> dielectric)
@@ -183,7 +491,8 @@ impl Material for Dielectric {
 
         // Calculate the cosine of the incident angle
         // cos(theta_i) = dot(-incident, normal)
-        let cos_theta = -ray.direction.dot(hit.normal.0).min(1.0);
+        let normal = hit.shading_normal.0;
+        let cos_theta = -ray.direction.dot(normal).min(1.0);
 
         // Calculate the sine of the incident angle using Snell's law
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
@@ -198,31 +507,128 @@ impl Material for Dielectric {
         // Decide whether to reflect or refract based on reflectance
         let should_reflect = cannot_refract || sampler.next_f64() < reflectance;
 
-        let scattered = if should_reflect {
+        if should_reflect {
             // Reflect the ray
-            let reflected = ray.direction.reflect(hit.normal);
+            let reflected = ray.direction.reflect(hit.shading_normal);
             Ray::new(hit.point, reflected)
         } else {
             // Refract the ray using Snell's law
             // Calculate the direction of the refracted ray
-            let r_out_perp = eta_ratio * (ray.direction + cos_theta * hit.normal.0);
-            let r_out_parallel = -(1.0 - r_out_perp.length_squared()).sqrt() * hit.normal.0;
+            let r_out_perp = eta_ratio * (ray.direction + cos_theta * normal);
+            let r_out_parallel = -(1.0 - r_out_perp.length_squared()).sqrt() * normal;
             Ray::new(hit.point, r_out_perp + r_out_parallel)
+        }
+    }
+
+    /// Reflects or refracts through a microfacet picked from the GGX distribution, Walter et
+    /// al., "Microfacet Models for Refraction through Rough Surfaces". Returns the scattered
+    /// ray and its weight.
+    fn scatter_rough(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(f64, Ray)> {
+        let eta_ratio = if hit.front_face {
+            1.0 / self.index_of_refraction
+        } else {
+            self.index_of_refraction
+        };
+
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = ggx.sample_visible_normal(wo, sampler);
+
+        let refracted = refract(wo, h, eta_ratio);
+        let reflectance = match refracted {
+            Some(_) => self.reflectance(wo.dot(h), eta_ratio),
+            None => 1.0,
+        };
+        let (wi, reflected) = match refracted {
+            Some(refracted) if sampler.next_f64() >= reflectance => (refracted, false),
+            _ => (reflect(wo, h), true),
         };
 
-        // Dielectric materials don't absorb light (color is white/1.0)
-        // But we can still return a color if desired (usually white for glass)
-        Some((Color::new(1.0, 1.0, 1.0), scattered))
+        let direction = frame.to_world(wi);
+        // Microfacets can send the ray to the wrong side of the surface
+        if (direction.dot(hit.normal.0) > 0.0) != reflected {
+            return None;
+        }
+        // Sampling the microfacet in proportion to its visibility and choosing between the
+        // two sides by reflectance leaves only the masking of the scattered direction
+        Some((ggx.g(wo, wi) / ggx.g1(wo), Ray::new(hit.point, direction)))
+    }
+
+    /// Reflects off or passes straight through a sheet, counting the light that bounces
+    /// between its two sides
+    fn scatter_thin(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let eta_ratio = 1.0 / self.index_of_refraction;
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let ggx = (self.roughness > 0.0).then(|| Ggx::from_roughness(self.roughness));
+        let h = match ggx {
+            Some(ggx) => ggx.sample_visible_normal(wo, sampler),
+            None => Vec3::new(0.0, 0.0, 1.0),
+        };
+        let reflectance = self.reflectance(wo.dot(h), eta_ratio);
+        let reflectance = 2.0 * reflectance / (1.0 + reflectance);
+
+        let mut wi = reflect(wo, h);
+        let mut attenuation = Color::WHITE;
+        let reflected = sampler.next_f64() < reflectance;
+        if !reflected {
+            // Both sides bend the light by the same angle in opposite directions
+            wi = Vec3::new(wi.x, wi.y, -wi.z);
+            attenuation = self.absorption_color;
+        }
+
+        let direction = frame.to_world(wi);
+        if (direction.dot(hit.normal.0) > 0.0) != reflected {
+            return None;
+        }
+        if let Some(ggx) = ggx {
+            attenuation = attenuation * (ggx.g(wo, wi) / ggx.g1(wo));
+        }
+        Some((attenuation, Ray::new(hit.point, direction)))
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        if self.thin_walled {
+            return self.scatter_thin(ray, hit, sampler);
+        }
+
+        let (weight, scattered) = if self.roughness > 0.0 {
+            self.scatter_rough(ray, hit, sampler)?
+        } else {
+            (1.0, self.scatter_smooth(ray, hit, sampler))
+        };
+
+        // A ray leaving the material has travelled through it since it last scattered
+        let attenuation = if hit.front_face {
+            Color::WHITE
+        } else {
+            self.transmittance(hit.t)
+        };
+        Some((attenuation * weight, scattered))
     }
 }
 
 pub struct DiffuseLight {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
     pub strength: f64,
 }
 
 impl DiffuseLight {
     pub fn new(albedo: Color, strength: f64) -> Self {
+        Self::textured(Arc::new(SolidColor { color: albedo }), strength)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, strength: f64) -> Self {
         Self { albedo, strength }
     }
 }
@@ -231,8 +637,326 @@ impl Material for DiffuseLight {
     fn scatter(&self, _ray: Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         None
     }
-    fn emitted(&self, _hit: &Hit) -> Color {
-        self.albedo * self.strength
+    fn emitted(&self, hit: &Hit) -> Color {
+        albedo_at(&self.albedo, hit) * self.strength
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// Blends two materials by a factor, taken from the brightness of a texture so that a mask can
+/// decide where each material shows. A factor of 0 is all `first`, 1 is all `second`.
+pub struct Mix {
+    pub first: Arc<dyn Material>,
+    pub second: Arc<dyn Material>,
+    pub factor: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(first: Arc<dyn Material>, second: Arc<dyn Material>, factor: f64) -> Self {
+        let factor = Color::WHITE * factor;
+        Self::textured(first, second, Arc::new(SolidColor { color: factor }))
+    }
+
+    pub fn textured(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        factor: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            factor,
+        }
+    }
+
+    fn factor_at(&self, hit: &Hit) -> f64 {
+        self.factor
+            .value(hit.u, hit.v, hit.point)
+            .luminance()
+            .clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    /// Scatters off one of the two materials, picked with the probability of its share
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let material = if sampler.next_f64() < self.factor_at(hit) {
+            &self.second
+        } else {
+            &self.first
+        };
+        let (attenuation, scattered) = material.scatter(ray, hit, sampler)?;
+
+        // With both materials known for every direction, weigh the sample against the chance of
+        // either material picking it, as `evaluate` does
+        match self.evaluate(ray, hit, scattered.direction) {
+            Some((value, pdf)) if pdf > 0.0 => Some((value * (1.0 / pdf), scattered)),
+            Some(_) => None,
+            None => Some((attenuation, scattered)),
+        }
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        let factor = self.factor_at(hit);
+        self.first.emitted(hit) * (1.0 - factor) + self.second.emitted(hit) * factor
+    }
+
+    fn evaluate(&self, ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        let factor = self.factor_at(hit);
+        let (first, first_pdf) = self.first.evaluate(ray, hit, direction)?;
+        let (second, second_pdf) = self.second.evaluate(ray, hit, direction)?;
+        Some((
+            first * (1.0 - factor) + second * factor,
+            first_pdf * (1.0 - factor) + second_pdf * factor,
+        ))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.first.is_emissive() || self.second.is_emissive()
+    }
+}
+
+/// A clear coat of varnish or lacquer over another material. The coat reflects a GGX lobe
+/// with the Fresnel reflectance of a dielectric, and the base only receives the light the coat
+/// lets through, tinted by `tint` on the way in and out.
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub index_of_refraction: f64,
+    pub roughness: f64,
+    /// The color of white light that went through the coat head-on, to the base and back
+    pub tint: Color,
+}
+
+impl Coated {
+    /// The least often the coat is sampled, so that its highlights don't get noisy where it
+    /// barely reflects
+    const MIN_COAT_PROBABILITY: f64 = 0.25;
+
+    /// A clear, smooth coat
+    pub fn new(base: Arc<dyn Material>, index_of_refraction: f64) -> Self {
+        Self {
+            base,
+            index_of_refraction,
+            roughness: 0.0,
+            tint: Color::WHITE,
+        }
+    }
+
+    fn fresnel(&self, cosine: f64) -> f64 {
+        let r0 = (self.index_of_refraction - 1.0) / (self.index_of_refraction + 1.0);
+        schlick(Color::WHITE * (r0 * r0), cosine).r
+    }
+
+    fn coat_probability(&self, wo: Vec3) -> f64 {
+        self.fresnel(wo.z).max(Self::MIN_COAT_PROBABILITY)
+    }
+
+    /// The fraction of the light reaching the base from `wi` that leaves towards `wo`
+    fn transmittance(&self, wo: Vec3, wi: Vec3) -> Color {
+        let (cos_o, cos_i) = (wo.z.abs().max(0.05), wi.z.abs().max(0.05));
+        let exponent = 0.5 * (1.0 / cos_o + 1.0 / cos_i);
+        let c = self.tint;
+        let absorbed = Color::new(
+            c.r.max(0.0).powf(exponent),
+            c.g.max(0.0).powf(exponent),
+            c.b.max(0.0).powf(exponent),
+        );
+        absorbed * ((1.0 - self.fresnel(cos_o)) * (1.0 - self.fresnel(cos_i)))
+    }
+
+    /// The value and pdf of the coat's reflection in shading space
+    fn evaluate_coat(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (Color::BLACK, 0.0);
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = (wo + wi).normalized();
+        let fresnel = self.fresnel(wo.dot(h));
+        let value = Color::WHITE * (fresnel * ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z));
+        let pdf = ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h));
+        (value, pdf)
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction.normalized());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let coat_probability = self.coat_probability(wo);
+        let from_coat = sampler.next_f64() < coat_probability;
+        let (attenuation, scattered) = if from_coat {
+            let h = Ggx::from_roughness(self.roughness).sample_visible_normal(wo, sampler);
+            let direction = frame.to_world(reflect(wo, h));
+            // Absorb rays that would leave below the surface
+            if direction.dot(hit.normal.0) <= 0.0 {
+                return None;
+            }
+            let (value, pdf) = self.evaluate_coat(wo, reflect(wo, h));
+            if pdf <= 0.0 {
+                return None;
+            }
+            (
+                value * (1.0 / (coat_probability * pdf)),
+                Ray::new(hit.point, direction),
+            )
+        } else {
+            let (attenuation, scattered) = self.base.scatter(ray, hit, sampler)?;
+            let wi = frame.to_local(scattered.direction.normalized());
+            let attenuation = attenuation * self.transmittance(wo, wi);
+            (attenuation * (1.0 / (1.0 - coat_probability)), scattered)
+        };
+
+        // With the base known for every direction, weigh the sample against the chance of
+        // either layer picking it, as `evaluate` does
+        match self.evaluate(ray, hit, scattered.direction) {
+            Some((value, pdf)) if pdf > 0.0 => Some((value * (1.0 / pdf), scattered)),
+            Some(_) => None,
+            None => Some((attenuation, scattered)),
+        }
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        self.base.emitted(hit) * (1.0 - self.fresnel(1.0))
+    }
+
+    fn evaluate(&self, ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        let (base, base_pdf) = self.base.evaluate(ray, hit, direction)?;
+        let frame = ShadingFrame::new(hit.shading_normal.0);
+        let wo = frame.to_local(-ray.direction.normalized());
+        let wi = frame.to_local(direction.normalized());
+        let (coat, coat_pdf) = self.evaluate_coat(wo, wi);
+
+        let coat_probability = self.coat_probability(wo);
+        Some((
+            coat + base * self.transmittance(wo, wi),
+            coat_probability * coat_pdf + (1.0 - coat_probability) * base_pdf,
+        ))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+}
+
+/// Adds detail to another material's surface without changing its geometry, by tilting the
+/// shading normal with a tangent-space normal map, a height map or both. Surfaces without
+/// texture coordinates to orient the maps by are left as they are.
+pub struct NormalMapped {
+    pub material: Arc<dyn Material>,
+    /// Red, green and blue hold the x, y and z of the normal, mapped from [-1, 1] to [0, 1].
+    /// x points where `u` grows, y where `v` grows and z away from the surface.
+    pub normal_map: Option<Arc<dyn Texture>>,
+    /// Scales the tilt of the normal map's normals
+    pub normal_strength: f64,
+    /// A height map, whose brightness lifts the surface by up to `bump_height`
+    pub bump_map: Option<Arc<dyn Texture>>,
+    pub bump_height: f64,
+}
+
+impl NormalMapped {
+    /// How far apart, in texture coordinates, the height map is sampled to find its slope
+    const BUMP_DELTA: f64 = 1e-3;
+
+    /// Leaves `material` as it is until maps are added
+    pub fn new(material: Arc<dyn Material>) -> Self {
+        Self {
+            material,
+            normal_map: None,
+            normal_strength: 1.0,
+            bump_map: None,
+            bump_height: 0.0,
+        }
+    }
+
+    /// `hit` with its shading normal tilted by the maps
+    fn perturb(&self, hit: &Hit) -> Hit {
+        let mut hit = hit.clone();
+        let Some([mut dpdu, mut dpdv]) = hit.tangents else {
+            return hit;
+        };
+        // Bumps rise from the outside of the surface, whichever side it is seen from
+        let side = if hit.front_face { 1.0 } else { -1.0 };
+        let mut normal = hit.shading_normal.0 * side;
+        // Triangle tangents are flat; keep them in the plane of the interpolated normal so bumps
+        // don't bring the facets back
+        dpdu = dpdu - normal * normal.dot(dpdu);
+        dpdv = dpdv - normal * normal.dot(dpdv);
+
+        if let Some(bump_map) = &self.bump_map {
+            let height = |du: f64, dv: f64| {
+                let point = hit.point + dpdu * du + dpdv * dv;
+                bump_map.value(hit.u + du, hit.v + dv, point).luminance() * self.bump_height
+            };
+            let base = height(0.0, 0.0);
+            let dhdu = (height(Self::BUMP_DELTA, 0.0) - base) / Self::BUMP_DELTA;
+            let dhdv = (height(0.0, Self::BUMP_DELTA) - base) / Self::BUMP_DELTA;
+
+            dpdu = dpdu + normal * dhdu;
+            dpdv = dpdv + normal * dhdv;
+            // The cross product follows the handedness of the texture coordinates
+            let bumped = dpdu.cross(dpdv);
+            if bumped.length_squared() > 0.0 {
+                let bumped = bumped.normalized();
+                normal = if bumped.dot(normal) < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                };
+            }
+        }
+
+        if let Some(normal_map) = &self.normal_map {
+            let frame = ShadingFrame::with_tangent(normal, dpdu);
+            // The bitangent follows `v`, even where the texture coordinates are mirrored
+            let handedness = if frame.to_local(dpdv).y < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            let c = normal_map.value(hit.u, hit.v, hit.point);
+            let local = Vec3::new(
+                (2.0 * c.r - 1.0) * self.normal_strength,
+                (2.0 * c.g - 1.0) * self.normal_strength * handedness,
+                2.0 * c.b - 1.0,
+            );
+            if local.length_squared() > 0.0 {
+                normal = frame.to_world(local.normalized());
+            }
+        }
+
+        // Keep the shading normal on the side of the surface the ray comes from
+        let mut normal = normal * side;
+        let lift = 0.01 - normal.dot(hit.normal.0);
+        if lift > 0.0 {
+            normal = (normal + hit.normal.0 * lift).normalized();
+        }
+        hit.shading_normal = Normal3(normal);
+        hit
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        self.material.scatter(ray, &self.perturb(hit), sampler)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        self.material.emitted(&self.perturb(hit))
+    }
+
+    fn evaluate(&self, ray: Ray, hit: &Hit, direction: Vec3) -> Option<(Color, f64)> {
+        self.material.evaluate(ray, &self.perturb(hit), direction)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
     }
 }
 
@@ -255,9 +979,16 @@ mod tests {
         let hit = Hit {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
             t: 1.0,
             front_face: true,
             material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
         };
 
         // Ray coming from air (eta_i = 1.0) hitting glass (eta_t = 1.5)
@@ -298,9 +1029,16 @@ mod tests {
         let hit = Hit {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
             t: 1.0,
             front_face: true,
             material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
         };
 
         // Ray coming from air hitting glass at a steep angle
@@ -332,9 +1070,16 @@ mod tests {
         let hit = Hit {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
             t: 1.0,
             front_face: false, // Exiting the material
             material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
         };
 
         // Ray coming from glass (eta_i = 1.5) exiting to air (eta_t = 1.0)
@@ -379,6 +1124,238 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_lambertian_evaluate_matches_scatter() {
+        // scatter() attenuates by the albedo, which must equal the evaluated value over the pdf
+        let lambertian = Lambertian::new(Color::new(0.5, 0.25, 1.0));
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+
+        let hit = Hit {
+            point: Point3::new(0.0, 0.0, 0.0),
+            normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            front_face: true,
+            material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
+        };
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let (attenuation, scattered) = lambertian.scatter(ray, &hit, &mut sampler).unwrap();
+        let (value, pdf) = lambertian.evaluate(ray, &hit, scattered.direction).unwrap();
+
+        assert!((value.r / pdf - attenuation.r).abs() < 1e-9);
+        assert!((value.g / pdf - attenuation.g).abs() < 1e-9);
+        assert!((value.b / pdf - attenuation.b).abs() < 1e-9);
+
+        // Light from below the surface is not scattered
+        let (value, pdf) = lambertian
+            .evaluate(ray, &hit, Vec3::new(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(value, Color::BLACK);
+        assert_eq!(pdf, 0.0);
+    }
+
+    fn hit_facing_up() -> Hit {
+        Hit {
+            point: Point3::new(0.0, 0.0, 0.0),
+            normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            front_face: true,
+            material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
+        }
+    }
+
+    #[test]
+    fn test_principled_evaluate_matches_scatter() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+
+        for (metallic, roughness) in [(0.0, 0.5), (1.0, 0.2), (0.5, 0.9)] {
+            let material = Principled::new(Color::new(0.8, 0.5, 0.2), metallic, roughness, 0.5);
+            for _ in 0..100 {
+                let Some((attenuation, scattered)) = material.scatter(ray, &hit, &mut sampler)
+                else {
+                    continue;
+                };
+                let (value, pdf) = material.evaluate(ray, &hit, scattered.direction).unwrap();
+                assert!(pdf > 0.0);
+                assert!((value.r / pdf - attenuation.r).abs() < 1e-9);
+                assert!((value.g / pdf - attenuation.g).abs() < 1e-9);
+                assert!((value.b / pdf - attenuation.b).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_principled_conserves_energy() {
+        // A white material reflects at most all of the light, and a smooth metal nearly all
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 0.0, -2.0));
+
+        let albedo = |material: &Principled, sampler: &mut dyn Sampler| {
+            let n = 20_000;
+            let sum: f64 = (0..n)
+                .filter_map(|_| material.scatter(ray, &hit, sampler))
+                .map(|(attenuation, _)| attenuation.luminance())
+                .sum();
+            sum / n as f64
+        };
+
+        for metallic in [0.0, 1.0] {
+            for roughness in [0.05, 0.5, 1.0] {
+                let material = Principled::new(Color::WHITE, metallic, roughness, 0.5);
+                let reflected = albedo(&material, &mut sampler);
+                assert!(reflected <= 1.01, "{metallic} {roughness}: {reflected}");
+            }
+        }
+        let polished = Principled::new(Color::WHITE, 1.0, 0.05, 0.5);
+        assert!(albedo(&polished, &mut sampler) > 0.98);
+    }
+
+    #[test]
+    fn test_conductor_evaluate_matches_scatter() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        // Tilted, so that brushing has a direction
+        let hit = Hit {
+            normal: Normal3::new(0.6, 0.8, 0.0),
+            shading_normal: Normal3::new(0.6, 0.8, 0.0),
+            ..hit_facing_up()
+        };
+        let ray = Ray::new(Point3::new(-1.0, 1.0, 0.5), Vec3::new(1.0, -1.0, -0.5));
+
+        for anisotropy in [0.0, 0.9] {
+            let material = Conductor::preset(ConductorPreset::Copper, 0.4, anisotropy);
+            for _ in 0..100 {
+                let Some((attenuation, scattered)) = material.scatter(ray, &hit, &mut sampler)
+                else {
+                    continue;
+                };
+                let (value, pdf) = material.evaluate(ray, &hit, scattered.direction).unwrap();
+                assert!((value.r / pdf - attenuation.r).abs() < 1e-9);
+                assert!((value.g / pdf - attenuation.g).abs() < 1e-9);
+                assert!((value.b / pdf - attenuation.b).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conductor_presets_are_colored() {
+        // Gold and copper reflect red light best, silver is nearly neutral
+        let reflectance = |preset: ConductorPreset| {
+            let (eta, k) = preset.ior();
+            fresnel_conductor(1.0, eta, k)
+        };
+        let gold = reflectance(ConductorPreset::Gold);
+        assert!(gold.r > 0.9 && gold.b < 0.5);
+        let copper = reflectance(ConductorPreset::Copper);
+        assert!(copper.r > copper.g && copper.g > copper.b);
+        let silver = reflectance(ConductorPreset::Silver);
+        assert!(silver.r > 0.9 && silver.b > 0.9);
+    }
+
+    #[test]
+    fn test_rough_dielectric_spreads_refracted_rays() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let frosted = Dielectric {
+            roughness: 0.5,
+            ..Dielectric::new(1.5)
+        };
+        let mut refracted = 0;
+        let mut spread = 0.0;
+        for _ in 0..1000 {
+            let Some((attenuation, scattered)) = frosted.scatter(ray, &hit, &mut sampler) else {
+                continue;
+            };
+            // Masking only ever takes energy away
+            assert!(attenuation.r > 0.0 && attenuation.r <= 1.0);
+            if scattered.direction.z < 0.0 {
+                refracted += 1;
+                spread += 1.0 + scattered.direction.z;
+            }
+        }
+        // Most light passes through head-on, but no longer straight
+        assert!(refracted > 900, "{refracted}");
+        assert!(spread / refracted as f64 > 0.01);
+    }
+
+    #[test]
+    fn test_dielectric_absorption() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let tinted = Dielectric {
+            absorption_color: Color::new(0.5, 1.0, 0.25),
+            absorption_distance: 1.0,
+            ..Dielectric::new(1.5)
+        };
+
+        // Entering is free...
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (attenuation, _) = tinted.scatter(ray, &hit_facing_up(), &mut sampler).unwrap();
+        assert_eq!(attenuation, Color::WHITE);
+
+        // ...leaving after travelling twice the absorption distance is not
+        let exit = Hit {
+            t: 2.0,
+            front_face: false,
+            ..hit_facing_up()
+        };
+        let (attenuation, _) = tinted.scatter(ray, &exit, &mut sampler).unwrap();
+        assert!((attenuation.r - 0.25).abs() < 1e-12);
+        assert!((attenuation.g - 1.0).abs() < 1e-12);
+        assert!((attenuation.b - 0.0625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_thin_walled_dielectric_does_not_bend_light() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let window = Dielectric {
+            absorption_color: Color::new(0.8, 0.9, 1.0),
+            thin_walled: true,
+            ..Dielectric::new(1.5)
+        };
+        let direction = Vec3::new(1.0, 0.0, -1.0).normalized();
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 1.0), direction);
+
+        let mut reflected = 0;
+        for _ in 0..1000 {
+            let (attenuation, scattered) =
+                window.scatter(ray, &hit_facing_up(), &mut sampler).unwrap();
+            if scattered.direction.z > 0.0 {
+                reflected += 1;
+                assert_eq!(attenuation, Color::WHITE);
+            } else {
+                assert!((scattered.direction - direction).length() < 1e-12);
+                assert_eq!(attenuation, window.absorption_color);
+            }
+        }
+        // Both sides reflect, more than the 5% of a single surface at 45 degrees
+        assert!((50..200).contains(&reflected), "{reflected}");
+    }
+
     #[test]
     fn test_dielectric_color_is_white() {
         // Test that dielectric materials scatter white light (no absorption)
@@ -389,9 +1366,16 @@ mod tests {
         let hit = Hit {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
             t: 1.0,
             front_face: true,
             material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
         };
 
         let ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
@@ -407,4 +1391,176 @@ mod tests {
             panic!("Expected Some((attenuation, scattered)), got None");
         }
     }
+
+    #[test]
+    fn test_mix_blends_materials() {
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+        let blue: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+        let lamp: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::WHITE, 4.0));
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let mix = Mix::new(red.clone(), blue, 0.25);
+        let (value, pdf) = mix.evaluate(ray, &hit, Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!((value.r - 0.75 / PI).abs() < 1e-9);
+        assert!((value.b - 0.25 / PI).abs() < 1e-9);
+        assert!((pdf - 1.0 / PI).abs() < 1e-9);
+
+        let glowing = Mix::new(red, lamp, 0.25);
+        assert!(glowing.is_emissive());
+        assert_eq!(glowing.emitted(&hit), Color::WHITE);
+        // A lamp can't be evaluated, so neither can the mix
+        assert!(
+            glowing
+                .evaluate(ray, &hit, Vec3::new(0.0, 0.0, 1.0))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_layered_evaluate_matches_scatter() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+
+        let matte: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.5, 0.2)));
+        let gold: Arc<dyn Material> = Arc::new(Principled::new(Color::WHITE, 1.0, 0.3, 0.5));
+        let materials: [Box<dyn Material>; 2] = [
+            Box::new(Mix::new(matte.clone(), gold, 0.4)),
+            Box::new(Coated {
+                roughness: 0.1,
+                tint: Color::new(0.9, 0.7, 0.4),
+                ..Coated::new(matte, 1.5)
+            }),
+        ];
+        for material in materials {
+            for _ in 0..100 {
+                let Some((attenuation, scattered)) = material.scatter(ray, &hit, &mut sampler)
+                else {
+                    continue;
+                };
+                let (value, pdf) = material.evaluate(ray, &hit, scattered.direction).unwrap();
+                assert!((value.r / pdf - attenuation.r).abs() < 1e-9);
+                assert!((value.g / pdf - attenuation.g).abs() < 1e-9);
+                assert!((value.b / pdf - attenuation.b).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_coated_conserves_energy() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(42);
+        let mut sampler = RandomSampler::new(rng);
+        let hit = hit_facing_up();
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 0.0, -2.0));
+
+        let albedo = |material: &Coated, sampler: &mut dyn Sampler| {
+            let n = 20_000;
+            let sum: f64 = (0..n)
+                .filter_map(|_| material.scatter(ray, &hit, sampler))
+                .map(|(attenuation, _)| attenuation.luminance())
+                .sum();
+            sum / n as f64
+        };
+
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::WHITE));
+        for roughness in [0.0, 0.3] {
+            let coated = Coated {
+                roughness,
+                ..Coated::new(white.clone(), 1.5)
+            };
+            let reflected = albedo(&coated, &mut sampler);
+            assert!(
+                reflected <= 1.01 && reflected > 0.85,
+                "{roughness}: {reflected}"
+            );
+        }
+
+        // Over a mirror the coat can't be evaluated, but still scatters
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(Color::WHITE, 0.0));
+        let coated = Coated::new(mirror, 1.5);
+        assert!(
+            coated
+                .evaluate(ray, &hit, Vec3::new(0.0, 0.0, 1.0))
+                .is_none()
+        );
+        let reflected = albedo(&coated, &mut sampler);
+        assert!(reflected <= 1.01 && reflected > 0.9, "{reflected}");
+    }
+
+    /// The shading normal `material` ends up with, as shown by a normal visualizer
+    fn mapped_normal(material: &NormalMapped, hit: &Hit) -> Vec3 {
+        let color = material.emitted(hit);
+        Vec3::new(color.r, color.g, color.b) * 2.0 - Vec3::ONES
+    }
+
+    /// Brightens along `u`
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _point: Point3) -> Color {
+            Color::WHITE * u
+        }
+    }
+
+    #[test]
+    fn test_normal_map_tilts_shading_normal() {
+        let hit = Hit {
+            tangents: Some([Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]),
+            ..hit_facing_up()
+        };
+        let with_map = |color: Color| NormalMapped {
+            normal_map: Some(Arc::new(SolidColor { color })),
+            ..NormalMapped::new(Arc::new(NormalVisualizer))
+        };
+
+        let flat = with_map(Color::new(0.5, 0.5, 1.0));
+        assert!((mapped_normal(&flat, &hit) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        // Tilted towards growing u, and towards falling v
+        let tilted = with_map(Color::new(0.75, 0.25, 1.0));
+        let expected = Vec3::new(0.5, -0.5, 1.0).normalized();
+        assert!((mapped_normal(&tilted, &hit) - expected).length() < 1e-9);
+
+        // With mirrored texture coordinates, green still points where v grows
+        let mirrored = Hit {
+            tangents: Some([Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)]),
+            ..hit_facing_up()
+        };
+        let expected = Vec3::new(0.5, 0.5, 1.0).normalized();
+        assert!((mapped_normal(&tilted, &mirrored) - expected).length() < 1e-9);
+
+        // Without tangents the surface is left as it is
+        let untextured = hit_facing_up();
+        assert_eq!(
+            mapped_normal(&tilted, &untextured),
+            Vec3::new(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_bump_map_tilts_away_from_slope() {
+        let bumpy = NormalMapped {
+            bump_map: Some(Arc::new(Ramp)),
+            bump_height: 0.5,
+            ..NormalMapped::new(Arc::new(NormalVisualizer))
+        };
+        let hit = Hit {
+            tangents: Some([Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]),
+            ..hit_facing_up()
+        };
+        // The surface rises by 0.5 per unit along x
+        let expected = Vec3::new(-0.5, 0.0, 1.0).normalized();
+        assert!((mapped_normal(&bumpy, &hit) - expected).length() < 1e-6);
+
+        // Seen from behind, the same slope faces the other way
+        let behind = Hit {
+            normal: Normal3::new(0.0, 0.0, -1.0),
+            shading_normal: Normal3::new(0.0, 0.0, -1.0),
+            front_face: false,
+            ..hit
+        };
+        assert!((mapped_normal(&bumpy, &behind) + expected).length() < 1e-6);
+    }
 }
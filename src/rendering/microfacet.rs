@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::math::Vec3;
+use crate::rendering::sampler::Sampler;
+
+/// The smallest GGX width, below which highlights get too sharp to be found by sampling
+const MIN_ALPHA: f64 = 1e-3;
+
+/// An orthonormal basis around a shading normal. Microfacet models are written in this local
+/// space, where the normal is the z axis.
+pub struct ShadingFrame {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl ShadingFrame {
+    /// `normal` must be of unit length
+    pub fn new(normal: Vec3) -> Self {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// A frame whose tangent is `tangent` made perpendicular to `normal`, or an arbitrary one
+    /// if `tangent` is parallel to the normal
+    pub fn with_tangent(normal: Vec3, tangent: Vec3) -> Self {
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.length_squared() < 1e-12 {
+            return Self::new(normal);
+        }
+        let tangent = tangent.normalized();
+        Self {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals with Smith's
+/// height-correlated masking and shadowing, in shading space. Anisotropic distributions are
+/// wider along the x axis, the tangent, than along the y axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    /// Maps the perceptually linear roughness artists author to the width of the distribution
+    pub fn from_roughness(roughness: f64) -> Self {
+        Self::anisotropic(roughness, 0.0)
+    }
+
+    /// Stretches the distribution along the tangent by `anisotropy` between 0 and 1, keeping
+    /// the overall roughness. Burley, "Physically-Based Shading at Disney", 2012.
+    pub fn anisotropic(roughness: f64, anisotropy: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        let alpha = roughness * roughness;
+        Self {
+            alpha_x: (alpha / aspect).max(MIN_ALPHA),
+            alpha_y: (alpha * aspect).max(MIN_ALPHA),
+        }
+    }
+
+    /// Density of microfacets facing `h`, per unit of projected area
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let e = h.x * h.x / (ax * ax) + h.y * h.y / (ay * ay) + h.z * h.z;
+        1.0 / (PI * ax * ay * e * e)
+    }
+
+    /// Smith's auxiliary function, how much of the surface seen from `w` is hidden
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let stretched = ax * ax * w.x * w.x + ay * ay * w.y * w.y;
+        ((1.0 + stretched / cos2).sqrt() - 1.0) / 2.0
+    }
+
+    /// The fraction of microfacets visible from `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both directions
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the microfacet normals seen from `wo`, the distribution that
+    /// [`Ggx::sample_visible_normal`] samples
+    pub fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+    /// Picks a microfacet normal visible from `wo`, which must be above the surface.
+    /// Heitz, "Sampling the GGX Distribution of Visible Normals", 2018.
+    pub fn sample_visible_normal(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere
+        let v = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalized();
+        let length_squared = v.x * v.x + v.y * v.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(t1);
+
+        // A point on the disk the hemisphere projects to, squeezed to its visible part
+        let r = sampler.next_f64().sqrt();
+        let phi = 2.0 * PI * sampler.next_f64();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        // Unstretch
+        Vec3::new(self.alpha_x * n.x, self.alpha_y * n.y, n.z.max(0.0)).normalized()
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, given the reflectance at normal
+/// incidence
+pub fn schlick(f0: Color, cosine: f64) -> Color {
+    let weight = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::WHITE - f0) * weight
+}
+
+/// The exact Fresnel reflectance of a conductor with the complex index of refraction
+/// `eta + i k`, for unpolarized light
+pub fn fresnel_conductor(cosine: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cosine.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let a = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (a * a + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cosine * (0.5 * (a2_plus_b2 + a)).max(0.0).sqrt();
+        let perpendicular = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+        (perpendicular + parallel) / 2.0
+    };
+    Color::new(
+        channel(eta.r, k.r),
+        channel(eta.g, k.g),
+        channel(eta.b, k.b),
+    )
+}
+
+/// Mirrors `wo` about the microfacet normal `h`
+pub fn reflect(wo: Vec3, h: Vec3) -> Vec3 {
+    2.0 * wo.dot(h) * h - wo
+}
+
+/// Bends `wo` through a microfacet with normal `h` on the same side, into a material whose
+/// index of refraction is `1 / eta_ratio` times the current one. `None` on total internal
+/// reflection.
+pub fn refract(wo: Vec3, h: Vec3, eta_ratio: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(h);
+    let sin2_t = eta_ratio * eta_ratio * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-eta_ratio * wo + (eta_ratio * cos_i - cos_t) * h)
+}
+
+/// A cosine weighted direction on the hemisphere around the z axis
+pub fn sample_cosine_hemisphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let u = sampler.next_f64();
+    let r = u.sqrt();
+    let phi = 2.0 * PI * sampler.next_f64();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::sampler::RandomSampler;
+    use rand::SeedableRng;
+
+    fn sampler() -> RandomSampler<rand::prelude::SmallRng> {
+        RandomSampler::new(rand::prelude::SmallRng::seed_from_u64(42))
+    }
+
+    /// Integrates `f` over the upper hemisphere with the midpoint rule in spherical coordinates
+    fn integrate(f: impl Fn(Vec3) -> f64) -> f64 {
+        let (thetas, phis) = (2000, 200);
+        let (d_theta, d_phi) = (PI / 2.0 / thetas as f64, 2.0 * PI / phis as f64);
+        let mut sum = 0.0;
+        for i in 0..thetas {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..phis {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn distributions_are_normalized() {
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for roughness in [0.3, 0.6, 1.0] {
+            let ggx = Ggx::from_roughness(roughness);
+            // Microfacets cover the surface once when projected onto it...
+            let projected = integrate(|h| ggx.d(h) * h.z);
+            assert!((projected - 1.0).abs() < 1e-3, "{roughness}: {projected}");
+            // ...and the visible ones are a distribution of directions
+            let visible = integrate(|h| ggx.visible_normal_pdf(wo, h));
+            assert!((visible - 1.0).abs() < 1e-3, "{roughness}: {visible}");
+        }
+    }
+
+    #[test]
+    fn anisotropic_distributions_are_normalized() {
+        let ggx = Ggx::anisotropic(0.6, 0.8);
+        assert!(ggx.alpha_x > ggx.alpha_y);
+        for wo in [Vec3::new(0.6, 0.0, 0.8), Vec3::new(0.0, 0.6, 0.8)] {
+            let visible = integrate(|h| ggx.visible_normal_pdf(wo, h));
+            assert!((visible - 1.0).abs() < 1e-3, "{visible}");
+        }
+        let projected = integrate(|h| ggx.d(h) * h.z);
+        assert!((projected - 1.0).abs() < 1e-3, "{projected}");
+    }
+
+    #[test]
+    fn conductor_fresnel() {
+        // Without absorption, the reflectance head-on is that of a dielectric...
+        let f = fresnel_conductor(1.0, Color::new(1.5, 1.5, 1.5), Color::BLACK);
+        assert!((f.r - 0.04).abs() < 1e-12);
+        // ...with it, it is ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let f = fresnel_conductor(1.0, Color::new(0.2, 0.2, 0.2), Color::new(3.0, 3.0, 3.0));
+        let expected = (0.64 + 9.0) / (1.44 + 9.0);
+        assert!((f.g - expected).abs() < 1e-12);
+        // Every surface becomes a mirror at grazing angles
+        let f = fresnel_conductor(0.0, Color::new(0.2, 0.2, 0.2), Color::new(3.0, 3.0, 3.0));
+        assert!((f.b - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sampled_normals_follow_the_visible_distribution() {
+        // Compare the fraction of samples in a cone around the normal with the pdf's integral
+        let ggx = Ggx::from_roughness(0.5);
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        let in_cone = |h: Vec3| h.z > 0.9;
+        let mut sampler = sampler();
+        let n = 100_000;
+        let sampled = (0..n)
+            .filter(|_| in_cone(ggx.sample_visible_normal(wo, &mut sampler)))
+            .count() as f64
+            / n as f64;
+        let expected = integrate(|h| {
+            if in_cone(h) {
+                ggx.visible_normal_pdf(wo, h)
+            } else {
+                0.0
+            }
+        });
+        assert!((sampled - expected).abs() < 0.01, "{sampled} vs {expected}");
+    }
+
+    #[test]
+    fn shading_frame_round_trip() {
+        let normal = Vec3::new(1.0, 2.0, -2.0).normalized();
+        let frame = ShadingFrame::new(normal);
+        assert!((frame.to_local(normal) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        let v = Vec3::new(0.3, -0.5, 0.7);
+        assert!((frame.to_world(frame.to_local(v)) - v).length() < 1e-12);
+    }
+}
@@ -1,9 +1,12 @@
 pub mod camera;
 pub mod image;
+pub mod light;
 pub mod material;
+pub mod microfacet;
 pub mod renderer;
 pub mod sampler;
 pub mod sky;
+pub mod texture;
 
 pub use camera::Camera;
 pub use material::Material;
@@ -1,35 +1,95 @@
 use log::info;
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
 
 use crate::World;
 use crate::color::Color;
-use crate::image::Image;
+use crate::image::{AccumulationBuffer, Image};
 use crate::progressbar::ProgressBar;
-use std::time::Instant;
+use std::ops::ControlFlow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::math::{Ray, geometry::Intersect, interval::Interval};
+use crate::math::{Hit, Ray, geometry::Intersect, interval::Interval};
 
+use super::Material;
 use super::camera::Camera;
-use super::sampler::Sampler;
+use super::light::power_heuristic;
+use super::sampler::{RandomSampler, Sampler};
+
+/// Side length of the square tiles the image is split into, in pixels.
+const TILE_SIZE: u32 = 16;
+
+/// A rectangular region of the output image, rendered as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Splits an image into tiles in row-major order. Tiles along the right and bottom edges are
+/// cropped to fit the image.
+fn split_into_tiles(image_width: u32, image_height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..image_height).step_by(TILE_SIZE as usize) {
+        for x in (0..image_width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(image_width - x),
+                height: TILE_SIZE.min(image_height - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// The number of threads to render with when none is configured: one per available core.
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Stopping criteria for [`Renderer::render_progressive`]. Rendering stops as soon as any of
+/// them is reached; with no limits it continues until the pass callback stops it.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressiveLimits {
+    pub max_passes: Option<u32>,
+    pub time_budget: Option<Duration>,
+}
 
 pub struct Renderer {
     samples_per_pixel: u32,
     max_bounces: u32,
+    threads: usize,
 }
 
 impl Renderer {
-    pub fn new(samples_per_pixel: u32, max_bounces: u32) -> Self {
+    pub fn new(samples_per_pixel: u32, max_bounces: u32, threads: usize) -> Self {
         Self {
             samples_per_pixel,
             max_bounces,
+            threads: threads.max(1),
         }
     }
 
+    /// Traces `ray` into the scene and returns the light arriving along it.
+    ///
+    /// `scattering_pdf` is the density with which the previous bounce picked `ray`, or `None` for
+    /// camera rays and bounces off materials that are not sampled against lights. It is used to
+    /// weigh emission found by chance against emission found by light sampling.
     fn ray_color(
         &self,
         ray: Ray,
         max_bounces: u32,
         world: &World,
         sampler: &mut dyn Sampler,
+        scattering_pdf: Option<f64>,
     ) -> Color {
         if max_bounces == 0 {
             return Color::BLACK;
@@ -37,36 +97,173 @@ impl Renderer {
 
         let hit = world.intersect(&ray, Interval::new(0.001, f64::INFINITY));
         if hit.is_none() {
-            return world.sky(ray);
+            let sky = world.sky(ray);
+            return match scattering_pdf {
+                // The previous bounce may also have sampled the sky directly
+                Some(pdf) if sky != Color::BLACK => {
+                    sky * power_heuristic(pdf, world.sky_pdf(ray.direction))
+                }
+                _ => sky,
+            };
         }
         let hit = hit.unwrap();
 
         let material = world.lookup_material(hit.material_id);
 
-        let color_from_emission = material.emitted(&hit);
+        let color_from_emission = {
+            let emitted = material.emitted(&hit);
+            match scattering_pdf {
+                // The previous bounce also sampled this light directly
+                Some(pdf) if emitted != Color::BLACK => {
+                    let light_pdf = world.light_pdf(ray.origin, &hit);
+                    emitted * power_heuristic(pdf, light_pdf)
+                }
+                _ => emitted,
+            }
+        };
+
+        let color_from_lights = self.sample_lights(ray, &hit, material.as_ref(), world, sampler);
 
         let color_from_scatter = {
             if let Some((attenuation, scattered)) = material.scatter(ray, &hit, sampler) {
-                attenuation * self.ray_color(scattered, max_bounces - 1, world, sampler)
+                let pdf = material
+                    .evaluate(ray, &hit, scattered.direction)
+                    .map(|(_, pdf)| pdf);
+                attenuation * self.ray_color(scattered, max_bounces - 1, world, sampler, pdf)
             } else {
                 Color::BLACK
             }
         };
 
-        color_from_emission + color_from_scatter
-
-        // if let Some(hit) = world.intersect(&ray, Interval::new(0.001, f64::INFINITY)) {
-        //     let material = world.lookup_material(hit.material_id);
-        //     if let Some((attenuation, scattered)) = material.scatter(ray, &hit, sampler) {
-        //         return attenuation * self.ray_color(scattered, max_bounces - 1, world, sampler);
-        //     } else {
-        //         return material.emitted(&hit);
-        //     }
-        // }
-        //
-        // world.sky(ray)
+        color_from_emission + color_from_lights + color_from_scatter
     }
 
+    /// Estimates the light arriving at `hit` directly from a randomly picked light, weighted
+    /// against the chance of the material's own scattering finding the same light.
+    fn sample_lights(
+        &self,
+        ray: Ray,
+        hit: &Hit,
+        material: &dyn Material,
+        world: &World,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        // Skip materials that can't be evaluated before spending any samples
+        if material.evaluate(ray, hit, hit.shading_normal.0).is_none() {
+            return Color::BLACK;
+        }
+
+        let Some((light, selection_pdf)) = world.sample_light(sampler) else {
+            return Color::BLACK;
+        };
+        let Some(sample) = light.sample(hit.point, sampler) else {
+            return Color::BLACK;
+        };
+        let Some((value, scattering_pdf)) = material.evaluate(ray, hit, sample.direction) else {
+            return Color::BLACK;
+        };
+        if value == Color::BLACK {
+            return Color::BLACK;
+        }
+
+        let shadow_ray = Ray::new(hit.point, sample.direction);
+        let unoccluded = Interval::new(0.001, sample.distance * (1.0 - 1e-6));
+        if world.intersects(&shadow_ray, unoccluded) {
+            return Color::BLACK;
+        }
+
+        let light_pdf = selection_pdf * sample.pdf;
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, scattering_pdf)
+        };
+        value * sample.radiance * (weight / light_pdf)
+    }
+
+    /// Traces `samples` samples for every pixel of `tile` and returns the sum of their colors,
+    /// in row-major order.
+    fn render_tile(
+        &self,
+        world: &World,
+        camera: &Camera,
+        tile: Tile,
+        samples: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Vec<Color> {
+        let mut pixels = Vec::with_capacity(tile.width as usize * tile.height as usize);
+        for j in tile.y..tile.y + tile.height {
+            for i in tile.x..tile.x + tile.width {
+                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                for _ in 0..samples {
+                    let offset = sampler.in_square();
+                    let ray = camera.get_ray(i as i32, j as i32, offset);
+                    pixel_color =
+                        pixel_color + self.ray_color(ray, self.max_bounces, world, sampler, None);
+                }
+                pixels.push(pixel_color);
+            }
+        }
+        pixels
+    }
+
+    /// Adds `samples` samples per pixel to `buffer`. The tiles are handed out to a pool of
+    /// worker threads.
+    ///
+    /// Every tile gets its own sampler, seeded from `seed` and the tile's index, so the result
+    /// does not depend on the number of threads or the order in which tiles finish.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        world: &World,
+        camera: &Camera,
+        tiles: &[Tile],
+        seed: u64,
+        samples: u32,
+        buffer: &mut AccumulationBuffer,
+        progress_bar: Option<&mut ProgressBar>,
+    ) {
+        let threads = self.threads.min(tiles.len()).max(1);
+        let next_tile = AtomicUsize::new(0);
+        let buffer = Mutex::new(buffer);
+        let progress_bar = Mutex::new(progress_bar);
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        let Some(&tile) = tiles.get(index) else {
+                            break;
+                        };
+
+                        let rng = SmallRng::seed_from_u64(seed.wrapping_add(index as u64));
+                        let mut tile_sampler = RandomSampler::new(rng);
+                        let pixels =
+                            self.render_tile(world, camera, tile, samples, &mut tile_sampler);
+
+                        let mut buffer = buffer.lock().unwrap();
+                        for (k, color) in pixels.into_iter().enumerate() {
+                            let i = tile.x + k as u32 % tile.width;
+                            let j = tile.y + k as u32 / tile.width;
+                            buffer.accumulate(i, j, color);
+                        }
+                        drop(buffer);
+
+                        if let Some(progress_bar) = progress_bar.lock().unwrap().as_mut() {
+                            progress_bar.increment();
+                        }
+                    }
+                });
+            }
+        });
+
+        buffer.into_inner().unwrap().complete_pass(samples);
+    }
+
+    /// Renders the image with all samples per pixel in a single pass.
+    ///
+    /// The image only depends on the state of `sampler`, not on the number of threads.
     pub fn render(
         &self,
         world: &World,
@@ -79,34 +276,96 @@ impl Renderer {
             camera.image_width, camera.image_height
         );
 
-        let mut image = Image::new(camera.image_width, camera.image_height);
+        let tiles = split_into_tiles(camera.image_width, camera.image_height);
+        info!(
+            "rendering {} tiles on {} threads",
+            tiles.len(),
+            self.threads.min(tiles.len()).max(1)
+        );
 
-        let pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+        let seed = sampler.next_u64();
+        let mut buffer = AccumulationBuffer::new(camera.image_width, camera.image_height);
+        let mut progress_bar = ProgressBar::new("Rendering".to_string(), tiles.len() as u32);
 
-        let mut progress_bar = ProgressBar::new("Rendering".to_string(), camera.image_height);
         let rendering_started = Instant::now();
-        for j in 0..camera.image_height {
-            for i in 0..camera.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let offset = sampler.in_square();
-                    let ray = camera.get_ray(i as i32, j as i32, offset);
-                    pixel_color =
-                        pixel_color + self.ray_color(ray, self.max_bounces, world, sampler);
-                }
-                pixel_color = pixel_color * pixel_samples_scale;
-                image.add_pixel(pixel_color);
-            }
-            if show_progress {
-                progress_bar.increment();
-            }
-        }
+        self.render_pass(
+            world,
+            camera,
+            &tiles,
+            seed,
+            self.samples_per_pixel,
+            &mut buffer,
+            show_progress.then_some(&mut progress_bar),
+        );
         let rendering_finished = Instant::now();
         progress_bar.finish();
         info!(
             "image rendered in {} ms",
             (rendering_finished - rendering_started).as_millis()
         );
+        buffer.to_image()
+    }
+
+    /// Renders the image one sample per pixel at a time, averaging the passes in an
+    /// accumulation buffer.
+    ///
+    /// `on_pass` is called with the image averaged over all passes so far and the number of
+    /// completed passes. Rendering stops once one of `limits` is reached or `on_pass` returns
+    /// [`ControlFlow::Break`]. The last intermediate image is returned.
+    pub fn render_progressive<F>(
+        &self,
+        world: &World,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        limits: &ProgressiveLimits,
+        show_progress: bool,
+        mut on_pass: F,
+    ) -> Image
+    where
+        F: FnMut(&Image, u32) -> ControlFlow<()>,
+    {
+        info!(
+            "output image dimensions: {}x{}",
+            camera.image_width, camera.image_height
+        );
+
+        let tiles = split_into_tiles(camera.image_width, camera.image_height);
+        let mut buffer = AccumulationBuffer::new(camera.image_width, camera.image_height);
+        let mut progress_bar = limits
+            .max_passes
+            .filter(|_| show_progress)
+            .map(|passes| ProgressBar::new("Rendering".to_string(), passes));
+
+        let rendering_started = Instant::now();
+        let mut passes = 0;
+        let image = loop {
+            let seed = sampler.next_u64();
+            self.render_pass(world, camera, &tiles, seed, 1, &mut buffer, None);
+            passes += 1;
+
+            if let Some(progress_bar) = progress_bar.as_mut() {
+                progress_bar.increment();
+            }
+
+            let image = buffer.to_image();
+            let stop_requested = on_pass(&image, passes).is_break();
+            let out_of_passes = limits.max_passes.is_some_and(|max| passes >= max);
+            let out_of_time = limits
+                .time_budget
+                .is_some_and(|budget| rendering_started.elapsed() >= budget);
+
+            if stop_requested || out_of_passes || out_of_time {
+                break image;
+            }
+        };
+
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.finish();
+        }
+        info!(
+            "{passes} passes rendered in {} ms",
+            rendering_started.elapsed().as_millis()
+        );
         image
     }
 }
@@ -115,7 +374,9 @@ impl Renderer {
 mod tests {
     use rand::{SeedableRng, rngs::SmallRng};
 
-    use crate::{math::Point3, rendering::sampler::RandomSampler};
+    use std::path::Path;
+
+    use crate::{Config, load_scene_from_config, math::Point3, rendering::sampler::RandomSampler};
 
     use super::*;
     #[test]
@@ -127,10 +388,133 @@ mod tests {
 
         let world = World::default();
 
-        let renderer = Renderer::new(1, 1);
+        let renderer = Renderer::new(1, 1, 1);
 
         let image = renderer.render(&world, &camera, &mut sampler, false);
 
         assert_eq!(image.pixels.len(), 32 * 32);
     }
+
+    #[test]
+    fn progressive_render_stops_after_max_passes() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let camera = Camera::new(1.0, 32, 90, Point3::ORIGIN, Point3::new(0.0, -1.0, 0.0));
+        let world = World::default();
+        let renderer = Renderer::new(1, 1, 2);
+
+        let limits = ProgressiveLimits {
+            max_passes: Some(3),
+            ..Default::default()
+        };
+        let mut passes_seen = Vec::new();
+        let image = renderer.render_progressive(
+            &world,
+            &camera,
+            &mut sampler,
+            &limits,
+            false,
+            |_, passes| {
+                passes_seen.push(passes);
+                ControlFlow::Continue(())
+            },
+        );
+
+        assert_eq!(passes_seen, vec![1, 2, 3]);
+        assert_eq!(image.pixels.len(), 32 * 32);
+    }
+
+    #[test]
+    fn progressive_render_stops_when_asked() {
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let camera = Camera::new(1.0, 32, 90, Point3::ORIGIN, Point3::new(0.0, -1.0, 0.0));
+        let world = World::default();
+        let renderer = Renderer::new(1, 1, 2);
+
+        let mut passes_seen = 0;
+        renderer.render_progressive(
+            &world,
+            &camera,
+            &mut sampler,
+            &ProgressiveLimits::default(),
+            false,
+            |_, passes| {
+                passes_seen = passes;
+                if passes == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+
+        assert_eq!(passes_seen, 2);
+    }
+
+    #[test]
+    fn tiles_cover_image() {
+        let tiles = split_into_tiles(40, 20);
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            Tile {
+                x: 32,
+                y: 0,
+                width: 8,
+                height: 16
+            }
+        );
+
+        let covered: u32 = tiles.iter().map(|tile| tile.width * tile.height).sum();
+        assert_eq!(covered, 40 * 20);
+    }
+
+    #[test]
+    fn image_does_not_depend_on_thread_count() {
+        let config: Config = toml::from_str(
+            r#"
+            [image]
+            width = 40
+            output = "ignore"
+
+            [renderer]
+            samples_per_pixel = 4
+            max_bounces = 4
+
+            [camera]
+            position = "0, 0, 0"
+            look_at = "0, 0, -1"
+            field_of_view = 90
+            aspect_ratio = "1:1"
+
+            [sky]
+            type = "linear-gradient"
+            from = "1, 1, 1"
+            to = "0.5, 0.7, 1.0"
+
+            [[materials]]
+            type = "lambertian"
+            albedo = "1, 0, 0"
+            name = "red"
+
+            [[objects]]
+            type = "sphere"
+            position = "0, 0, -1"
+            radius = 0.5
+            material = "red"
+            "#,
+        )
+        .unwrap();
+        let (camera, world, _) = load_scene_from_config(&config, Path::new("."));
+
+        let mut single_threaded_sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let single_threaded =
+            Renderer::new(4, 4, 1).render(&world, &camera, &mut single_threaded_sampler, false);
+
+        let mut multi_threaded_sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+        let multi_threaded =
+            Renderer::new(4, 4, 8).render(&world, &camera, &mut multi_threaded_sampler, false);
+
+        assert_eq!(single_threaded.pixels, multi_threaded.pixels);
+    }
 }
@@ -6,6 +6,11 @@ pub trait Sampler {
     /// Returns a random f64 in the range [0, 1)
     fn next_f64(&mut self) -> f64;
 
+    /// Returns a random u64, e.g. for seeding other samplers
+    fn next_u64(&mut self) -> u64 {
+        (self.next_f64() * (1u64 << 53) as f64) as u64
+    }
+
     /// Returns a random vector where each component is in the range [0, 1)
     fn vec3(&mut self) -> Vec3 {
         Vec3::new(self.next_f64(), self.next_f64(), self.next_f64())
@@ -14,7 +19,8 @@ pub trait Sampler {
     /// Returns a random vector with length 1.0
     fn unit_vector(&mut self) -> Vec3 {
         loop {
-            let p = self.vec3();
+            // Pick points in the [-1, 1) cube, rejecting those outside the unit sphere
+            let p = 2.0 * self.vec3() - Vec3::ONES;
             let length_squared = p.length_squared();
             // Reject vectors in the black hole region, that normalized would yield
             // [inf, inf, inf]
@@ -56,6 +62,10 @@ impl<R: Rng> Sampler for RandomSampler<R> {
     fn next_f64(&mut self) -> f64 {
         self.rng.random()
     }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.random()
+    }
 }
 
 #[cfg(test)]
@@ -73,6 +83,18 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn random_unit_vectors_cover_sphere() {
+        let rng = rand::prelude::SmallRng::seed_from_u64(1337);
+        let mut sampler = RandomSampler::new(rng);
+        let n = 10_000;
+        let mean = (0..n)
+            .map(|_| sampler.unit_vector())
+            .fold(Vec3::ZERO, |sum, v| sum + v)
+            / n as f64;
+        assert!(mean.length() < 0.05, "mean was {mean:?}");
+    }
+
     #[test]
     pub fn trait_object_works() {
         let rng = rand::prelude::SmallRng::seed_from_u64(42);
@@ -1,9 +1,31 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
 use crate::color::Color;
 use crate::math::Lerp;
 use crate::math::Ray;
+use crate::math::{Distribution2D, Vec3};
+use crate::rendering::light::{LightSample, cone_pdf, sample_cone};
+use crate::rendering::sampler::Sampler;
 
-pub trait SkyBox {
+pub trait SkyBox: Send + Sync {
     fn color(&self, ray: Ray) -> Color;
+
+    /// Whether the sky is bright and varied enough to be worth sampling like a light.
+    /// Such skies implement `sample` and `pdf`.
+    fn is_importance_sampled(&self) -> bool {
+        false
+    }
+
+    /// Picks a direction towards the sky, preferring its brighter parts.
+    fn sample(&self, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        None
+    }
+
+    /// The solid angle density with which `sample` picks `direction`.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
 }
 
 pub struct LinearGradientSkyBox {
@@ -27,3 +49,495 @@ impl SkyBox for SolidColorSkyBox {
         self.color
     }
 }
+
+/// A sky given by an equirectangular image, where the horizontal axis spans all directions
+/// around the y axis and the vertical axis runs from straight up to straight down.
+pub struct EnvironmentMapSkyBox {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Rotation around the y axis in radians
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMapSkyBox {
+    /// Creates an environment map from `width * height` pixels in row-major order.
+    /// `rotation` turns the map around the y axis and is given in degrees.
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+        rotation: f64,
+        intensity: f64,
+    ) -> Self {
+        // Rows near the poles cover a smaller solid angle than rows at the horizon
+        let brightness: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                color.luminance().max(0.0) * theta.sin()
+            })
+            .collect();
+        let distribution = Distribution2D::new(&brightness, width, height);
+
+        Self {
+            width,
+            height,
+            pixels,
+            rotation: rotation.to_radians(),
+            intensity,
+            distribution,
+        }
+    }
+
+    /// Loads an environment map from a Radiance HDR, EXR or any other image file.
+    pub fn load(path: &Path, rotation: f64, intensity: f64) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_rgb32f();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        Ok(Self::new(
+            width as usize,
+            height as usize,
+            pixels,
+            rotation,
+            intensity,
+        ))
+    }
+
+    /// Maps a direction to image coordinates in [0, 1)².
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let direction = direction.normalized();
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.z.atan2(direction.x) - self.rotation;
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = theta / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + self.rotation;
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+}
+
+impl SkyBox for EnvironmentMapSkyBox {
+    fn color(&self, ray: Ray) -> Color {
+        let (u, v) = self.direction_to_uv(ray.direction);
+        self.lookup(u, v)
+    }
+
+    fn is_importance_sampled(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let ((u, v), uv_pdf) = self
+            .distribution
+            .sample(sampler.next_f64(), sampler.next_f64());
+        let sin_theta = (v * PI).sin();
+        if uv_pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: self.uv_to_direction(u, v),
+            distance: f64::INFINITY,
+            radiance: self.lookup(u, v),
+            pdf: uv_pdf / (2.0 * PI * PI * sin_theta),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Converts the sky model's luminance in kcd/m² to the renderer's units. Together with
+/// `SUN_IRRADIANCE` this is chosen so that a white surface in the midday sun is about as bright
+/// as under the default gradient sky.
+const SKY_LUMINANCE_SCALE: f64 = 0.02;
+
+/// Irradiance of the sun before passing through the atmosphere, in the same units.
+const SUN_IRRADIANCE: f64 = 2.5;
+
+/// Angular radius of the sun disk in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+/// Chance of sampling the sun disk rather than the whole sphere.
+const SUN_SAMPLE_PROBABILITY: f64 = 0.5;
+
+/// Coefficients of the Perez sky luminance distribution.
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// Relative value for a direction at angle `theta` from the zenith and `gamma` from the sun.
+    fn evaluate(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta.max(1e-3)).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// A procedural daylight sky following Preetham et al., "A Practical Analytic Model for
+/// Daylight" (1999). Below the horizon lies a uniformly lit ground.
+pub struct PhysicalSkyBox {
+    sun_direction: Vec3,
+    /// Angle between the zenith and the sun in radians
+    sun_theta: f64,
+    perez_luminance: Perez,
+    perez_x: Perez,
+    perez_y: Perez,
+    /// Luminance and chromaticity of the zenith
+    zenith: (f64, f64, f64),
+    /// Radiance of the sun disk, or `None` if the disk is hidden
+    sun_radiance: Option<Color>,
+    ground_radiance: Color,
+}
+
+impl PhysicalSkyBox {
+    /// Creates a sky for a sun at `elevation` degrees above the horizon and `azimuth` degrees
+    /// clockwise from the -z axis when looking down. `turbidity` describes the haziness of the
+    /// atmosphere, from 2 for a clear sky to 10 for a hazy one.
+    pub fn new(
+        elevation: f64,
+        azimuth: f64,
+        turbidity: f64,
+        ground_albedo: Color,
+        sun_disk: bool,
+    ) -> Self {
+        // The model is only valid for a sun above the horizon and moderate turbidity
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let t = turbidity.clamp(1.7, 10.0);
+
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let sun_theta = PI / 2.0 - elevation;
+
+        let perez_luminance = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_y = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s1, s2, s3) = (sun_theta, sun_theta * sun_theta, sun_theta.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s1)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s1 + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s1 + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s1)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s1 + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s1 + 0.26688);
+
+        let sun_radiance = sun_disk.then(|| {
+            let solid_angle = 2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos());
+            sun_transmittance(sun_theta, t) * (SUN_IRRADIANCE / solid_angle)
+        });
+
+        let mut sky = Self {
+            sun_direction,
+            sun_theta,
+            perez_luminance,
+            perez_x,
+            perez_y,
+            zenith: (zenith_luminance.max(0.0), zenith_x, zenith_y),
+            sun_radiance,
+            ground_radiance: Color::BLACK,
+        };
+
+        // The ground scatters the light it receives from the sky and sun equally in all
+        // directions
+        let mut irradiance = sky.sky_irradiance();
+        if let Some(radiance) = sun_radiance {
+            let solid_angle = 2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos());
+            irradiance = irradiance + radiance * (solid_angle * sun_direction.y);
+        }
+        sky.ground_radiance = ground_albedo * irradiance * (1.0 / PI);
+
+        sky
+    }
+
+    /// Radiance of the sky without the sun disk, for a direction above the horizon.
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let cos_theta = direction.y;
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let relative =
+            |perez: &Perez| perez.evaluate(cos_theta, gamma) / perez.evaluate(1.0, self.sun_theta);
+        let (zenith_luminance, zenith_x, zenith_y) = self.zenith;
+        let luminance = zenith_luminance * relative(&self.perez_luminance) * SKY_LUMINANCE_SCALE;
+        let x = zenith_x * relative(&self.perez_x);
+        let y = zenith_y * relative(&self.perez_y);
+
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    /// Integrates the sky radiance arriving at an upward facing surface.
+    fn sky_irradiance(&self) -> Color {
+        let (n_theta, n_phi) = (32, 64);
+        let d_theta = PI / 2.0 / n_theta as f64;
+        let d_phi = 2.0 * PI / n_phi as f64;
+
+        let mut irradiance = Color::BLACK;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let weight = theta.cos() * theta.sin() * d_theta * d_phi;
+                irradiance = irradiance + self.sky_radiance(direction) * weight;
+            }
+        }
+        irradiance
+    }
+
+    fn cos_sun_radius() -> f64 {
+        SUN_ANGULAR_RADIUS.cos()
+    }
+
+    fn radiance(&self, direction: Vec3) -> Color {
+        if direction.y < 0.0 {
+            return self.ground_radiance;
+        }
+
+        let mut radiance = self.sky_radiance(direction);
+        if let Some(sun_radiance) = self.sun_radiance
+            && direction.dot(self.sun_direction) >= Self::cos_sun_radius()
+        {
+            radiance = radiance + sun_radiance;
+        }
+        radiance
+    }
+}
+
+/// The fraction of sunlight that passes through the atmosphere, following the Rayleigh and
+/// aerosol extinction in Preetham et al. Evaluated at one wavelength per color channel.
+fn sun_transmittance(sun_theta: f64, turbidity: f64) -> Color {
+    // Relative optical path length through the atmosphere (Kasten and Young)
+    let zenith_degrees = sun_theta.to_degrees().min(93.0);
+    let air_mass = 1.0 / (sun_theta.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    // Wavelengths in micrometers
+    let transmittance = |lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    };
+    Color::new(
+        transmittance(0.68),
+        transmittance(0.55),
+        transmittance(0.44),
+    )
+}
+
+/// Converts a CIE xyY color to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::BLACK;
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
+
+impl SkyBox for PhysicalSkyBox {
+    fn color(&self, ray: Ray) -> Color {
+        self.radiance(ray.direction.normalized())
+    }
+
+    /// Only the sun disk is small and bright enough to be worth sampling.
+    fn is_importance_sampled(&self) -> bool {
+        self.sun_radiance.is_some()
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let direction = if sampler.next_f64() < SUN_SAMPLE_PROBABILITY {
+            sample_cone(self.sun_direction, Self::cos_sun_radius(), sampler)
+        } else {
+            sampler.unit_vector()
+        };
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance(direction),
+            pdf: self.pdf(direction),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let uniform = (1.0 - SUN_SAMPLE_PROBABILITY) / (4.0 * PI);
+        if direction.normalized().dot(self.sun_direction) >= Self::cos_sun_radius() {
+            uniform + SUN_SAMPLE_PROBABILITY * cone_pdf(Self::cos_sun_radius())
+        } else {
+            uniform
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point3;
+    use crate::rendering::sampler::RandomSampler;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    fn sun_map() -> EnvironmentMapSkyBox {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[2 * width + 5] = Color::new(1000.0, 1000.0, 1000.0);
+        EnvironmentMapSkyBox::new(width, height, pixels, 30.0, 2.0)
+    }
+
+    #[test]
+    fn environment_map_lookup_roundtrips() {
+        let sky = sun_map();
+        let direction = sky.uv_to_direction((5.0 + 0.5) / 16.0, (2.0 + 0.5) / 8.0);
+
+        let color = sky.color(Ray::new(Point3::ORIGIN, direction * 3.0));
+
+        assert_eq!(color, Color::new(2000.0, 2000.0, 2000.0));
+    }
+
+    #[test]
+    fn environment_map_samples_the_sun() {
+        let sky = sun_map();
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+
+        let n = 1000;
+        let mut sun_samples = 0;
+        for _ in 0..n {
+            let sample = sky.sample(&mut sampler).unwrap();
+            assert!((sample.pdf - sky.pdf(sample.direction)).abs() < 1e-6 * sample.pdf);
+            if sample.radiance.r > 1.0 {
+                sun_samples += 1;
+            }
+        }
+
+        assert!(sun_samples > n * 9 / 10);
+    }
+
+    #[test]
+    fn physical_sky_is_brightest_towards_the_sun() {
+        let sky = PhysicalSkyBox::new(30.0, 90.0, 3.0, Color::new(0.3, 0.3, 0.3), false);
+        let brightness =
+            |direction: Vec3| sky.color(Ray::new(Point3::ORIGIN, direction)).luminance();
+
+        // The sun is in the +x direction
+        let near_sun = brightness(Vec3::new(1.0, 0.7, 0.0));
+        let away_from_sun = brightness(Vec3::new(-1.0, 0.7, 0.0));
+        let zenith = brightness(Vec3::new(0.0, 1.0, 0.0));
+
+        assert!(near_sun > zenith);
+        assert!(zenith > 0.0);
+        assert!(near_sun > away_from_sun);
+
+        // A clear sky is blue
+        let color = sky.color(Ray::new(Point3::ORIGIN, Vec3::new(-1.0, 1.0, 0.0)));
+        assert!(color.b > color.r);
+    }
+
+    #[test]
+    fn physical_sky_ground_reflects_sky() {
+        let dark = PhysicalSkyBox::new(45.0, 0.0, 3.0, Color::new(0.1, 0.1, 0.1), true);
+        let bright = PhysicalSkyBox::new(45.0, 0.0, 3.0, Color::new(0.5, 0.5, 0.5), true);
+        let down = Ray::new(Point3::ORIGIN, Vec3::new(0.0, -1.0, 0.0));
+
+        let ratio = bright.color(down).g / dark.color(down).g;
+        assert!((ratio - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn physical_sky_samples_the_sun() {
+        let sky = PhysicalSkyBox::new(45.0, 0.0, 3.0, Color::new(0.3, 0.3, 0.3), true);
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+
+        let n = 1000;
+        let mut sun_samples = 0;
+        for _ in 0..n {
+            let sample = sky.sample(&mut sampler).unwrap();
+            assert_eq!(sample.pdf, sky.pdf(sample.direction));
+            if sample.direction.dot(sky.sun_direction) >= PhysicalSkyBox::cos_sun_radius() {
+                sun_samples += 1;
+            }
+        }
+        assert!(sun_samples > n * 4 / 10);
+
+        // Directions away from the sun are only found by uniform sampling
+        let away = -sky.sun_direction;
+        assert_eq!(sky.pdf(away), (1.0 - SUN_SAMPLE_PROBABILITY) / (4.0 * PI));
+    }
+
+    #[test]
+    fn environment_map_pdf_integrates_to_one() {
+        let sky = sun_map();
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(1337));
+
+        // Monte Carlo integration over the sphere with uniformly distributed directions
+        let n = 1_000_000;
+        let sum: f64 = (0..n).map(|_| sky.pdf(sampler.unit_vector())).sum();
+        let integral = sum * 4.0 * PI / n as f64;
+
+        assert!((integral - 1.0).abs() < 0.05, "integral was {integral}");
+    }
+}
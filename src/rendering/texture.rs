@@ -0,0 +1,355 @@
+use std::path::Path;
+
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::color::Color;
+use crate::math::{Point3, Vec3};
+use crate::rendering::sampler::{RandomSampler, Sampler};
+
+/// A color that varies over a surface, looked up by the texture coordinates and position of
+/// a hit.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Point3) -> Color {
+        self.color
+    }
+}
+
+/// A 3D checkerboard of cubes with the given edge length.
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    pub scale: f64,
+}
+
+impl Texture for Checker {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        let sum = cell(point.0.x) + cell(point.0.y) + cell(point.0.z);
+        if sum.rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// How texture coordinates outside of [0, 1] are mapped onto an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    /// Maps the pixel index `i` onto an image of size `n`.
+    fn apply(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let period = i.rem_euclid(2 * n);
+                if period < n {
+                    period
+                } else {
+                    2 * n - 1 - period
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+/// An image mapped onto the surface by its texture coordinates, with bilinear filtering.
+/// The bottom left corner of the image is at (0, 0).
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Linear colors in row-major order, starting at the top
+    pixels: Vec<Color>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, wrap: WrapMode) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Self {
+            width,
+            height,
+            pixels,
+            wrap,
+        }
+    }
+
+    /// Loads a PNG, JPEG or other image whose colors are sRGB encoded.
+    pub fn load(path: &Path, wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Ok(Self::from_image(image::open(path)?, wrap, srgb_to_linear))
+    }
+
+    /// Decodes an sRGB encoded image held in memory, in any format `load` supports.
+    pub fn from_memory(bytes: &[u8], wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Ok(Self::from_image(
+            image::load_from_memory(bytes)?,
+            wrap,
+            srgb_to_linear,
+        ))
+    }
+
+    /// Loads an image whose values are stored as they are, such as a normal or height map.
+    pub fn load_linear(path: &Path, wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Ok(Self::from_image(image::open(path)?, wrap, |value| value))
+    }
+
+    /// Decodes an image held in memory whose values are stored as they are.
+    pub fn from_memory_linear(bytes: &[u8], wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Ok(Self::from_image(
+            image::load_from_memory(bytes)?,
+            wrap,
+            |value| value,
+        ))
+    }
+
+    fn from_image(image: image::DynamicImage, wrap: WrapMode, decode: fn(f64) -> f64) -> Self {
+        let image = image.into_rgb8();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                Color::new(
+                    decode(p[0] as f64 / 255.0),
+                    decode(p[1] as f64 / 255.0),
+                    decode(p[2] as f64 / 255.0),
+                )
+            })
+            .collect();
+
+        Self::new(width as usize, height as usize, pixels, wrap)
+    }
+
+    /// The mean color of the pixels
+    pub fn average(&self) -> Color {
+        let sum = self
+            .pixels
+            .iter()
+            .fold(Color::BLACK, |sum, pixel| sum + *pixel);
+        sum * (1.0 / self.pixels.len().max(1) as f64)
+    }
+
+    /// Multiplies every pixel by `color`.
+    pub fn tint(&mut self, color: Color) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = *pixel * color;
+        }
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Color {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Point3) -> Color {
+        // Pixel centers lie at half-integer coordinates
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.pixel(x0, y0) * (1.0 - tx) + self.pixel(x0 + 1, y0) * tx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - tx) + self.pixel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+const PERLIN_POINT_COUNT: usize = 256;
+
+/// Gradient noise after Ken Perlin, smoothly varying in [-1, 1].
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    /// Creates the noise function. The same seed always yields the same noise.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut sampler = RandomSampler::new(SmallRng::seed_from_u64(rng.random()));
+        let gradients = (0..PERLIN_POINT_COUNT)
+            .map(|_| sampler.unit_vector())
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..PERLIN_POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    pub fn noise(&self, point: Point3) -> f64 {
+        let p = point.0;
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let mask = PERLIN_POINT_COUNT as i64 - 1;
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.permutations[0][((i + di) & mask) as usize]
+                        ^ self.permutations[1][((j + dj) & mask) as usize]
+                        ^ self.permutations[2][((k + dk) & mask) as usize];
+                    let gradient = self.gradients[index];
+
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    // Hermite smoothing hides the grid
+                    let fade = |t: f64, corner: f64| {
+                        let s = t * t * (3.0 - 2.0 * t);
+                        corner * s + (1.0 - corner) * (1.0 - s)
+                    };
+                    accumulated += fade(u, a) * fade(v, b) * fade(w, c) * gradient.dot(weight);
+                }
+            }
+        }
+        accumulated
+    }
+
+    /// Fractal Brownian motion: the sum of `octaves` layers of noise, each with twice the
+    /// frequency and half the amplitude of the previous one. Returns values in [0, 1].
+    pub fn turbulence(&self, point: Point3, octaves: u32) -> f64 {
+        let mut accumulated = 0.0;
+        let mut p = point.0;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accumulated += weight * self.noise(Point3(p)).abs();
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        accumulated.min(1.0)
+    }
+}
+
+/// A cloudy pattern made from layered Perlin noise.
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub color: Color,
+    /// Frequency of the noise, higher values give finer detail
+    pub scale: f64,
+    pub octaves: u32,
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let p = Point3(self.scale * point.0);
+        self.color * self.noise.turbulence(p, self.octaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient_image(wrap: WrapMode) -> ImageTexture {
+        // Two by two pixels, black on the left and white on the right
+        let pixels = vec![Color::BLACK, Color::WHITE, Color::BLACK, Color::WHITE];
+        ImageTexture::new(2, 2, pixels, wrap)
+    }
+
+    #[test]
+    fn image_texture_filters_bilinearly() {
+        let texture = gradient_image(WrapMode::Clamp);
+
+        assert_eq!(texture.value(0.25, 0.5, Point3::ORIGIN), Color::BLACK);
+        assert_eq!(texture.value(0.75, 0.5, Point3::ORIGIN), Color::WHITE);
+        assert_eq!(
+            texture.value(0.5, 0.5, Point3::ORIGIN),
+            Color::new(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn image_texture_wrap_modes() {
+        let repeat = gradient_image(WrapMode::Repeat);
+        let clamp = gradient_image(WrapMode::Clamp);
+        let mirror = gradient_image(WrapMode::Mirror);
+
+        // One pixel beyond the right edge
+        assert_eq!(repeat.value(1.25, 0.5, Point3::ORIGIN), Color::BLACK);
+        assert_eq!(clamp.value(1.25, 0.5, Point3::ORIGIN), Color::WHITE);
+        assert_eq!(mirror.value(1.25, 0.5, Point3::ORIGIN), Color::WHITE);
+        assert_eq!(mirror.value(1.75, 0.5, Point3::ORIGIN), Color::BLACK);
+    }
+
+    #[test]
+    fn checker_alternates() {
+        let checker = Checker {
+            even: Color::WHITE,
+            odd: Color::BLACK,
+            scale: 0.5,
+        };
+
+        assert_eq!(
+            checker.value(0.0, 0.0, Point3::new(0.25, 0.25, 0.25)),
+            Color::WHITE
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, Point3::new(0.75, 0.25, 0.25)),
+            Color::BLACK
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, Point3::new(-0.25, 0.25, 0.25)),
+            Color::BLACK
+        );
+    }
+
+    #[test]
+    fn perlin_noise_is_smooth_and_bounded() {
+        let perlin = Perlin::new(1337);
+
+        // Gradient noise vanishes on the lattice
+        assert_eq!(perlin.noise(Point3::new(3.0, -2.0, 5.0)), 0.0);
+
+        let mut previous = perlin.noise(Point3::new(0.5, 0.5, 0.5));
+        for i in 1..1000 {
+            let x = 0.5 + i as f64 * 0.001;
+            let value = perlin.noise(Point3::new(x, 0.5, 0.5));
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - previous).abs() < 0.01);
+            previous = value;
+        }
+
+        let turbulence = perlin.turbulence(Point3::new(0.3, 0.7, 0.1), 7);
+        assert!((0.0..=1.0).contains(&turbulence));
+    }
+}
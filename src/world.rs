@@ -1,26 +1,55 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use log::error;
 use log::info;
+use log::warn;
 
 use crate::color::Color;
 use crate::material::DiffuseLight;
 use crate::material::MaterialLibrary;
 use crate::math::BVH;
+use crate::math::BVHOptions;
+use crate::math::Hit;
+use crate::math::Instance;
 use crate::math::Intersect;
+use crate::math::PackedTriangle;
 use crate::math::Primitive;
 use crate::math::Ray;
-use crate::math::{Point3, Sphere, Triangle, interval::Interval};
-use crate::mesh::{Mesh, ObjParseError};
+use crate::math::SplitMethod;
+use crate::math::{
+    Cone, Csg, CsgOperation, Cuboid, Cylinder, Disk, Normal3, Plane, Point3, Quad, Sdf, SdfNode,
+    Shape, Sphere, Torus, Transform, Triangle, Vec3, interval::Interval,
+};
+use crate::mesh::{GltfCamera, GltfScene, Mesh, MeshCache, MeshParseError};
 use crate::rendering::Material;
-use crate::rendering::material::{Dielectric, Lambertian, Metal, NormalVisualizer};
+use crate::rendering::light::{
+    AreaLight, AreaLightShape, DirectionalLight, Light, PointLight, SkyLight, SpotLight,
+};
+use crate::rendering::material::{
+    Coated, Conductor, ConductorPreset, Dielectric, DummyMaterial, Lambertian, Metal, Mix,
+    NormalMapped, NormalVisualizer, Principled,
+};
+use crate::rendering::renderer::default_thread_count;
+use crate::rendering::sampler::Sampler;
 use crate::rendering::sky::SkyBox;
+use crate::rendering::texture::{
+    Checker, ImageTexture, NoiseTexture, Perlin, SolidColor, Texture, WrapMode,
+};
 
-use crate::config::{Config, MaterialConfig, ObjectConfig, SkyConfig};
-use crate::rendering::sky::{LinearGradientSkyBox, SolidColorSkyBox};
+use crate::config::{
+    BvhConfig, BvhMethodConfig, CameraConfig, ColorSource, ColorSpaceConfig, ConductorMetalConfig,
+    ConductorPresetConfig, Config, CsgOperationConfig, FactorSource, LightConfig, MaterialConfig,
+    ObjectConfig, RotationConfig, ScaleConfig, SdfConfig, SkyConfig, SurfaceMapsConfig,
+    TextureConfig, TransformConfig, WrapConfig,
+};
+use crate::rendering::sky::{
+    EnvironmentMapSkyBox, LinearGradientSkyBox, PhysicalSkyBox, SolidColorSkyBox,
+};
 
 pub struct SceneObject {
     pub id: usize,
@@ -28,118 +57,217 @@ pub struct SceneObject {
     pub material_id: usize,
 }
 
+/// Identifies a primitive by the index of its instance in the top-level BVH, if any, and its own
+/// index in the BVH holding it
+type PrimitiveKey = (Option<usize>, usize);
+
 pub struct World {
+    /// The top-level BVH over the scene's objects. Meshes are instances of a bottom-level BVH
+    /// each, spheres and triangles are stored directly.
     bvh: BVH,
-    skybox: Box<dyn SkyBox>,
+    /// Infinite planes, which no bounding box can hold. Hits on them are numbered after the
+    /// primitives of `bvh`.
+    planes: Vec<Plane>,
+    skybox: Arc<dyn SkyBox>,
     material_library: MaterialLibrary,
+    lights: Vec<Box<dyn Light>>,
+    /// Maps an emissive primitive to its index in `lights`
+    primitive_lights: HashMap<PrimitiveKey, usize>,
+    /// A camera imported from one of the scene's files, replacing the configured one
+    camera: Option<CameraConfig>,
+}
+
+/// Meshes and materials kept between builds of a scene. As long as the materials and textures
+/// stay the same, rebuilding a scene only rebuilds the top-level BVH, and meshes are only loaded
+/// again if their files changed.
+pub struct GeometryCache {
+    /// The materials, textures, BVH settings and asset directory the cache was built for
+    key: Option<(Vec<MaterialConfig>, Vec<TextureConfig>, BvhConfig, PathBuf)>,
+    material_library: MaterialLibrary,
+    /// Models by path and default material
+    models: HashMap<(PathBuf, usize), Model>,
+}
+
+impl GeometryCache {
+    pub fn new() -> Self {
+        Self {
+            key: None,
+            material_library: MaterialLibrary::new(),
+            models: HashMap::new(),
+        }
+    }
+
+    /// Starts over if the materials, textures or the asset directory changed, since the cached
+    /// models refer to materials by ID, or if the BVHs should be built differently.
+    fn prepare(&mut self, config: &Config, asset_base_path: &Path) {
+        let key = (
+            config.materials.clone(),
+            config.textures.clone(),
+            config.bvh.clone(),
+            asset_base_path.to_path_buf(),
+        );
+        if self.key.as_ref() == Some(&key) {
+            return;
+        }
+
+        let textures = build_textures(config, asset_base_path);
+        self.material_library = World::build_material_library(config, &textures);
+        self.models.clear();
+        self.key = Some(key);
+    }
+}
+
+impl Default for GeometryCache {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn from_config(config: &Config, asset_base_path: &Path) -> Self {
-        let material_library = Self::build_material_library(config);
+        Self::from_config_with_cache(config, asset_base_path, &mut GeometryCache::new())
+    }
+
+    /// Builds the scene, reusing the meshes that earlier builds left in `cache`.
+    pub fn from_config_with_cache(
+        config: &Config,
+        asset_base_path: &Path,
+        cache: &mut GeometryCache,
+    ) -> Self {
+        cache.prepare(config, asset_base_path);
+        let GeometryCache {
+            material_library,
+            models,
+            ..
+        } = cache;
+        let mut builder = ObjectBuilder {
+            asset_base_path,
+            material_library,
+            models,
+            used_models: HashSet::new(),
+            bvh_options: build_bvh_options(&config.bvh, config.renderer.threads),
+            mesh_cache: config.bvh.cache_directory.as_ref().map(|directory| {
+                MeshCache::new(&resolve_relative_path(asset_base_path, directory))
+            }),
+            statistics: config.bvh.statistics,
+        };
 
         let mut primitives: Vec<Primitive> = Vec::new();
+        let mut planes = Vec::new();
+        let mut camera = None;
         for object_config in &config.objects {
+            let transform = build_transform(object_config.transform());
             match object_config {
-                ObjectConfig::Sphere {
-                    position,
-                    radius,
+                ObjectConfig::Plane {
+                    point,
+                    normal,
                     material,
+                    ..
                 } => {
-                    let material_id = material_library.lookup_material_id(material);
-                    let primitive = Primitive::Sphere(Sphere {
-                        center: Point3(*position),
-                        radius: *radius,
+                    if !is_nonzero(*normal, "plane normal") {
+                        continue;
+                    }
+                    let material_id = builder.material_library.lookup_material_id(material);
+                    planes.push(Plane::new(
+                        transform.point(*point),
+                        transform.normal(Normal3(*normal)).0,
                         material_id,
-                    });
-                    primitives.push(primitive);
+                    ));
                 }
-                ObjectConfig::Triangle {
-                    p1,
-                    p2,
-                    p3,
+                ObjectConfig::Gltf {
+                    path,
                     material,
+                    use_camera,
+                    material_override,
+                    ..
                 } => {
-                    let material_id = material_library.lookup_material_id(material);
-                    let primitive = Primitive::Triangle(Triangle {
-                        p1: *p1,
-                        p2: *p2,
-                        p3: *p3,
-                        material_id,
-                    });
-                    primitives.push(primitive);
-                }
-                ObjectConfig::Mesh { path, material } => {
-                    let material_id = material_library.lookup_material_id(material);
-                    let asset_path = resolve_relative_path(asset_base_path, path);
-                    match load_mesh_from_path(&asset_path, &material_library, material_id) {
-                        Err(message) => {
-                            error!("{message}");
-                        }
-                        Ok(mesh) => {
-                            for tri in mesh.triangles {
-                                let primitive = Primitive::Triangle(tri);
-                                primitives.push(primitive);
+                    let Some(model) = builder.model(path, material, ModelFormat::Gltf) else {
+                        continue;
+                    };
+                    if *use_camera {
+                        match model.cameras.first() {
+                            Some(gltf_camera) => {
+                                camera =
+                                    Some(imported_camera(gltf_camera, &config.camera, &transform));
                             }
+                            None => warn!(
+                                "{} has no camera",
+                                resolve_relative_path(asset_base_path, path).display()
+                            ),
                         }
                     }
+                    let material_override = material_override
+                        .as_ref()
+                        .map(|name| builder.material_library.lookup_material_id(name));
+                    primitives.extend(model.instance(transform, material_override));
                 }
+                _ => primitives.extend(builder.primitive(object_config)),
             }
         }
 
-        let skybox = build_skybox(&config.sky);
+        let skybox = build_skybox(&config.sky, asset_base_path);
+
+        // Forget the models that are no longer part of the scene
+        let ObjectBuilder {
+            material_library,
+            models,
+            used_models,
+            bvh_options,
+            ..
+        } = builder;
+        models.retain(|key, _| used_models.contains(key));
 
-        let n_objects = primitives.len();
+        let n_objects = primitives.len() + planes.len();
         let n_materials = material_library.size();
-        let bvh = BVH::build(primitives);
+        let bvh = BVH::build_with(primitives, &bvh_options);
+        if config.bvh.statistics {
+            info!("scene: {}", bvh.statistics());
+        }
+        let (mut lights, primitive_lights) = collect_area_lights(&bvh, material_library);
+        lights.extend(config.lights.iter().map(build_light));
+        if skybox.is_importance_sampled() {
+            lights.push(Box::new(SkyLight {
+                sky: skybox.clone(),
+            }));
+        }
 
         info!(
-            "constructed scene: {n_objects} objects, {} materials",
-            n_materials
+            "constructed scene: {n_objects} objects, {} materials, {} lights",
+            n_materials,
+            lights.len()
         );
 
         World {
             bvh,
+            planes,
             skybox,
-            material_library,
+            material_library: material_library.clone(),
+            lights,
+            primitive_lights,
+            camera,
         }
     }
 
-    fn build_material_library(config: &Config) -> MaterialLibrary {
+    fn build_material_library(
+        config: &Config,
+        textures: &HashMap<String, Arc<dyn Texture>>,
+    ) -> MaterialLibrary {
+        let mut builder = MaterialBuilder::new(&config.materials, textures);
         let mut material_library = MaterialLibrary::new();
 
         for material_config in &config.materials {
-            match material_config {
-                MaterialConfig::Lambertian { name, albedo } => {
-                    let lamb = Lambertian::new(Color::from(*albedo));
-                    material_library.register_material(name, Arc::new(lamb));
-                }
-                MaterialConfig::Metal { name, albedo, fuzz } => {
-                    let metal = Metal::new(Color::from(*albedo), *fuzz);
-                    material_library.register_material(name, Arc::new(metal));
-                }
-                MaterialConfig::NormalVisualization { name } => {
-                    let mat = NormalVisualizer;
-                    material_library.register_material(name, Arc::new(mat));
-                }
-                MaterialConfig::Dielectric { name, ior } => {
-                    let dielectric = Dielectric::new(*ior);
-                    material_library.register_material(name, Arc::new(dielectric));
-                }
-                MaterialConfig::DiffuseLight {
-                    name,
-                    albedo,
-                    strength,
-                } => {
-                    let diffuse_light = DiffuseLight::new(Color::from(*albedo), *strength);
-                    material_library.register_material(name, Arc::new(diffuse_light));
-                }
-            };
+            let name = material_config.name();
+            material_library.register_material(name, builder.build(name));
         }
 
         material_library
     }
 
+    /// The camera imported from a glTF file, if any object asked to use it
+    pub fn imported_camera(&self) -> Option<&CameraConfig> {
+        self.camera.as_ref()
+    }
+
     pub fn lookup_material(&self, id: usize) -> Arc<dyn Material> {
         self.material_library.lookup_material(id)
     }
@@ -148,6 +276,611 @@ impl World {
     pub fn sky(&self, ray: Ray) -> Color {
         self.skybox.color(ray)
     }
+
+    /// The density with which sampling a light yields `direction` towards the sky.
+    /// Zero if the sky is not sampled as a light.
+    pub fn sky_pdf(&self, direction: Vec3) -> f64 {
+        if self.skybox.is_importance_sampled() {
+            self.skybox.pdf(direction) / self.lights.len() as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Picks one of the scene's lights uniformly at random.
+    /// Returns the light along with the probability of having picked it.
+    pub fn sample_light(&self, sampler: &mut dyn Sampler) -> Option<(&dyn Light, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let n = self.lights.len();
+        let index = ((sampler.next_f64() * n as f64) as usize).min(n - 1);
+        Some((self.lights[index].as_ref(), 1.0 / n as f64))
+    }
+
+    /// The density with which sampling a light from `origin` yields the direction towards `hit`.
+    /// Zero if the hit primitive is not a light.
+    pub fn light_pdf(&self, origin: Point3, hit: &Hit) -> f64 {
+        match self
+            .primitive_lights
+            .get(&(hit.instance_id, hit.primitive_id))
+        {
+            Some(&index) => self.lights[index].pdf(origin, hit) / self.lights.len() as f64,
+            None => 0.0,
+        }
+    }
+}
+
+/// A model loaded from a file, shared by every object that places it
+#[derive(Clone)]
+struct Model {
+    bvh: Arc<BVH>,
+    cameras: Vec<GltfCamera>,
+    /// When the file was last modified at the time it was loaded
+    modified: Option<SystemTime>,
+}
+
+impl Model {
+    fn new(
+        triangles: Vec<Triangle>,
+        cameras: Vec<GltfCamera>,
+        path: &Path,
+        bvh_options: &BVHOptions,
+    ) -> Self {
+        Self::from_bvh(BVH::build_triangles(triangles, bvh_options), cameras, path)
+    }
+
+    fn from_bvh(bvh: BVH, cameras: Vec<GltfCamera>, path: &Path) -> Self {
+        Self {
+            bvh: Arc::new(bvh),
+            cameras,
+            modified: modification_time(path),
+        }
+    }
+
+    /// Whether the file has not changed since it was loaded
+    fn is_current(&self, path: &Path) -> bool {
+        self.modified.is_some() && self.modified == modification_time(path)
+    }
+
+    /// An instance placing the model in the scene, `None` if the model is empty
+    fn instance(
+        &self,
+        transform: Transform,
+        material_override: Option<usize>,
+    ) -> Option<Primitive> {
+        if self.bvh.primitives.is_empty() {
+            return None;
+        }
+
+        let instance = Instance::new(self.bvh.clone(), transform, material_override);
+        Some(Primitive::Instance(Box::new(instance)))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ModelFormat {
+    /// OBJ, PLY or STL
+    Mesh,
+    Gltf,
+}
+
+/// Turns object configurations into primitives, loading the models they place through the
+/// geometry cache
+struct ObjectBuilder<'a> {
+    asset_base_path: &'a Path,
+    material_library: &'a mut MaterialLibrary,
+    models: &'a mut HashMap<(PathBuf, usize), Model>,
+    /// The models placed so far, the cache forgets the others
+    used_models: HashSet<(PathBuf, usize)>,
+    bvh_options: BVHOptions,
+    mesh_cache: Option<MeshCache>,
+    statistics: bool,
+}
+
+impl ObjectBuilder<'_> {
+    /// The model at `path` with `material` as its default material, loaded again only if the
+    /// geometry cache holds no current copy. Errors are logged.
+    fn model(&mut self, path: &PathBuf, material: &str, format: ModelFormat) -> Option<Model> {
+        let material_id = self.material_library.lookup_material_id(material);
+        let asset_path = resolve_relative_path(self.asset_base_path, path);
+        let key = (asset_path.clone(), material_id);
+        if !self
+            .models
+            .get(&key)
+            .is_some_and(|model| model.is_current(&asset_path))
+        {
+            let model = match format {
+                ModelFormat::Mesh => load_mesh_model(
+                    &asset_path,
+                    self.material_library,
+                    material_id,
+                    &self.bvh_options,
+                    self.mesh_cache.as_ref(),
+                    self.statistics,
+                )
+                .map_err(|e| e.to_string()),
+                ModelFormat::Gltf => {
+                    GltfScene::load(&asset_path, self.material_library, material_id)
+                        .map(|scene| {
+                            let model = Model::new(
+                                scene.triangles,
+                                scene.cameras,
+                                &asset_path,
+                                &self.bvh_options,
+                            );
+                            report_bvh(&asset_path, &model.bvh, self.statistics, false);
+                            model
+                        })
+                        .map_err(|e| e.to_string())
+                }
+            };
+            match model {
+                Err(message) => {
+                    error!("{message}");
+                    return None;
+                }
+                Ok(model) => {
+                    self.models.insert(key.clone(), model);
+                }
+            }
+        }
+        self.used_models.insert(key.clone());
+        Some(self.models[&key].clone())
+    }
+
+    /// The primitive placing an object in the scene, `None` if the object is invalid. Planes
+    /// and glTF scenes are not single primitives and are left to the caller.
+    fn primitive(&mut self, object_config: &ObjectConfig) -> Option<Primitive> {
+        let transform = build_transform(object_config.transform());
+        let shape = |shape: Shape| Primitive::Shape(Box::new(shape));
+        let primitive = match object_config {
+            ObjectConfig::Sphere {
+                position,
+                radius,
+                material,
+                ..
+            } => Primitive::Sphere(Sphere {
+                center: Point3(*position),
+                radius: *radius,
+                material_id: self.material_library.lookup_material_id(material),
+            }),
+            ObjectConfig::Triangle {
+                p1,
+                p2,
+                p3,
+                material,
+                ..
+            } => {
+                // A single triangle is cheaper to move than to instance
+                return Some(Primitive::Triangle(PackedTriangle::new(
+                    transform.point(*p1),
+                    transform.point(*p2),
+                    transform.point(*p3),
+                    self.material_library.lookup_material_id(material),
+                )));
+            }
+            ObjectConfig::Quad {
+                corner,
+                edge1,
+                edge2,
+                material,
+                ..
+            } => {
+                if !is_nonzero(edge1.cross(*edge2), "quad area") {
+                    return None;
+                }
+                // Transformed parallelograms are still parallelograms
+                return Some(shape(Shape::Quad(Quad::new(
+                    transform.point(*corner),
+                    transform.vector(*edge1),
+                    transform.vector(*edge2),
+                    self.material_library.lookup_material_id(material),
+                ))));
+            }
+            ObjectConfig::Disk {
+                center,
+                normal,
+                radius,
+                material,
+                ..
+            } => {
+                if !is_nonzero(*normal, "disk normal") {
+                    return None;
+                }
+                let material_id = self.material_library.lookup_material_id(material);
+                shape(Shape::Disk(Disk::new(
+                    *center,
+                    *normal,
+                    *radius,
+                    material_id,
+                )))
+            }
+            ObjectConfig::Cuboid {
+                min, max, material, ..
+            } => {
+                let material_id = self.material_library.lookup_material_id(material);
+                shape(Shape::Cuboid(Cuboid::new(*min, *max, material_id)))
+            }
+            ObjectConfig::Cylinder {
+                base,
+                top,
+                radius,
+                material,
+                ..
+            } => {
+                if !is_nonzero(*top - *base, "cylinder height") {
+                    return None;
+                }
+                let material_id = self.material_library.lookup_material_id(material);
+                shape(Shape::Cylinder(Cylinder::new(
+                    *base,
+                    *top,
+                    *radius,
+                    material_id,
+                )))
+            }
+            ObjectConfig::Cone {
+                base,
+                apex,
+                radius,
+                material,
+                ..
+            } => {
+                if !is_nonzero(*apex - *base, "cone height") {
+                    return None;
+                }
+                let material_id = self.material_library.lookup_material_id(material);
+                shape(Shape::Cone(Cone::new(*base, *apex, *radius, material_id)))
+            }
+            ObjectConfig::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                material,
+                ..
+            } => {
+                if !is_nonzero(*axis, "torus axis") {
+                    return None;
+                }
+                let material_id = self.material_library.lookup_material_id(material);
+                shape(Shape::Torus(Torus::new(
+                    *center,
+                    *axis,
+                    *major_radius,
+                    *minor_radius,
+                    material_id,
+                )))
+            }
+            ObjectConfig::Csg {
+                operation,
+                left,
+                right,
+                ..
+            } => {
+                let operation = match operation {
+                    CsgOperationConfig::Union => CsgOperation::Union,
+                    CsgOperationConfig::Intersection => CsgOperation::Intersection,
+                    CsgOperationConfig::Difference => CsgOperation::Difference,
+                };
+                let left = self.csg_operand(left)?;
+                let right = self.csg_operand(right)?;
+                Primitive::Csg(Box::new(Csg::new(operation, left, right)))
+            }
+            ObjectConfig::Sdf {
+                shape, material, ..
+            } => {
+                let material_id = self.material_library.lookup_material_id(material);
+                Primitive::Sdf(Box::new(Sdf::new(sdf_node(shape), material_id)))
+            }
+            ObjectConfig::Mesh {
+                path,
+                material,
+                material_override,
+                ..
+            } => {
+                let model = self.model(path, material, ModelFormat::Mesh)?;
+                // Looked up after loading, the override may come from the mesh's MTL files
+                let material_override = material_override
+                    .as_ref()
+                    .map(|name| self.material_library.lookup_material_id(name));
+                return model.instance(transform, material_override);
+            }
+            ObjectConfig::Plane { .. } | ObjectConfig::Gltf { .. } => return None,
+        };
+
+        if transform == Transform::IDENTITY {
+            Some(primitive)
+        } else {
+            let object = Arc::new(BVH::build_with(vec![primitive], &self.bvh_options));
+            Some(Primitive::Instance(Box::new(Instance::new(
+                object, transform, None,
+            ))))
+        }
+    }
+
+    /// Only objects that enclose a volume can be combined, and a CSG object is skipped if one
+    /// of its operands is not.
+    fn csg_operand(&mut self, object_config: &ObjectConfig) -> Option<Primitive> {
+        match object_config {
+            ObjectConfig::Sphere { .. }
+            | ObjectConfig::Cuboid { .. }
+            | ObjectConfig::Cylinder { .. }
+            | ObjectConfig::Cone { .. }
+            | ObjectConfig::Torus { .. }
+            | ObjectConfig::Mesh { .. }
+            | ObjectConfig::Csg { .. }
+            | ObjectConfig::Sdf { .. } => self.primitive(object_config),
+            _ => {
+                warn!(
+                    "csg operands must be closed, skipping the csg object with a {} operand",
+                    object_config.type_name().to_lowercase()
+                );
+                None
+            }
+        }
+    }
+}
+
+fn conductor_preset(config: ConductorPresetConfig) -> ConductorPreset {
+    match config {
+        ConductorPresetConfig::Gold => ConductorPreset::Gold,
+        ConductorPresetConfig::Silver => ConductorPreset::Silver,
+        ConductorPresetConfig::Copper => ConductorPreset::Copper,
+        ConductorPresetConfig::Aluminium => ConductorPreset::Aluminium,
+        ConductorPresetConfig::Chromium => ConductorPreset::Chromium,
+        ConductorPresetConfig::Iron => ConductorPreset::Iron,
+    }
+}
+
+fn sdf_node(config: &SdfConfig) -> SdfNode {
+    let half = |size: &Vec3| *size / 2.0;
+    let node = |config: &SdfConfig| Box::new(sdf_node(config));
+    match config {
+        SdfConfig::Sphere { center, radius } => SdfNode::Sphere {
+            center: *center,
+            radius: *radius,
+        },
+        SdfConfig::Cuboid { center, size } => SdfNode::Cuboid {
+            center: *center,
+            half_size: half(size),
+        },
+        SdfConfig::RoundedBox {
+            center,
+            size,
+            radius,
+        } => SdfNode::RoundedBox {
+            center: *center,
+            half_size: half(size),
+            radius: *radius,
+        },
+        SdfConfig::Torus {
+            center,
+            major_radius,
+            minor_radius,
+        } => SdfNode::Torus {
+            center: *center,
+            major_radius: *major_radius,
+            minor_radius: *minor_radius,
+        },
+        SdfConfig::Capsule { a, b, radius } => SdfNode::Capsule {
+            a: *a,
+            b: *b,
+            radius: *radius,
+        },
+        SdfConfig::SmoothUnion {
+            smoothness,
+            left,
+            right,
+        } => SdfNode::SmoothUnion {
+            smoothness: *smoothness,
+            left: node(left),
+            right: node(right),
+        },
+        SdfConfig::SmoothSubtract {
+            smoothness,
+            left,
+            right,
+        } => SdfNode::SmoothSubtraction {
+            smoothness: *smoothness,
+            left: node(left),
+            right: node(right),
+        },
+        SdfConfig::SmoothIntersect {
+            smoothness,
+            left,
+            right,
+        } => SdfNode::SmoothIntersection {
+            smoothness: *smoothness,
+            left: node(left),
+            right: node(right),
+        },
+        SdfConfig::Repeat {
+            period,
+            count,
+            shape,
+        } => SdfNode::Repeat {
+            period: *period,
+            count: *count,
+            shape: node(shape),
+        },
+        SdfConfig::Displace {
+            amplitude,
+            frequency,
+            shape,
+        } => SdfNode::Displace {
+            amplitude: *amplitude,
+            frequency: *frequency,
+            shape: node(shape),
+        },
+    }
+}
+
+/// Whether `vector` is usable as a direction, warning about `what` if it is not
+fn is_nonzero(vector: Vec3, what: &str) -> bool {
+    if vector.length_squared() > 0.0 {
+        true
+    } else {
+        warn!("{what} must not be zero");
+        false
+    }
+}
+
+/// Logs how long building or loading a model's BVH took and how much memory it uses, or all of
+/// its statistics if they were asked for.
+fn report_bvh(path: &Path, bvh: &BVH, statistics: bool, cached: bool) {
+    if statistics {
+        info!("{}: {}", path.display(), bvh.statistics());
+    } else {
+        info!(
+            "{}: {} BVH over {} primitives in {:.1?}, {:.1} MiB",
+            path.display(),
+            if cached { "loaded cached" } else { "built" },
+            bvh.primitives.len(),
+            bvh.build_time(),
+            bvh.memory_usage() as f64 / (1024.0 * 1024.0)
+        );
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// BVHs are built with as many threads as the scene is rendered with.
+fn build_bvh_options(config: &BvhConfig, threads: Option<usize>) -> BVHOptions {
+    let mut max_leaf_size = config.max_leaf_size;
+    if max_leaf_size == 0 {
+        warn!("bvh max_leaf_size must be at least 1");
+        max_leaf_size = 1;
+    }
+
+    BVHOptions {
+        split_method: match config.method {
+            BvhMethodConfig::Sah => SplitMethod::Sah,
+            BvhMethodConfig::Median => SplitMethod::Median,
+        },
+        max_leaf_size,
+        traversal_cost: config.traversal_cost,
+        intersection_cost: config.intersection_cost,
+        threads: threads.unwrap_or_else(default_thread_count),
+    }
+}
+
+/// Scales, then rotates and finally translates. Invalid parts of the transformation are
+/// skipped with a warning.
+fn build_transform(config: &TransformConfig) -> Transform {
+    let rotation = match &config.rotate {
+        None => Transform::IDENTITY,
+        Some(RotationConfig::Euler(angles)) => {
+            Transform::rotation(Vec3::new(0.0, 0.0, 1.0), angles.z)
+                * Transform::rotation(Vec3::new(0.0, 1.0, 0.0), angles.y)
+                * Transform::rotation(Vec3::new(1.0, 0.0, 0.0), angles.x)
+        }
+        Some(RotationConfig::AxisAngle { axis, angle }) => {
+            if axis.length_squared() > 0.0 {
+                Transform::rotation(*axis, *angle)
+            } else {
+                warn!("rotation axis must not be zero");
+                Transform::IDENTITY
+            }
+        }
+    };
+
+    let factors = match config.scale {
+        None => Vec3::ONES,
+        Some(ScaleConfig::Uniform(factor)) => Vec3::new(factor, factor, factor),
+        Some(ScaleConfig::PerAxis(factors)) => factors,
+    };
+    let scale = if factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0 {
+        Transform::scale(factors)
+    } else {
+        warn!("scale factors must not be zero");
+        Transform::IDENTITY
+    };
+
+    Transform::translation(config.translate) * rotation * scale
+}
+
+/// Creates an area light for every primitive with an emissive material, including the
+/// primitives of instances.
+fn collect_area_lights(
+    bvh: &BVH,
+    material_library: &MaterialLibrary,
+) -> (Vec<Box<dyn Light>>, HashMap<PrimitiveKey, usize>) {
+    let mut lights: Vec<Box<dyn Light>> = Vec::new();
+    let mut primitive_lights = HashMap::new();
+
+    let mut add_light = |key, material_id, shape: AreaLightShape| {
+        let material = material_library.lookup_material(material_id);
+        if !material.is_emissive() {
+            return;
+        }
+
+        primitive_lights.insert(key, lights.len());
+        lights.push(Box::new(AreaLight::new(shape, material)));
+    };
+
+    for (index, primitive) in bvh.primitives.iter().enumerate() {
+        match primitive {
+            Primitive::Sphere(sphere) => add_light(
+                (None, index),
+                sphere.material_id,
+                AreaLightShape::Sphere(sphere.clone()),
+            ),
+            Primitive::Triangle(_) => {
+                if let Some(triangle) = bvh.triangle(index) {
+                    add_light(
+                        (None, index),
+                        triangle.material_id,
+                        AreaLightShape::Triangle(triangle),
+                    );
+                }
+            }
+            // Only found by the rays that happen to hit them
+            Primitive::Shape(_) | Primitive::Csg(_) | Primitive::Sdf(_) => {}
+            Primitive::Instance(instance) => {
+                for primitive_id in 0..instance.object.primitives.len() {
+                    // Lights are sampled in world space. Transformed spheres are no longer
+                    // spheres, and are only found by the rays that happen to hit them.
+                    let Some(mut triangle) = instance.object.triangle(primitive_id) else {
+                        continue;
+                    };
+                    let transform = &instance.transform;
+                    triangle.p1 = transform.point(triangle.p1);
+                    triangle.p2 = transform.point(triangle.p2);
+                    triangle.p3 = transform.point(triangle.p3);
+                    triangle.normals = triangle
+                        .normals
+                        .map(|normals| normals.map(|n| transform.normal(n)));
+                    let material_id = instance.material_override.unwrap_or(triangle.material_id);
+                    add_light(
+                        (Some(index), primitive_id),
+                        material_id,
+                        AreaLightShape::Triangle(triangle),
+                    );
+                }
+            }
+        }
+    }
+
+    (lights, primitive_lights)
+}
+
+/// The configuration of a camera from a glTF file, moved along with the file's scene. The
+/// file's aspect ratio is used if it has one, otherwise the configured one is kept.
+fn imported_camera(
+    camera: &GltfCamera,
+    config: &CameraConfig,
+    transform: &Transform,
+) -> CameraConfig {
+    CameraConfig {
+        aspect_ratio: camera.aspect_ratio.unwrap_or(config.aspect_ratio),
+        field_of_view: camera.field_of_view.round() as u32,
+        position: transform.point(camera.position),
+        look_at: transform.point(camera.look_at),
+    }
 }
 
 fn resolve_relative_path(base: &Path, path: &PathBuf) -> PathBuf {
@@ -158,47 +891,638 @@ fn resolve_relative_path(base: &Path, path: &PathBuf) -> PathBuf {
     }
 }
 
+/// Loads a mesh and builds its BVH, or reads both from `mesh_cache` if it holds an up to date
+/// copy. Freshly built BVHs are added to the cache.
+fn load_mesh_model(
+    path: &PathBuf,
+    material_library: &mut MaterialLibrary,
+    material_id: usize,
+    bvh_options: &BVHOptions,
+    mesh_cache: Option<&MeshCache>,
+    statistics: bool,
+) -> Result<Model, MeshParseError> {
+    if let Some(bvh) =
+        mesh_cache.and_then(|cache| cache.load(path, material_id, bvh_options, material_library))
+    {
+        report_bvh(path, &bvh, statistics, true);
+        return Ok(Model::from_bvh(bvh, Vec::new(), path));
+    }
+
+    let Mesh {
+        triangles,
+        material_libraries,
+    } = load_mesh_from_path(path, material_library, material_id)?;
+    let model = Model::new(triangles, Vec::new(), path, bvh_options);
+    report_bvh(path, &model.bvh, statistics, false);
+    if let Some(cache) = mesh_cache {
+        cache.store(
+            path,
+            material_id,
+            &model.bvh,
+            &material_libraries,
+            material_library,
+        );
+    }
+    Ok(model)
+}
+
 fn load_mesh_from_path(
     path: &PathBuf,
-    material_library: &MaterialLibrary,
+    material_library: &mut MaterialLibrary,
     material_id: usize,
-) -> Result<Mesh, ObjParseError> {
+) -> Result<Mesh, MeshParseError> {
     let mut file =
-        File::open(path).map_err(|_| ObjParseError::unable_to_open_file(path.to_path_buf()))?;
-    let mesh = Mesh::read_from_obj(&mut file, material_library, material_id)?;
-    Ok(mesh)
+        File::open(path).map_err(|_| MeshParseError::unable_to_open_file(path.to_path_buf()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("ply") => Mesh::read_from_ply(&mut file, material_id),
+        Some("stl") => Mesh::read_from_stl(&mut file, material_id),
+        _ => {
+            let base_path = path.parent().unwrap_or(Path::new(""));
+            Mesh::read_from_obj(&mut file, base_path, material_library, material_id)
+        }
+    }
+}
+
+fn build_textures(config: &Config, asset_base_path: &Path) -> HashMap<String, Arc<dyn Texture>> {
+    let mut textures: HashMap<String, Arc<dyn Texture>> = HashMap::new();
+
+    for texture_config in &config.textures {
+        let texture: Arc<dyn Texture> = match texture_config {
+            TextureConfig::Image {
+                path,
+                wrap,
+                color_space,
+                ..
+            } => {
+                let asset_path = resolve_relative_path(asset_base_path, path);
+                let wrap = match wrap {
+                    WrapConfig::Repeat => WrapMode::Repeat,
+                    WrapConfig::Clamp => WrapMode::Clamp,
+                    WrapConfig::Mirror => WrapMode::Mirror,
+                };
+                let loaded = match color_space {
+                    ColorSpaceConfig::Srgb => ImageTexture::load(&asset_path, wrap),
+                    ColorSpaceConfig::Linear => ImageTexture::load_linear(&asset_path, wrap),
+                };
+                match loaded {
+                    Ok(texture) => Arc::new(texture),
+                    Err(e) => {
+                        error!("unable to load texture {}: {e}", asset_path.display());
+                        Arc::new(SolidColor {
+                            color: MISSING_TEXTURE_COLOR,
+                        })
+                    }
+                }
+            }
+            TextureConfig::Checker {
+                even, odd, scale, ..
+            } => Arc::new(Checker {
+                even: Color::from(*even),
+                odd: Color::from(*odd),
+                scale: *scale,
+            }),
+            TextureConfig::Noise {
+                color,
+                scale,
+                octaves,
+                ..
+            } => Arc::new(NoiseTexture {
+                noise: Perlin::new(0),
+                color: Color::from(*color),
+                scale: *scale,
+                octaves: *octaves,
+            }),
+        };
+        textures.insert(texture_config.name().to_string(), texture);
+    }
+
+    textures
+}
+
+/// Used in place of textures that could not be found or loaded
+const MISSING_TEXTURE_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.0,
+    b: 1.0,
+};
+
+/// Builds the materials of a scene, including those made of other materials, which refer to
+/// them by name
+struct MaterialBuilder<'a> {
+    configs: HashMap<&'a str, &'a MaterialConfig>,
+    textures: &'a HashMap<String, Arc<dyn Texture>>,
+    built: HashMap<&'a str, Arc<dyn Material>>,
+    /// The materials being built, each made of the next
+    in_progress: Vec<&'a str>,
+}
+
+impl<'a> MaterialBuilder<'a> {
+    fn new(configs: &'a [MaterialConfig], textures: &'a HashMap<String, Arc<dyn Texture>>) -> Self {
+        Self {
+            configs: configs
+                .iter()
+                .map(|config| (config.name(), config))
+                .collect(),
+            textures,
+            built: HashMap::new(),
+            in_progress: Vec::new(),
+        }
+    }
+
+    /// The material called `name`. Materials that can't be resolved or that are made of
+    /// themselves are replaced by the fallback material.
+    fn build(&mut self, name: &str) -> Arc<dyn Material> {
+        if let Some(material) = self.built.get(name) {
+            return material.clone();
+        }
+        let Some((&name, &config)) = self.configs.get_key_value(name) else {
+            warn!("material '{name}' could not be resolved");
+            return Arc::new(DummyMaterial);
+        };
+        if let Some(start) = self.in_progress.iter().position(|other| *other == name) {
+            let cycle = self.in_progress[start..].join(" -> ");
+            error!("material '{name}' is made of itself: {cycle} -> {name}");
+            return Arc::new(DummyMaterial);
+        }
+
+        self.in_progress.push(name);
+        let mut material = self.construct(config);
+        self.in_progress.pop();
+        if let Some(maps) = config.maps() {
+            material = apply_maps(material, maps, self.textures);
+        }
+        self.built.insert(name, material.clone());
+        material
+    }
+
+    fn construct(&mut self, config: &MaterialConfig) -> Arc<dyn Material> {
+        let textures = self.textures;
+        match config {
+            MaterialConfig::Lambertian { albedo, .. } => {
+                let lamb = Lambertian::textured(resolve_color(albedo, textures));
+                Arc::new(lamb)
+            }
+            MaterialConfig::Metal { albedo, fuzz, .. } => {
+                let metal = Metal::textured(resolve_color(albedo, textures), *fuzz);
+                Arc::new(metal)
+            }
+            MaterialConfig::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                ..
+            } => {
+                let principled = Principled::textured(
+                    resolve_color(base_color, textures),
+                    *metallic,
+                    *roughness,
+                    *specular,
+                );
+                Arc::new(principled)
+            }
+            MaterialConfig::Conductor {
+                metal,
+                roughness,
+                anisotropy,
+                ..
+            } => {
+                let (eta, k) = match metal {
+                    ConductorMetalConfig::Preset(preset) => conductor_preset(*preset).ior(),
+                    ConductorMetalConfig::Custom { eta, k } => (Color::from(*eta), Color::from(*k)),
+                };
+                let conductor = Conductor::new(eta, k, *roughness, *anisotropy);
+                Arc::new(conductor)
+            }
+            MaterialConfig::NormalVisualization { .. } => Arc::new(NormalVisualizer),
+            MaterialConfig::Dielectric {
+                ior,
+                roughness,
+                absorption_color,
+                absorption_distance,
+                thin_walled,
+                ..
+            } => {
+                let dielectric = Dielectric {
+                    roughness: *roughness,
+                    absorption_color: Color::from(*absorption_color),
+                    absorption_distance: *absorption_distance,
+                    thin_walled: *thin_walled,
+                    ..Dielectric::new(*ior)
+                };
+                Arc::new(dielectric)
+            }
+            MaterialConfig::DiffuseLight {
+                albedo, strength, ..
+            } => {
+                let diffuse_light =
+                    DiffuseLight::textured(resolve_color(albedo, textures), *strength);
+                Arc::new(diffuse_light)
+            }
+            MaterialConfig::Mix {
+                first,
+                second,
+                factor,
+                ..
+            } => {
+                let mix = Mix::textured(
+                    self.build(first),
+                    self.build(second),
+                    resolve_factor(factor, textures),
+                );
+                Arc::new(mix)
+            }
+            MaterialConfig::Coated {
+                base,
+                ior,
+                roughness,
+                tint,
+                ..
+            } => {
+                let coated = Coated {
+                    roughness: *roughness,
+                    tint: Color::from(*tint),
+                    ..Coated::new(self.build(base), *ior)
+                };
+                Arc::new(coated)
+            }
+        }
+    }
 }
 
-fn build_skybox(config: &SkyConfig) -> Box<dyn SkyBox> {
+fn resolve_color(
+    source: &ColorSource,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+) -> Arc<dyn Texture> {
+    match source {
+        ColorSource::Constant(color) => Arc::new(SolidColor {
+            color: Color::from(*color),
+        }),
+        ColorSource::Texture { texture } => resolve_texture(texture, textures),
+    }
+}
+
+fn resolve_texture(name: &str, textures: &HashMap<String, Arc<dyn Texture>>) -> Arc<dyn Texture> {
+    textures.get(name).cloned().unwrap_or_else(|| {
+        warn!("texture '{name}' could not be resolved");
+        Arc::new(SolidColor {
+            color: MISSING_TEXTURE_COLOR,
+        })
+    })
+}
+
+/// Wraps `material` in its normal and bump maps, if it has any
+fn apply_maps(
+    material: Arc<dyn Material>,
+    maps: &SurfaceMapsConfig,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+) -> Arc<dyn Material> {
+    if maps.normal_map.is_none() && maps.bump_map.is_none() {
+        return material;
+    }
+    let mut mapped = NormalMapped::new(material);
+    if let Some(normal_map) = &maps.normal_map {
+        mapped.normal_map = Some(resolve_texture(&normal_map.texture, textures));
+        mapped.normal_strength = normal_map.strength;
+    }
+    if let Some(bump_map) = &maps.bump_map {
+        mapped.bump_map = Some(resolve_texture(&bump_map.texture, textures));
+        mapped.bump_height = bump_map.height;
+    }
+    Arc::new(mapped)
+}
+
+/// A texture whose brightness is the factor
+fn resolve_factor(
+    source: &FactorSource,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+) -> Arc<dyn Texture> {
+    match source {
+        FactorSource::Constant(factor) => Arc::new(SolidColor {
+            color: Color::WHITE * *factor,
+        }),
+        FactorSource::Texture { texture } => resolve_texture(texture, textures),
+    }
+}
+
+fn build_light(config: &LightConfig) -> Box<dyn Light> {
     match config {
-        SkyConfig::LinearGradient { from, to } => Box::new(LinearGradientSkyBox {
+        LightConfig::Point {
+            position,
+            color,
+            intensity,
+        } => Box::new(PointLight {
+            position: *position,
+            intensity: Color::from(*color) * *intensity,
+        }),
+        LightConfig::Spot {
+            position,
+            direction,
+            color,
+            intensity,
+            inner_angle,
+            outer_angle,
+        } => Box::new(SpotLight::new(
+            *position,
+            *direction,
+            Color::from(*color) * *intensity,
+            *inner_angle,
+            *outer_angle,
+        )),
+        LightConfig::Directional {
+            direction,
+            color,
+            intensity,
+        } => Box::new(DirectionalLight {
+            direction: *direction,
+            irradiance: Color::from(*color) * *intensity,
+        }),
+    }
+}
+
+fn build_skybox(config: &SkyConfig, asset_base_path: &Path) -> Arc<dyn SkyBox> {
+    match config {
+        SkyConfig::LinearGradient { from, to } => Arc::new(LinearGradientSkyBox {
             from: Color::from(*from),
             to: Color::from(*to),
         }),
-        SkyConfig::Solid { color } => Box::new(SolidColorSkyBox {
+        SkyConfig::Solid { color } => Arc::new(SolidColorSkyBox {
             color: Color::from(*color),
         }),
+        SkyConfig::EnvironmentMap {
+            path,
+            rotation,
+            intensity,
+        } => {
+            let asset_path = resolve_relative_path(asset_base_path, path);
+            match EnvironmentMapSkyBox::load(&asset_path, *rotation, *intensity) {
+                Ok(skybox) => Arc::new(skybox),
+                Err(e) => {
+                    error!(
+                        "unable to load environment map {}: {e}",
+                        asset_path.display()
+                    );
+                    Arc::new(SolidColorSkyBox {
+                        color: Color::BLACK,
+                    })
+                }
+            }
+        }
+        SkyConfig::Physical {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            ground_albedo,
+            sun_disk,
+        } => Arc::new(PhysicalSkyBox::new(
+            *sun_elevation,
+            *sun_azimuth,
+            *turbidity,
+            Color::from(*ground_albedo),
+            *sun_disk,
+        )),
     }
 }
 
 impl Intersect for World {
     fn intersect(&self, ray: &Ray, interval: Interval) -> Option<crate::math::Hit> {
-        self.bvh.intersect(ray, interval)
+        let mut closest = self.bvh.intersect(ray, interval);
+        for (index, plane) in self.planes.iter().enumerate() {
+            let max = closest.as_ref().map_or(interval.max, |hit| hit.t);
+            if let Some(mut hit) = plane.intersect(ray, Interval::new(interval.min, max)) {
+                hit.primitive_id = self.bvh.primitives.len() + index;
+                closest = Some(hit);
+            }
+        }
+        closest
     }
 
     fn bounding_box(&self) -> crate::math::AABB {
         self.bvh.bounding_box()
     }
+
+    fn intersects(&self, ray: &Ray, interval: Interval) -> bool {
+        self.bvh.intersects(ray, interval)
+            || self
+                .planes
+                .iter()
+                .any(|plane| plane.intersects(ray, interval))
+    }
 }
 
 impl Default for World {
     fn default() -> Self {
         Self {
             bvh: BVH::build(Vec::new()),
-            skybox: Box::new(SolidColorSkyBox {
+            planes: Vec::new(),
+            skybox: Arc::new(SolidColorSkyBox {
                 color: Color::WHITE,
             }),
             material_library: MaterialLibrary::new(),
+            lights: Vec::new(),
+            primitive_lights: HashMap::new(),
+            camera: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn octahedron_scene(material: &str, translate: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [image]
+            width = 8
+            output = "ignore"
+
+            [renderer]
+            max_bounces = 2
+            samples_per_pixel = 1
+
+            [camera]
+            position = "0, 0, 0"
+            look_at = "0, 0, -1"
+            field_of_view = 60
+            aspect_ratio = "1:1"
+
+            [sky]
+            type = "solid"
+            color = "0, 0, 0"
+
+            [[materials]]
+            type = "lambertian"
+            name = "matte"
+            albedo = "0.5, 0.5, 0.5"
+
+            [[materials]]
+            type = "diffuse_light"
+            name = "lamp"
+            albedo = "1, 1, 1"
+            strength = 2
+
+            [[objects]]
+            type = "mesh"
+            path = "assets/octahedron.obj"
+            material = "{material}"
+            translate = "{translate}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn hit_towards(world: &World, x: f64) -> Option<Hit> {
+        let ray = Ray::new(Point3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        world.intersect(&ray, Interval::new(0.001, f64::INFINITY))
+    }
+
+    #[test]
+    fn moving_objects_reuses_meshes() {
+        let base_path = Path::new("golden_images/scenes");
+        let mut cache = GeometryCache::new();
+
+        let world = World::from_config_with_cache(
+            &octahedron_scene("matte", "0, 0, -5"),
+            base_path,
+            &mut cache,
+        );
+        assert!(hit_towards(&world, 0.0).is_some());
+        let mesh = cache.models.values().next().unwrap().bvh.clone();
+
+        let world = World::from_config_with_cache(
+            &octahedron_scene("matte", "3, 0, -5"),
+            base_path,
+            &mut cache,
+        );
+        assert!(hit_towards(&world, 0.0).is_none());
+        assert!(hit_towards(&world, 3.0).is_some());
+        assert_eq!(cache.models.len(), 1);
+        assert!(Arc::ptr_eq(
+            &cache.models.values().next().unwrap().bvh,
+            &mesh
+        ));
+    }
+
+    #[test]
+    fn csg_operands_must_be_closed() {
+        #[derive(serde::Deserialize)]
+        struct Objects {
+            objects: Vec<ObjectConfig>,
         }
+        let mut config = octahedron_scene("matte", "0, 0, -5");
+        config.objects = toml::from_str::<Objects>(
+            r#"
+            [[objects]]
+            type = "csg"
+            operation = "difference"
+            left = { type = "box", min = "-1, -1, -5.5", max = "1, 1, -4.5", material = "matte" }
+            right = { type = "mesh", path = "assets/octahedron.obj", material = "matte", translate = "0, 0, -5" }
+
+            [[objects]]
+            type = "csg"
+            operation = "union"
+            left = { type = "sphere", position = "3, 0, -5", radius = 1, material = "matte" }
+            right = { type = "quad", corner = "3, 0, -5", edge1 = "1, 0, 0", edge2 = "0, 1, 0", material = "matte" }
+            "#,
+        )
+        .unwrap()
+        .objects;
+        let world = World::from_config(&config, Path::new("golden_images/scenes"));
+
+        // The octahedron punches a hole through the middle of the box
+        assert!(hit_towards(&world, 0.0).is_none());
+        let hit = hit_towards(&world, 0.7).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-6);
+        assert!(hit.front_face);
+        // The union with an open quad is skipped along with its sphere
+        assert!(hit_towards(&world, 3.0).is_none());
+    }
+
+    #[test]
+    fn instanced_emissive_triangles_are_lights() {
+        let world = World::from_config(
+            &octahedron_scene("lamp", "0, 0, -5"),
+            Path::new("golden_images/scenes"),
+        );
+        assert_eq!(world.lights.len(), 8);
+
+        let hit = hit_towards(&world, 0.2).unwrap();
+        assert_eq!(hit.instance_id, Some(0));
+        assert!(world.light_pdf(Point3::new(0.2, 0.0, 0.0), &hit) > 0.0);
+    }
+
+    #[test]
+    fn layered_materials_refer_to_others_by_name() {
+        #[derive(serde::Deserialize)]
+        struct Materials {
+            materials: Vec<MaterialConfig>,
+        }
+        let mut config = octahedron_scene("matte", "0, 0, -5");
+        config.materials.extend(
+            toml::from_str::<Materials>(
+                r#"
+                [[materials]]
+                type = "coated"
+                name = "varnish"
+                base = "matte"
+
+                [[materials]]
+                type = "mix"
+                name = "patchy"
+                first = "varnish"
+                second = "matte"
+                factor = 0.5
+
+                [[materials]]
+                type = "mix"
+                name = "loop"
+                first = "matte"
+                second = "knot"
+                factor = 0.5
+
+                [[materials]]
+                type = "coated"
+                name = "knot"
+                base = "loop"
+                "#,
+            )
+            .unwrap()
+            .materials,
+        );
+        let textures = HashMap::new();
+        let mut builder = MaterialBuilder::new(&config.materials, &textures);
+
+        let varnish = builder.build("varnish");
+        builder.build("patchy");
+        assert!(Arc::ptr_eq(&varnish, &builder.build("varnish")));
+
+        // The cycle is cut where it closes, by the fallback material which can't be evaluated
+        let hit = Hit {
+            point: Point3::ORIGIN,
+            normal: Normal3::new(0.0, 0.0, 1.0),
+            shading_normal: Normal3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            front_face: true,
+            material_id: 0,
+            primitive_id: 0,
+            instance_id: None,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+            color: Color::WHITE,
+        };
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        assert!(varnish.evaluate(ray, &hit, up).is_some());
+        assert!(builder.build("loop").evaluate(ray, &hit, up).is_none());
+        assert!(builder.build("knot").evaluate(ray, &hit, up).is_none());
+
+        let library = World::build_material_library(&config, &textures);
+        assert_eq!(library.size(), 6);
     }
 }
@@ -25,7 +25,7 @@ fn load_golden_scene(name: &str) -> (Camera, World, Renderer) {
         panic!();
     });
 
-    load_scene_from_config(&config, &base_path.as_path())
+    load_scene_from_config(&config, base_path.as_path())
 }
 
 fn assert_images_are_close(expected: &Image, actual: &Image) {
@@ -44,3 +44,147 @@ fn bunny() {
 
     golden_test("bunny", &mut sampler)
 }
+
+#[test]
+fn small_lights() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("small_lights", &mut sampler)
+}
+
+#[test]
+fn analytic_lights() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("analytic_lights", &mut sampler)
+}
+
+#[test]
+fn environment_map() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("environment_map", &mut sampler)
+}
+
+#[test]
+fn physical_sky() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("physical_sky", &mut sampler)
+}
+
+#[test]
+fn textures() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("textures", &mut sampler)
+}
+
+#[test]
+fn smooth_mesh() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("smooth_mesh", &mut sampler)
+}
+
+#[test]
+fn mtl_materials() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("mtl_materials", &mut sampler)
+}
+
+#[test]
+fn gltf_scene() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("gltf_scene", &mut sampler)
+}
+
+#[test]
+fn ply_stl_meshes() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("ply_stl_meshes", &mut sampler)
+}
+
+#[test]
+fn instancing() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("instancing", &mut sampler)
+}
+
+#[test]
+fn analytic_shapes() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("analytic_shapes", &mut sampler)
+}
+
+#[test]
+fn csg() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("csg", &mut sampler)
+}
+
+#[test]
+fn sdf() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("sdf", &mut sampler)
+}
+
+#[test]
+fn principled() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("principled", &mut sampler)
+}
+
+#[test]
+fn glass() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("glass", &mut sampler)
+}
+
+#[test]
+fn conductors() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("conductors", &mut sampler)
+}
+
+#[test]
+fn layered() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("layered", &mut sampler)
+}
+
+#[test]
+fn normal_maps() {
+    let rng = SmallRng::seed_from_u64(1337);
+    let mut sampler = RandomSampler::new(rng);
+
+    golden_test("normal_maps", &mut sampler)
+}